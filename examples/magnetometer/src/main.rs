@@ -17,7 +17,13 @@ use cortex_m_rt::entry;
 fn main() -> ! {
     let board = calliope_mini::Board::take().unwrap();
 
-    let i2c = { twi::Twi::new(board.TWI0, board.i2c.into(), FREQUENCY_A::K100) };
+    let i2c = {
+        twi::Twi::new(
+            board.TWI0,
+            board.grove_a0.into_i2c().into(),
+            FREQUENCY_A::K100,
+        )
+    };
 
     let mut timer = Timer::new(board.TIMER0);
 
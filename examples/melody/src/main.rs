@@ -0,0 +1,88 @@
+#![no_std]
+#![no_main]
+
+use defmt_rtt as _;
+use panic_halt as _;
+
+use core::cell::RefCell;
+use cortex_m::interrupt::Mutex;
+use cortex_m_rt::entry;
+
+use calliope_mini::{
+    board::Board,
+    channels::{GpioteChannels, PpiChannels},
+    hal::{
+        pac::{self, interrupt, TIMER0, TIMER1},
+        prelude::*,
+    },
+    sound::{
+        expressions::Expression,
+        melody::{Melody, MelodyPlayer},
+        Beeper,
+    },
+};
+
+static PLAYER: Mutex<RefCell<Option<MelodyPlayer<TIMER0, TIMER1>>>> =
+    Mutex::new(RefCell::new(None));
+
+const RINGTONE: &str = "Tetris:d=4,o=5,b=160:e6,8b,8c6,8d6,16e6,16d6,8c6,8b,a,8a,8c6,e6,8d6,8c6,b,8b,8c6,d6,e6,c6,a,2a";
+
+const MAKECODE: &str = "C5:2 D E F G:4 G A:2 A A A G:8";
+
+#[entry]
+fn main() -> ! {
+    if let Some(board) = Board::take() {
+        let gpiote = GpioteChannels::new(board.GPIOTE);
+        let ppi = PpiChannels::new(board.PPI);
+        let beeper = Beeper::new(
+            board.TIMER0,
+            board.beeper_motor_pins,
+            [gpiote.channel0, gpiote.channel1],
+            [ppi.ppi0, ppi.ppi1, ppi.ppi2, ppi.ppi3],
+        );
+        let mut player = MelodyPlayer::new(beeper, board.TIMER1);
+        let button_a = board.buttons.button_a;
+        let button_b = board.buttons.button_b;
+
+        player.play_expression(Expression::Happy);
+        cortex_m::interrupt::free(move |cs| {
+            *PLAYER.borrow(cs).borrow_mut() = Some(player);
+        });
+        unsafe {
+            pac::NVIC::unmask(pac::Interrupt::TIMER1);
+        }
+
+        defmt::info!("A plays the ringtone, B loops the MakeCode melody.");
+        loop {
+            let melody = if button_a.is_low().unwrap() {
+                Some((Melody::rtttl(RINGTONE).unwrap(), false))
+            } else if button_b.is_low().unwrap() {
+                Some((Melody::makecode(MAKECODE).unwrap(), true))
+            } else {
+                None
+            };
+            if let Some((melody, looping)) = melody {
+                cortex_m::interrupt::free(|cs| {
+                    if let Some(player) = PLAYER.borrow(cs).borrow_mut().as_mut() {
+                        if looping {
+                            player.play_looping(melody);
+                        } else {
+                            player.play(melody);
+                        }
+                    }
+                });
+                while button_a.is_low().unwrap() || button_b.is_low().unwrap() {}
+            }
+        }
+    }
+    panic!("End");
+}
+
+#[interrupt]
+fn TIMER1() {
+    cortex_m::interrupt::free(|cs| {
+        if let Some(player) = PLAYER.borrow(cs).borrow_mut().as_mut() {
+            player.handle_timer_event();
+        }
+    });
+}
@@ -7,9 +7,11 @@ use panic_halt as _;
 use cortex_m_rt::entry;
 
 use calliope_mini::{
+    adc::{Adc, AdcConfig, Default},
     board::Board,
     display::blocking::Display,
-    hal::{adc::AdcConfig, prelude::*, Adc, Timer},
+    hal::Timer,
+    microphone::{Microphone, SoundEvent},
 };
 
 #[entry]
@@ -18,35 +20,29 @@ fn main() -> ! {
         let mut timer = Timer::new(board.TIMER0);
         let mut display = Display::new(board.display_pins);
 
-        // initialize adc
-        let adc_config = AdcConfig::default();
-        let mut adc = Adc::new(board.ADC, adc_config);
-        let mut mic_in = board.microphone_pins.mic_in.into_floating_input();
+        let adc = Adc::new(board.ADC, AdcConfig::default_10bit());
+        let mut microphone = Microphone::new(adc, board.microphone_pins);
 
-        let mut count: u64 = 0;
-        let mut sum: u64 = 0;
-        let mut max_value: u16 = 0;
+        let mut countdown: u32 = 100;
         loop {
-            let mic_value = adc
-                .read(&mut mic_in)
-                .expect("could not read value of microphone") as u16;
-
-            // Smoothen the signal as audio comes in waves
-            max_value = max_value.max(mic_value);
-            sum += mic_value as u64;
-            count += 1;
-
-            if count % 100 == 0 {
-                let avg = (sum / count) as u16;
-                let image = [
-                    [if max_value > avg + 100 { 1 } else { 0 }; 5],
-                    [if max_value > avg + 80 { 1 } else { 0 }; 5],
-                    [if max_value > avg + 60 { 1 } else { 0 }; 5],
-                    [if max_value > avg + 40 { 1 } else { 0 }; 5],
-                    [if max_value > avg + 20 { 1 } else { 0 }; 5],
-                ];
+            match microphone.sample() {
+                Some(SoundEvent::Loud) => defmt::info!("loud: {} dB", microphone.level_db()),
+                Some(SoundEvent::Quiet) => defmt::info!("quiet"),
+                None => {}
+            }
+
+            countdown -= 1;
+            if countdown == 0 {
+                countdown = 100;
+                // Light one row for every 51 steps of the level, from the bottom
+                let level = microphone.level();
+                let mut image = [[0; 5]; 5];
+                for (row, leds) in image.iter_mut().enumerate() {
+                    if level > 51 * (4 - row as u8) {
+                        *leds = [1; 5];
+                    }
+                }
                 display.show(&mut timer, image, 10);
-                max_value = 0;
             }
         }
     }
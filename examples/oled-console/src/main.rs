@@ -0,0 +1,53 @@
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+
+use bmx055::Bmx055;
+use calliope_mini::{
+    hal::{pac::twi0::frequency::FREQUENCY_A, prelude::*, twi, Timer},
+    i2c::SharedBus,
+    ssd1306::{Console, DEFAULT_ADDRESS},
+};
+use defmt_rtt as _;
+use panic_halt as _;
+
+use cortex_m_rt::entry;
+
+#[entry]
+fn main() -> ! {
+    let board = calliope_mini::Board::take().unwrap();
+
+    // The motion sensor and the OLED on the Grove I2C port share the bus
+    let bus = SharedBus::new(twi::Twi::new(
+        board.TWI0,
+        board.grove_a0.into_i2c().into(),
+        FREQUENCY_A::K400,
+    ));
+    let mut i2c = bus.acquire();
+
+    let mut timer = Timer::new(board.TIMER0);
+
+    let mut sensor = Bmx055::new_with_i2c(bus.acquire());
+    sensor.init(&mut timer).unwrap();
+
+    let mut console = Console::new(DEFAULT_ADDRESS);
+    console.init(&mut i2c).unwrap();
+    writeln!(console, "Magnetic field in uT").ok();
+
+    loop {
+        let data = sensor.magnetic_field_data().unwrap();
+        if data.data_ready() {
+            writeln!(
+                console,
+                "{} {} {}",
+                data.x_compensated_ut(),
+                data.y_compensated_ut(),
+                data.z_compensated_ut(),
+            )
+            .ok();
+            console.flush(&mut i2c).unwrap();
+        }
+        timer.delay_ms(500_u32);
+    }
+}
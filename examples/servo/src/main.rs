@@ -10,114 +10,88 @@ use cortex_m_rt::entry;
 
 use calliope_mini::{
     board::Board,
+    channels::{GpioteChannels, PpiChannels},
     hal::{
         gpio::Level,
-        gpiote::*,
         pac::{self, interrupt, TIMER0},
-        ppi::{self, ConfigurablePpi, Ppi},
     },
+    pwm::{Channel, PwmPin},
+    servo::{Calibration, Servos},
 };
 
-static SERVO_TIMER: Mutex<RefCell<Option<TIMER0>>> = Mutex::new(RefCell::new(None));
+static SERVOS: Mutex<RefCell<Option<Servos<TIMER0>>>> = Mutex::new(RefCell::new(None));
 
 #[entry]
 fn main() -> ! {
     if let Some(board) = Board::take() {
-        let gpiote = Gpiote::new(board.GPIOTE);
-        // Servo output pins
-        let servopin1 = board
-            .beeper_motor_pins
-            .motor_in1
-            .into_push_pull_output(Level::Low)
-            .degrade();
-        let servopin2 = board
-            .beeper_motor_pins
-            .motor_in2
-            .into_push_pull_output(Level::Low)
-            .degrade();
+        let gpiote = GpioteChannels::new(board.GPIOTE);
+        let ppi = PpiChannels::new(board.PPI);
 
-        // Output channel for Servo 1
-        gpiote
-            .channel0()
-            .output_pin(servopin1)
-            .task_out_polarity(TaskOutPolarity::Toggle)
-            .init_low();
-        gpiote.channel0().task_out().write(|w| unsafe { w.bits(1) });
-        // Output channel for Servo 2
-        gpiote
-            .channel1()
-            .output_pin(servopin2)
-            .task_out_polarity(TaskOutPolarity::Toggle)
-            .init_low();
-        gpiote.channel1().task_out().write(|w| unsafe { w.bits(1) });
-
-        let ppi_channels = ppi::Parts::new(board.PPI);
-        // Set both servo outputs high form Timer0 CC[0]
-        // Set each servo output low from the respective Timer0 CC[1] and CC[2]
         // Each timer can run 3 Servos
-        let mut ppi0 = ppi_channels.ppi0;
-        ppi0.set_task_endpoint(gpiote.channel0().task_out());
-        ppi0.set_event_endpoint(&board.TIMER0.events_compare[0]);
-        ppi0.enable();
-        let mut ppi1 = ppi_channels.ppi1;
-        ppi1.set_task_endpoint(gpiote.channel0().task_out());
-        ppi1.set_event_endpoint(&board.TIMER0.events_compare[1]);
-        ppi1.enable();
-        let mut ppi2 = ppi_channels.ppi2;
-        ppi2.set_task_endpoint(gpiote.channel1().task_out());
-        ppi2.set_event_endpoint(&board.TIMER0.events_compare[0]);
-        ppi2.enable();
-        let mut ppi3 = ppi_channels.ppi3;
-        ppi3.set_task_endpoint(gpiote.channel1().task_out());
-        ppi3.set_event_endpoint(&board.TIMER0.events_compare[2]);
-        ppi3.enable();
-
-        // The Timer PAC is used directly as the HAL does not give full access to all registers
-        board.TIMER0.mode.write(|w| unsafe { w.bits(0) });
-        board.TIMER0.bitmode.write(|w| unsafe { w.bits(0) });
-        // CC[0] every 20 ms (50 Hz)
-        board.TIMER0.cc[0].write(|w| unsafe { w.bits(20000) });
-        board.TIMER0.shorts.write(|w| unsafe { w.bits(1) });
+        let mut servos = Servos::new(board.TIMER0);
         // Servo duty cycle is from 0.5 ms to 2.5 ms with 1.5 ms for center position
-        board.TIMER0.cc[1].write(|w| unsafe { w.bits(1500) });
-        board.TIMER0.cc[2].write(|w| unsafe { w.bits(1500) });
-        board.TIMER0.tasks_start.write(|w| unsafe { w.bits(1) });
-        // Timer0 interrupt on CC[0]
-        board.TIMER0.intenset.write(|w| unsafe { w.bits(1 << 16) });
+        let calibration = Calibration::default();
+        servos
+            .attach(
+                Channel::C0,
+                PwmPin {
+                    pin: board
+                        .beeper_motor_pins
+                        .motor_in1
+                        .into_push_pull_output(Level::Low)
+                        .degrade(),
+                    gpiote: gpiote.channel0,
+                    ppi: [ppi.ppi0, ppi.ppi1],
+                },
+                calibration,
+            )
+            .ok();
+        servos
+            .attach(
+                Channel::C1,
+                PwmPin {
+                    pin: board
+                        .beeper_motor_pins
+                        .motor_in2
+                        .into_push_pull_output(Level::Low)
+                        .degrade(),
+                    gpiote: gpiote.channel1,
+                    ppi: [ppi.ppi2, ppi.ppi3],
+                },
+                calibration,
+            )
+            .ok();
+        servos.set_angle(Channel::C0, 90);
+        servos.set_angle(Channel::C1, 90);
 
         cortex_m::interrupt::free(move |cs| {
-            *SERVO_TIMER.borrow(cs).borrow_mut() = Some(board.TIMER0);
+            *SERVOS.borrow(cs).borrow_mut() = Some(servos);
         });
         unsafe {
             pac::NVIC::unmask(pac::Interrupt::TIMER0);
         }
 
-        loop {}
+        loop {
+            cortex_m::asm::wfi();
+        }
     }
     panic!("End");
 }
 
 #[interrupt]
 fn TIMER0() {
-    // Change Servo position at the start of the duty cycle. Then there is no race condition
-    // between changing the duty cycle and a CC event.
-    static mut SPEED: i32 = 1500;
-    static mut DIRECTION: i32 = 1;
-    match SPEED {
-        i32::MIN..=500 => *DIRECTION = 1,
-        2500..=i32::MAX => *DIRECTION = -1,
-        _ => {}
-    }
-    *SPEED += *DIRECTION;
     cortex_m::interrupt::free(|cs| {
-        //    if let Some(cc_value) = CC_VALUE.borrow(cs).borrow().as_ref() {
-        if let Some(timer) = SERVO_TIMER.borrow(cs).borrow_mut().as_mut() {
-            //timer.cc[1].write(|w|unsafe { w.bits(u32::try_from(*cc_value).unwrap_or(1500)) });
-            let set_speed = u32::try_from(*SPEED).unwrap_or(1500);
-            timer.cc[1].write(|w| unsafe { w.bits(set_speed) });
-            timer.cc[2].write(|w| unsafe { w.bits(set_speed) });
-            timer.events_compare[0].write(|w| unsafe { w.bits(0) });
+        if let Some(servos) = SERVOS.borrow(cs).borrow_mut().as_mut() {
+            servos.handle_servo_event();
+            // Sweep both servos back and forth at 45°/s
+            if !servos.is_moving(Channel::C0) {
+                let target = match servos.angle(Channel::C0) {
+                    Some(0) => 180,
+                    _ => 0,
+                };
+                servos.move_to(Channel::C0, target, 45);
+                servos.move_to(Channel::C1, target, 45);
+            }
         }
-        //}
     });
 }
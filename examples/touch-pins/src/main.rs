@@ -0,0 +1,94 @@
+#![no_std]
+#![no_main]
+
+use defmt_rtt as _;
+use panic_halt as _;
+
+use core::cell::RefCell;
+use cortex_m::interrupt::Mutex;
+use cortex_m_rt::entry;
+
+use calliope_mini::{
+    board::Board,
+    channels::GpioteChannels,
+    hal::pac::{self, interrupt, TIMER1},
+    input::watch::{PinEvent, PinWatcher, Pull, Trigger},
+};
+
+static WATCHER: Mutex<RefCell<Option<PinWatcher<TIMER1>>>> = Mutex::new(RefCell::new(None));
+
+#[entry]
+fn main() -> ! {
+    if let Some(board) = Board::take() {
+        let gpiote = GpioteChannels::new(board.GPIOTE);
+        let mut watcher = PinWatcher::new(board.TIMER1);
+
+        // The touch pads P0 to P3, in the order of their watch ids
+        let pads = [
+            (board.edge.e00.degrade(), gpiote.channel0),
+            (board.edge.e01.degrade(), gpiote.channel1),
+            (board.edge.e02.degrade(), gpiote.channel2),
+            (board.edge.e03.degrade(), gpiote.channel3),
+        ];
+        for (pin, channel) in pads {
+            watcher
+                .watch(
+                    pin.into_disconnected(),
+                    Pull::None,
+                    Trigger::Pressed,
+                    channel,
+                )
+                .ok();
+        }
+
+        cortex_m::interrupt::free(move |cs| {
+            *WATCHER.borrow(cs).borrow_mut() = Some(watcher);
+        });
+        unsafe {
+            pac::NVIC::unmask(pac::Interrupt::GPIOTE);
+            pac::NVIC::unmask(pac::Interrupt::TIMER1);
+        }
+
+        defmt::info!("Touch the pads P0 to P3.");
+        loop {
+            let event = cortex_m::interrupt::free(|cs| {
+                let event = WATCHER
+                    .borrow(cs)
+                    .borrow_mut()
+                    .as_mut()
+                    .and_then(|watcher| watcher.next_event());
+                if event.is_none() {
+                    // With interrupts masked an edge after the check still
+                    // wakes the core, its handler runs after the critical
+                    // section.
+                    cortex_m::asm::wfi();
+                }
+                event
+            });
+            match event {
+                Some(PinEvent::Pressed(pad)) => defmt::info!("P{} pressed", pad),
+                Some(PinEvent::Released(pad)) => defmt::info!("P{} released", pad),
+                _ => {}
+            }
+        }
+    }
+    panic!("End");
+}
+
+#[interrupt]
+fn GPIOTE() {
+    handle_pin_event();
+}
+
+#[interrupt]
+fn TIMER1() {
+    handle_pin_event();
+}
+
+fn handle_pin_event() {
+    cortex_m::interrupt::free(|cs| {
+        if let Some(watcher) = WATCHER.borrow(cs).borrow_mut().as_mut() {
+            watcher.handle_pin_event();
+        }
+    });
+}
@@ -0,0 +1,320 @@
+//! GPIOTE and PPI channels that can be owned by drivers
+//!
+//! Drivers that connect a TIMER to edge pins through PPI, like the ones in
+//! the [`pulse`](crate::pulse) module, each need a few GPIOTE and PPI
+//! channels. The hal hands GPIOTE channels out as borrows of
+//! [`Gpiote`](crate::hal::gpiote::Gpiote) and PPI channels as distinct types,
+//! which makes it hard to store a varying number of them in a driver.
+//!
+//! [`GpioteChannels`] and [`PpiChannels`] split the peripherals into
+//! channels which are moved into a driver and handed back by its `free`
+//! method.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use microbit_common as microbit;
+//! use microbit::{
+//!     channels::{GpioteChannels, PpiChannels},
+//!     hal::gpiote::EventPolarity,
+//!     Board,
+//! };
+//!
+//! let board = Board::take().unwrap();
+//! let mut gpiote = GpioteChannels::new(board.GPIOTE);
+//! let mut ppi = PpiChannels::new(board.PPI);
+//!
+//! let button_a = board.buttons.button_a.degrade();
+//! gpiote.channel0.input_pin(&button_a, EventPolarity::HiToLo);
+//!
+//! // capture the TIMER0 counter whenever button A is pressed
+//! ppi.ppi0.set_event_endpoint(gpiote.channel0.event());
+//! ppi.ppi0.set_task_endpoint(&board.TIMER0.tasks_capture[0]);
+//! ppi.ppi0.enable();
+//! ```
+use crate::{
+    hal::{
+        gpio::Level,
+        gpiote::{EventPolarity, GpioteInputPin, GpioteOutputPin, TaskOutPolarity},
+    },
+    pac::{
+        self,
+        gpiote::{EVENTS_IN, TASKS_OUT},
+    },
+};
+
+#[cfg(feature = "v2")]
+use crate::hal::gpio::Port;
+
+/// A single GPIOTE channel
+pub struct GpioteChannel {
+    index: usize,
+}
+
+impl GpioteChannel {
+    fn gpiote(&self) -> &pac::gpiote::RegisterBlock {
+        // The channel owns its CONFIG, EVENTS_IN and TASKS_* registers and
+        // accesses the shared INTEN registers through their SET/CLR aliases.
+        unsafe { &*pac::GPIOTE::ptr() }
+    }
+
+    /// Index of the channel in the GPIOTE peripheral
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Configure the channel to generate events on transitions of `pin`
+    pub fn input_pin<P: GpioteInputPin>(&mut self, pin: &P, polarity: EventPolarity) {
+        self.gpiote().config[self.index].write(|w| {
+            match polarity {
+                EventPolarity::None => w.mode().event().polarity().none(),
+                EventPolarity::HiToLo => w.mode().event().polarity().hi_to_lo(),
+                EventPolarity::LoToHi => w.mode().event().polarity().lo_to_hi(),
+                EventPolarity::Toggle => w.mode().event().polarity().toggle(),
+            };
+            #[cfg(feature = "v2")]
+            w.port().bit(pin.port() == Port::Port1);
+            unsafe { w.psel().bits(pin.pin()) }
+        });
+    }
+
+    /// Configure the channel to drive `pin` from its OUT task
+    pub fn output_pin<P: GpioteOutputPin>(
+        &mut self,
+        pin: &P,
+        polarity: TaskOutPolarity,
+        initial: Level,
+    ) {
+        self.gpiote().config[self.index].write(|w| {
+            match initial {
+                Level::Low => w.mode().task().outinit().low(),
+                Level::High => w.mode().task().outinit().high(),
+            };
+            match polarity {
+                TaskOutPolarity::Set => w.polarity().lo_to_hi(),
+                TaskOutPolarity::Clear => w.polarity().hi_to_lo(),
+                TaskOutPolarity::Toggle => w.polarity().toggle(),
+            };
+            #[cfg(feature = "v2")]
+            w.port().bit(pin.port() == Port::Port1);
+            unsafe { w.psel().bits(pin.pin()) }
+        });
+    }
+
+    /// Release the pin, it returns to the control of the GPIO registers
+    pub fn disable(&mut self) {
+        self.gpiote().config[self.index].write(|w| w.mode().disabled());
+    }
+
+    /// Checks if the channel event has been triggered
+    pub fn is_event_triggered(&self) -> bool {
+        self.gpiote().events_in[self.index].read().bits() != 0
+    }
+
+    /// Resets the channel event
+    pub fn reset_events(&self) {
+        self.gpiote().events_in[self.index].write(|w| unsafe { w.bits(0) });
+    }
+
+    /// Triggers the OUT task
+    pub fn out(&self) {
+        self.gpiote().tasks_out[self.index].write(|w| unsafe { w.bits(1) });
+    }
+
+    /// Enable the GPIOTE interrupt for the channel event
+    pub fn enable_interrupt(&mut self) {
+        self.gpiote()
+            .intenset
+            .write(|w| unsafe { w.bits(1 << self.index) });
+    }
+
+    /// Disable the GPIOTE interrupt for the channel event
+    pub fn disable_interrupt(&mut self) {
+        self.gpiote()
+            .intenclr
+            .write(|w| unsafe { w.bits(1 << self.index) });
+    }
+
+    /// Channel event endpoint for PPI
+    pub fn event(&self) -> &EVENTS_IN {
+        &self.gpiote().events_in[self.index]
+    }
+
+    /// OUT task endpoint for PPI
+    pub fn task_out(&self) -> &TASKS_OUT {
+        &self.gpiote().tasks_out[self.index]
+    }
+}
+
+/// A single configurable PPI channel
+pub struct PpiChannel {
+    index: usize,
+}
+
+impl PpiChannel {
+    fn ppi(&self) -> &pac::ppi::RegisterBlock {
+        // The channel owns its CH registers and accesses the shared CHEN
+        // register through its SET/CLR aliases.
+        unsafe { &*pac::PPI::ptr() }
+    }
+
+    /// Index of the channel in the PPI peripheral
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Set the event which triggers the channel
+    pub fn set_event_endpoint<E: Event>(&mut self, event: &E) {
+        self.ppi().ch[self.index]
+            .eep
+            .write(|w| unsafe { w.bits(event as *const E as u32) });
+    }
+
+    /// Set the task which is triggered by the channel
+    pub fn set_task_endpoint<T: Task>(&mut self, task: &T) {
+        self.ppi().ch[self.index]
+            .tep
+            .write(|w| unsafe { w.bits(task as *const T as u32) });
+    }
+
+    /// Set a second task which is triggered by the channel
+    #[cfg(feature = "v2")]
+    pub fn set_fork_task_endpoint<T: Task>(&mut self, task: &T) {
+        self.ppi().fork[self.index]
+            .tep
+            .write(|w| unsafe { w.bits(task as *const T as u32) });
+    }
+
+    /// Remove the second task
+    #[cfg(feature = "v2")]
+    pub fn clear_fork_task_endpoint(&mut self) {
+        self.ppi().fork[self.index]
+            .tep
+            .write(|w| unsafe { w.bits(0) });
+    }
+
+    /// Enable the channel
+    pub fn enable(&mut self) {
+        self.ppi()
+            .chenset
+            .write(|w| unsafe { w.bits(1 << self.index) });
+    }
+
+    /// Disable the channel
+    pub fn disable(&mut self) {
+        self.ppi()
+            .chenclr
+            .write(|w| unsafe { w.bits(1 << self.index) });
+    }
+}
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// A task register which can be triggered through PPI
+pub trait Task: sealed::Sealed {}
+
+/// An event register which can trigger a PPI channel
+pub trait Event: sealed::Sealed {}
+
+macro_rules! endpoints {
+    ($trait:ident: $($register:ty),+ $(,)?) => {
+        $(
+            impl sealed::Sealed for $register {}
+            impl $trait for $register {}
+        )+
+    };
+}
+
+endpoints!(Task:
+    pac::gpiote::TASKS_OUT,
+    pac::timer0::TASKS_START,
+    pac::timer0::TASKS_STOP,
+    pac::timer0::TASKS_COUNT,
+    pac::timer0::TASKS_CLEAR,
+    pac::timer0::TASKS_CAPTURE,
+);
+
+#[cfg(feature = "v1")]
+endpoints!(Task: pac::adc::TASKS_START);
+
+#[cfg(feature = "v2")]
+endpoints!(Task: pac::gpiote::TASKS_SET, pac::gpiote::TASKS_CLR, pac::saadc::TASKS_SAMPLE);
+
+endpoints!(Event: pac::gpiote::EVENTS_IN, pac::timer0::EVENTS_COMPARE);
+
+macro_rules! channels {
+    ($(#[$doc:meta])* $name:ident($peripheral:ty) -> $channel:ident {
+        $($(#[$attr:meta])* $field:ident: $index:expr,)+
+    }) => {
+        $(#[$doc])*
+        #[allow(missing_docs)]
+        pub struct $name {
+            $(
+                $(#[$attr])*
+                pub $field: $channel,
+            )+
+        }
+
+        impl $name {
+            /// Split the peripheral into its channels
+            pub fn new(_peripheral: $peripheral) -> Self {
+                Self {
+                    $(
+                        $(#[$attr])*
+                        $field: $channel { index: $index },
+                    )+
+                }
+            }
+        }
+    };
+}
+
+channels!(
+    /// The GPIOTE peripheral split into its channels
+    GpioteChannels(pac::GPIOTE) -> GpioteChannel {
+        channel0: 0,
+        channel1: 1,
+        channel2: 2,
+        channel3: 3,
+        #[cfg(feature = "v2")]
+        channel4: 4,
+        #[cfg(feature = "v2")]
+        channel5: 5,
+        #[cfg(feature = "v2")]
+        channel6: 6,
+        #[cfg(feature = "v2")]
+        channel7: 7,
+    }
+);
+
+channels!(
+    /// The configurable channels of the PPI peripheral
+    PpiChannels(pac::PPI) -> PpiChannel {
+        ppi0: 0,
+        ppi1: 1,
+        ppi2: 2,
+        ppi3: 3,
+        ppi4: 4,
+        ppi5: 5,
+        ppi6: 6,
+        ppi7: 7,
+        ppi8: 8,
+        ppi9: 9,
+        ppi10: 10,
+        ppi11: 11,
+        ppi12: 12,
+        ppi13: 13,
+        ppi14: 14,
+        ppi15: 15,
+        #[cfg(feature = "v2")]
+        ppi16: 16,
+        #[cfg(feature = "v2")]
+        ppi17: 17,
+        #[cfg(feature = "v2")]
+        ppi18: 18,
+        #[cfg(feature = "v2")]
+        ppi19: 19,
+    }
+);
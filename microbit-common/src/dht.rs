@@ -0,0 +1,302 @@
+//! DHT11 and DHT22 humidity and temperature sensors
+//!
+//! The sensors answer a start pulse on their single data line with 40 bits.
+//! Each bit is a 50 µs low followed by a high of 26 µs for 0 or 70 µs for 1,
+//! so the time from one falling edge to the next gives its value.
+//!
+//! [`Dht`] timestamps the falling edges in hardware like
+//! [`PulseIn`](crate::pulse::PulseIn): a GPIOTE channel watching the pin
+//! triggers a capture of a TIMER through PPI. Interrupts stay enabled during
+//! a reading, their handlers only have to finish within about 70 µs so that
+//! no edge is missed.
+//!
+//! Leave at least 1 s between readings of a DHT11 and 2 s for a DHT22.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use microbit_common as microbit;
+//! use microbit::{
+//!     channels::{GpioteChannels, PpiChannels},
+//!     dht::{Dht, Model},
+//!     Board,
+//! };
+//!
+//! let board = Board::take().unwrap();
+//! let gpiote = GpioteChannels::new(board.GPIOTE);
+//! let ppi = PpiChannels::new(board.PPI);
+//!
+//! let mut dht = Dht::new(
+//!     Model::Dht22,
+//!     board.TIMER1,
+//!     board.edge.e01.into_pullup_input().degrade(),
+//!     gpiote.channel0,
+//!     ppi.ppi0,
+//! );
+//! if let Ok(reading) = dht.read() {
+//!     let _humidity = reading.humidity_permille;
+//!     let _temperature = reading.millidegrees;
+//! }
+//! ```
+use crate::{
+    channels::{GpioteChannel, PpiChannel},
+    hal::{
+        gpio::{Input, Level, Pin, PullUp},
+        gpiote::EventPolarity,
+        timer::Instance,
+    },
+};
+
+/// Number of data bits sent by the sensor
+pub const NUM_BITS: usize = 40;
+
+/// Longest time from one falling edge to the next of a 0 bit
+const ZERO_MAX_US: u16 = 100;
+
+/// Falling edges: the start of the response, one per bit and the stop bit
+const NUM_EDGES: usize = NUM_BITS + 2;
+
+/// Time without an edge after which the response is complete
+const EDGE_TIMEOUT_US: u16 = 500;
+
+/// Time for the sensor to start its response
+const RESPONSE_TIMEOUT_US: u16 = 1000;
+
+/// DHT errors
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The sensor did not answer or some bits were lost
+    Timeout,
+    /// The checksum does not match the data
+    Checksum,
+}
+
+/// Sensor model
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Model {
+    /// DHT11: 1 %RH and 1 °C resolution
+    Dht11,
+    /// DHT22 or AM2302: 0.1 %RH and 0.1 °C resolution
+    Dht22,
+}
+
+impl Model {
+    /// Length of the start pulse in µs
+    fn start_us(self) -> u16 {
+        match self {
+            Model::Dht11 => 20_000,
+            Model::Dht22 => 1_100,
+        }
+    }
+
+    /// Check the checksum of the 5 received bytes and convert them
+    pub fn decode(self, bytes: [u8; 5]) -> Result<Reading, Error> {
+        let sum = bytes[..4]
+            .iter()
+            .fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+        if sum != bytes[4] {
+            return Err(Error::Checksum);
+        }
+        Ok(match self {
+            Model::Dht11 => decode_dht11(bytes),
+            Model::Dht22 => decode_dht22(bytes),
+        })
+    }
+}
+
+/// Humidity and temperature
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Reading {
+    /// Relative humidity in 0.1 %
+    pub humidity_permille: u16,
+    /// Temperature in m°C
+    pub millidegrees: i32,
+}
+
+/// Assemble the bytes from the times between falling edges in µs
+///
+/// ```
+/// # use microbit_common as microbit;
+/// use microbit::dht::periods_to_bytes;
+///
+/// let mut periods = [78; 40];
+/// // 0b0000_0011 in the first byte
+/// periods[6] = 120;
+/// periods[7] = 121;
+/// assert_eq!(periods_to_bytes(&periods), [0x03, 0, 0, 0, 0]);
+/// ```
+pub fn periods_to_bytes(periods: &[u16; NUM_BITS]) -> [u8; 5] {
+    let mut bytes = [0; 5];
+    for (index, period) in periods.iter().enumerate() {
+        if *period > ZERO_MAX_US {
+            // most significant bit first
+            bytes[index / 8] |= 0x80 >> (index % 8);
+        }
+    }
+    bytes
+}
+
+/// Convert the data of a DHT11: integral and decimal parts of humidity and
+/// temperature
+///
+/// ```
+/// # use microbit_common as microbit;
+/// use microbit::dht::decode_dht11;
+///
+/// let reading = decode_dht11([45, 0, 23, 4, 72]);
+/// assert_eq!(reading.humidity_permille, 450);
+/// assert_eq!(reading.millidegrees, 23_400);
+/// ```
+pub fn decode_dht11(bytes: [u8; 5]) -> Reading {
+    let humidity_permille = u16::from(bytes[0]) * 10 + u16::from(bytes[1] % 10);
+    // newer DHT11 set the top bit of the decimal part below 0 °C
+    let magnitude = i32::from(bytes[2]) * 1000 + i32::from(bytes[3] & 0x7f) * 100;
+    let millidegrees = if bytes[3] & 0x80 != 0 {
+        -magnitude
+    } else {
+        magnitude
+    };
+    Reading {
+        humidity_permille,
+        millidegrees,
+    }
+}
+
+/// Convert the data of a DHT22: humidity and temperature in tenths, the
+/// temperature with a sign bit
+///
+/// ```
+/// # use microbit_common as microbit;
+/// use microbit::dht::decode_dht22;
+///
+/// let reading = decode_dht22([0x02, 0x8c, 0x80, 0x65, 0x73]);
+/// assert_eq!(reading.humidity_permille, 652);
+/// assert_eq!(reading.millidegrees, -10_100);
+/// ```
+pub fn decode_dht22(bytes: [u8; 5]) -> Reading {
+    let humidity_permille = u16::from_be_bytes([bytes[0], bytes[1]]);
+    let magnitude = i32::from(u16::from_be_bytes([bytes[2] & 0x7f, bytes[3]])) * 100;
+    let millidegrees = if bytes[2] & 0x80 != 0 {
+        -magnitude
+    } else {
+        magnitude
+    };
+    Reading {
+        humidity_permille,
+        millidegrees,
+    }
+}
+
+/// DHT sensor using a TIMER, a GPIOTE and a PPI channel
+pub struct Dht<T: Instance> {
+    model: Model,
+    timer: T,
+    // the slot is only empty while a reading is running
+    pin: Option<Pin<Input<PullUp>>>,
+    gpiote: GpioteChannel,
+    ppi: PpiChannel,
+}
+
+impl<T: Instance> Dht<T> {
+    /// Take the resources and start the timer
+    pub fn new(
+        model: Model,
+        timer: T,
+        pin: Pin<Input<PullUp>>,
+        gpiote: GpioteChannel,
+        mut ppi: PpiChannel,
+    ) -> Self {
+        let regs = timer.as_timer0();
+        regs.tasks_stop.write(|w| unsafe { w.bits(1) });
+        regs.tasks_clear.write(|w| unsafe { w.bits(1) });
+        regs.mode.write(|w| w.mode().timer());
+        regs.bitmode.write(|w| w.bitmode()._16bit());
+        // 16 MHz / 2^4 = 1 MHz
+        regs.prescaler.write(|w| unsafe { w.bits(4) });
+        regs.shorts.reset();
+        regs.intenclr.write(|w| unsafe { w.bits(0xffff_ffff) });
+        regs.tasks_start.write(|w| unsafe { w.bits(1) });
+
+        // every falling edge seen by the GPIOTE channel captures the counter
+        // into CC[0]
+        ppi.set_event_endpoint(gpiote.event());
+        ppi.set_task_endpoint(&regs.tasks_capture[0]);
+        ppi.enable();
+
+        Self {
+            model,
+            timer,
+            pin: Some(pin),
+            gpiote,
+            ppi,
+        }
+    }
+
+    /// Read humidity and temperature
+    ///
+    /// Takes about 25 ms for a DHT11 and 6 ms for a DHT22.
+    pub fn read(&mut self) -> Result<Reading, Error> {
+        let pin = self.pin.take().ok_or(Error::Timeout)?;
+        let start = pin.into_push_pull_output(Level::Low);
+        self.wait_us(self.model.start_us());
+        let pin = start.into_pullup_input();
+        self.gpiote.input_pin(&pin, EventPolarity::HiToLo);
+        self.gpiote.reset_events();
+        let edges = self.capture_edges();
+        self.gpiote.disable();
+        self.pin = Some(pin);
+
+        let (edges, count) = edges;
+        // the start of the response is missed if the sensor answers quickly
+        if count < NUM_EDGES - 1 {
+            return Err(Error::Timeout);
+        }
+        let edges = &edges[count - (NUM_EDGES - 1)..count];
+        let mut periods = [0; NUM_BITS];
+        for (period, pair) in periods.iter_mut().zip(edges.windows(2)) {
+            *period = pair[1].wrapping_sub(pair[0]);
+        }
+        self.model.decode(periods_to_bytes(&periods))
+    }
+
+    /// Timestamps of the falling edges until the line stays high
+    fn capture_edges(&self) -> ([u16; NUM_EDGES], usize) {
+        let regs = self.timer.as_timer0();
+        let mut edges = [0; NUM_EDGES];
+        let mut count = 0;
+        let mut last = self.now();
+        let mut timeout = RESPONSE_TIMEOUT_US;
+        while count < NUM_EDGES && self.now().wrapping_sub(last) < timeout {
+            if self.gpiote.is_event_triggered() {
+                self.gpiote.reset_events();
+                last = regs.cc[0].read().bits() as u16;
+                edges[count] = last;
+                count += 1;
+                timeout = EDGE_TIMEOUT_US;
+            }
+        }
+        (edges, count)
+    }
+
+    fn wait_us(&self, us: u16) {
+        let start = self.now();
+        while self.now().wrapping_sub(start) < us {}
+    }
+
+    fn now(&self) -> u16 {
+        let regs = self.timer.as_timer0();
+        regs.tasks_capture[1].write(|w| unsafe { w.bits(1) });
+        regs.cc[1].read().bits() as u16
+    }
+
+    /// Stop the timer and release the resources
+    pub fn free(mut self) -> (T, Pin<Input<PullUp>>, GpioteChannel, PpiChannel) {
+        self.ppi.disable();
+        self.timer
+            .as_timer0()
+            .tasks_stop
+            .write(|w| unsafe { w.bits(1) });
+        // `read` always puts the pin back
+        (self.timer, self.pin.unwrap(), self.gpiote, self.ppi)
+    }
+}
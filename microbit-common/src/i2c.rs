@@ -0,0 +1,89 @@
+//! Sharing the I2C bus
+//!
+//! The motion sensor and the Grove I2C port are on the same bus, which is
+//! driven by a single TWI peripheral. Most sensor drivers take ownership of
+//! the bus, [`SharedBus`] hands out any number of [`BusProxy`]s instead which
+//! each implement the embedded-hal I2C traits.
+//!
+//! The bus is borrowed for the duration of each transfer. A transfer from an
+//! interrupt handler while another one is running panics, so only use the
+//! proxies from one execution context.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! # use microbit_common as microbit;
+//! use embedded_hal::blocking::i2c::{Write, WriteRead};
+//!
+//! use microbit::i2c::SharedBus;
+//!
+//! fn use_both<I2C: Write + WriteRead>(i2c: I2C) {
+//!     let bus = SharedBus::new(i2c);
+//!     let mut sensor = bus.acquire();
+//!     let mut display = bus.acquire();
+//!     let mut id = [0];
+//!     sensor.write_read(0x18, &[0x00], &mut id).ok();
+//!     display.write(0x3c, &[0x00, 0xaf]).ok();
+//! }
+//! ```
+use core::cell::RefCell;
+
+use embedded_hal::blocking::i2c::{Read, Write, WriteRead};
+
+/// An I2C bus used by several drivers
+pub struct SharedBus<I2C> {
+    bus: RefCell<I2C>,
+}
+
+impl<I2C> SharedBus<I2C> {
+    /// Share a bus
+    pub fn new(i2c: I2C) -> Self {
+        Self {
+            bus: RefCell::new(i2c),
+        }
+    }
+
+    /// Create a handle to the bus for a driver
+    pub fn acquire(&self) -> BusProxy<'_, I2C> {
+        BusProxy { bus: &self.bus }
+    }
+
+    /// Release the bus once all proxies are gone
+    pub fn into_inner(self) -> I2C {
+        self.bus.into_inner()
+    }
+}
+
+/// Handle to a [`SharedBus`]
+pub struct BusProxy<'a, I2C> {
+    bus: &'a RefCell<I2C>,
+}
+
+impl<'a, I2C: Read> Read for BusProxy<'a, I2C> {
+    type Error = I2C::Error;
+
+    fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), Self::Error> {
+        self.bus.borrow_mut().read(address, buffer)
+    }
+}
+
+impl<'a, I2C: Write> Write for BusProxy<'a, I2C> {
+    type Error = I2C::Error;
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.bus.borrow_mut().write(address, bytes)
+    }
+}
+
+impl<'a, I2C: WriteRead> WriteRead for BusProxy<'a, I2C> {
+    type Error = I2C::Error;
+
+    fn write_read(
+        &mut self,
+        address: u8,
+        bytes: &[u8],
+        buffer: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        self.bus.borrow_mut().write_read(address, bytes, buffer)
+    }
+}
@@ -0,0 +1,406 @@
+//! Infrared remote controls
+//!
+//! [`IrReceiver`] decodes the NEC protocol used by most cheap remote
+//! controls from a 38 kHz IR receiver module like the TSOP38238 or VS1838B.
+//! The receiver output is low while it sees the carrier, a "mark", and high
+//! otherwise, a "space". A frame is made of:
+//!
+//! - a 9 ms mark and a 4.5 ms space
+//! - 32 bits, least significant first, each a 562 µs mark followed by a
+//!   562 µs space for 0 or a 1687 µs space for 1
+//! - a final 562 µs mark
+//!
+//! The bits are the address, the inverted address, the command and the
+//! inverted command. Remotes using the extended protocol send a 16 bit
+//! address instead of the address and its inverse. While a button is held,
+//! the remote sends a repeat code every 108 ms: a 9 ms mark, a 2.25 ms space
+//! and a 562 µs mark.
+//!
+//! The edges are timestamped in hardware like in [`PulseIn`](crate::pulse::PulseIn):
+//! a GPIOTE channel watching the pin triggers a capture of the TIMER through
+//! PPI. Call [`IrReceiver::handle_ir_event()`] in the GPIOTE interrupt
+//! handler and in the interrupt handler of the timer you are using. The
+//! decoded codes are turned into [`IrEvent`]s like the presses and releases
+//! of a button.
+//!
+//! The decoding itself is done by [`NecDecoder`] which only needs the length
+//! of each mark and space.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use microbit_common as microbit;
+//! use microbit::{
+//!     channels::{GpioteChannels, PpiChannels},
+//!     infrared::{IrEvent, IrReceiver},
+//!     Board,
+//! };
+//!
+//! let board = Board::take().unwrap();
+//! let gpiote = GpioteChannels::new(board.GPIOTE);
+//! let ppi = PpiChannels::new(board.PPI);
+//!
+//! let mut receiver = IrReceiver::new(
+//!     board.TIMER1,
+//!     board.edge.e01.into_pullup_input().degrade(),
+//!     gpiote.channel0,
+//!     ppi.ppi0,
+//! );
+//!
+//! // in the GPIOTE and TIMER1 interrupts
+//! {
+//!     receiver.handle_ir_event();
+//! }
+//!
+//! while let Some(event) = receiver.next_event() {
+//!     if let IrEvent::Pressed(code) = event {
+//!         let _button = code.command;
+//!     }
+//! }
+//! ```
+use embedded_hal::digital::v2::InputPin;
+
+use crate::{
+    channels::{GpioteChannel, PpiChannel},
+    hal::{
+        gpio::{Input, Pin, PullUp},
+        gpiote::EventPolarity,
+        timer::Instance,
+    },
+    input::Queue,
+};
+
+/// Number of events kept until they are read
+pub const QUEUE_LEN: usize = 8;
+
+/// Time without a repeat code after which a button counts as released
+pub const RELEASE_US: u32 = 150_000;
+
+/// Length of a timer tick, 16 MHz / 2^6
+const TICK_US: u32 = 4;
+
+const LEADER_MARK_US: u32 = 9000;
+const LEADER_SPACE_US: u32 = 4500;
+const REPEAT_SPACE_US: u32 = 2250;
+const BIT_MARK_US: u32 = 562;
+const ZERO_SPACE_US: u32 = 562;
+const ONE_SPACE_US: u32 = 1687;
+
+/// Receivers stretch marks and shorten spaces by up to 100 µs, on top of the
+/// tolerance of the remote's clock
+fn near(duration_us: u32, nominal_us: u32) -> bool {
+    duration_us.abs_diff(nominal_us) <= nominal_us / 4 + 100
+}
+
+/// Address and command of a frame
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct NecCode {
+    /// 8 bit address, or 16 bit address of the extended protocol
+    pub address: u16,
+    /// Command, usually the button
+    pub command: u8,
+}
+
+impl NecCode {
+    /// Check the inverted bytes of the 32 bits of a frame
+    ///
+    /// ```
+    /// # use microbit_common as microbit;
+    /// use microbit::infrared::NecCode;
+    ///
+    /// let code = NecCode::from_bits(0xba45_ff00).unwrap();
+    /// assert_eq!((code.address, code.command), (0x00, 0x45));
+    /// // extended address
+    /// assert_eq!(NecCode::from_bits(0xba45_1234).unwrap().address, 0x1234);
+    /// // corrupt command
+    /// assert_eq!(NecCode::from_bits(0xbb45_ff00), None);
+    /// ```
+    pub fn from_bits(bits: u32) -> Option<Self> {
+        let [address, address_inverse, command, command_inverse] = bits.to_le_bytes();
+        if command != !command_inverse {
+            return None;
+        }
+        let address = if address == !address_inverse {
+            u16::from(address)
+        } else {
+            bits as u16
+        };
+        Some(Self { address, command })
+    }
+}
+
+/// Result of [`NecDecoder::pulse`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Decoded {
+    /// A complete and valid frame
+    Code(NecCode),
+    /// The button of the last frame is still held
+    Repeat,
+}
+
+#[derive(Copy, Clone, Debug)]
+enum State {
+    Idle,
+    LeaderSpace,
+    BitMark { bits: u32, count: u8 },
+    BitSpace { bits: u32, count: u8 },
+    StopMark { bits: u32 },
+    RepeatStopMark,
+}
+
+/// NEC protocol state machine
+///
+/// Feed it the length of every mark and space in the order they are
+/// received. A pulse that does not fit restarts the search for a frame.
+///
+/// ```
+/// # use microbit_common as microbit;
+/// use microbit::infrared::{Decoded, NecCode, NecDecoder};
+///
+/// // spaces of the 32 bits recorded from a remote, each after a mark of
+/// // about 600 µs
+/// let spaces = [
+///     520, 540, 530, 520, 540, 530, 520, 540, // address 0x00
+///     1650, 1660, 1640, 1650, 1660, 1650, 1640, 1660, // inverse 0xff
+///     1650, 530, 1640, 540, 520, 530, 1660, 540, // command 0x45
+///     520, 1650, 530, 1660, 1650, 1640, 540, 1650, // inverse 0xba
+/// ];
+///
+/// let mut decoder = NecDecoder::new();
+/// assert_eq!(decoder.pulse(true, 9080), None);
+/// assert_eq!(decoder.pulse(false, 4420), None);
+/// for space in spaces {
+///     assert_eq!(decoder.pulse(true, 610), None);
+///     assert_eq!(decoder.pulse(false, space), None);
+/// }
+/// let code = NecCode { address: 0x00, command: 0x45 };
+/// assert_eq!(decoder.pulse(true, 600), Some(Decoded::Code(code)));
+///
+/// // repeat code 40 ms later
+/// assert_eq!(decoder.pulse(false, 40_000), None);
+/// assert_eq!(decoder.pulse(true, 9030), None);
+/// assert_eq!(decoder.pulse(false, 2200), None);
+/// assert_eq!(decoder.pulse(true, 620), Some(Decoded::Repeat));
+/// ```
+#[derive(Copy, Clone, Debug)]
+pub struct NecDecoder {
+    state: State,
+}
+
+impl NecDecoder {
+    /// Wait for the start of a frame
+    pub const fn new() -> Self {
+        Self { state: State::Idle }
+    }
+
+    /// Feed the length of a mark or a space in µs
+    pub fn pulse(&mut self, mark: bool, duration_us: u32) -> Option<Decoded> {
+        let (state, decoded) = self.next(mark, duration_us);
+        self.state = match state {
+            Some(state) => state,
+            // the pulse might start a new frame
+            None => self.restart(mark, duration_us),
+        };
+        decoded
+    }
+
+    fn restart(&self, mark: bool, duration_us: u32) -> State {
+        if mark && near(duration_us, LEADER_MARK_US) {
+            State::LeaderSpace
+        } else {
+            State::Idle
+        }
+    }
+
+    /// Next state, or `None` if the pulse does not fit
+    fn next(&self, mark: bool, duration_us: u32) -> (Option<State>, Option<Decoded>) {
+        let state = match (self.state, mark) {
+            (State::Idle, _) => None,
+            (State::LeaderSpace, false) if near(duration_us, LEADER_SPACE_US) => {
+                Some(State::BitMark { bits: 0, count: 0 })
+            }
+            (State::LeaderSpace, false) if near(duration_us, REPEAT_SPACE_US) => {
+                Some(State::RepeatStopMark)
+            }
+            (State::BitMark { bits, count }, true) if near(duration_us, BIT_MARK_US) => {
+                Some(State::BitSpace { bits, count })
+            }
+            (State::BitSpace { bits, count }, false) => {
+                let bit = if near(duration_us, ONE_SPACE_US) {
+                    Some(1 << count)
+                } else if near(duration_us, ZERO_SPACE_US) {
+                    Some(0)
+                } else {
+                    None
+                };
+                bit.map(|bit| {
+                    let (bits, count) = (bits | bit, count + 1);
+                    if count == 32 {
+                        State::StopMark { bits }
+                    } else {
+                        State::BitMark { bits, count }
+                    }
+                })
+            }
+            (State::StopMark { bits }, true) if near(duration_us, BIT_MARK_US) => {
+                let decoded = NecCode::from_bits(bits).map(Decoded::Code);
+                return (Some(State::Idle), decoded);
+            }
+            (State::RepeatStopMark, true) if near(duration_us, BIT_MARK_US) => {
+                return (Some(State::Idle), Some(Decoded::Repeat));
+            }
+            _ => None,
+        };
+        (state, None)
+    }
+}
+
+impl Default for NecDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Button-like event of a remote control
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum IrEvent {
+    /// A frame with a new code has been received
+    Pressed(NecCode),
+    /// The button is still held
+    Repeated(NecCode),
+    /// No repeat code arrived within [`RELEASE_US`], or another button has
+    /// been pressed
+    Released(NecCode),
+}
+
+/// NEC receiver using a TIMER, a GPIOTE and a PPI channel
+pub struct IrReceiver<T: Instance> {
+    timer: T,
+    pin: Pin<Input<PullUp>>,
+    gpiote: GpioteChannel,
+    ppi: PpiChannel,
+    last_capture: u16,
+    decoder: NecDecoder,
+    held: Option<NecCode>,
+    events: Queue<IrEvent, QUEUE_LEN>,
+}
+
+impl<T: Instance> IrReceiver<T> {
+    /// Take the resources and start listening on `pin`
+    ///
+    /// Enables the GPIOTE interrupt of the channel and the COMPARE\[1\]
+    /// interrupt of the timer, which detects released buttons.
+    pub fn new(
+        timer: T,
+        pin: Pin<Input<PullUp>>,
+        mut gpiote: GpioteChannel,
+        mut ppi: PpiChannel,
+    ) -> Self {
+        let regs = timer.as_timer0();
+        regs.tasks_stop.write(|w| unsafe { w.bits(1) });
+        regs.tasks_clear.write(|w| unsafe { w.bits(1) });
+        regs.mode.write(|w| w.mode().timer());
+        regs.bitmode.write(|w| w.bitmode()._16bit());
+        // 16 MHz / 2^6 = 250 kHz, the 16 bit counter wraps after 262 ms
+        regs.prescaler.write(|w| unsafe { w.bits(6) });
+        regs.shorts.reset();
+        regs.intenclr.write(|w| unsafe { w.bits(0xffff_ffff) });
+        regs.tasks_start.write(|w| unsafe { w.bits(1) });
+
+        // every edge seen by the GPIOTE channel captures the counter into CC[0]
+        gpiote.input_pin(&pin, EventPolarity::Toggle);
+        gpiote.reset_events();
+        gpiote.enable_interrupt();
+        ppi.set_event_endpoint(gpiote.event());
+        ppi.set_task_endpoint(&regs.tasks_capture[0]);
+        ppi.enable();
+
+        Self {
+            timer,
+            pin,
+            gpiote,
+            ppi,
+            last_capture: 0,
+            decoder: NecDecoder::new(),
+            held: None,
+            events: Queue::new(),
+        }
+    }
+
+    /// Code of the button currently held
+    pub fn held(&self) -> Option<NecCode> {
+        self.held
+    }
+
+    /// Oldest event which has not been read yet
+    pub fn next_event(&mut self) -> Option<IrEvent> {
+        self.events.pop()
+    }
+
+    /// Decode a new edge and detect released buttons
+    ///
+    /// Call this in the GPIOTE interrupt handler and in the interrupt handler
+    /// of the timer. It clears the events of the GPIOTE channel and the
+    /// timer's event registers.
+    pub fn handle_ir_event(&mut self) {
+        let regs = self.timer.as_timer0();
+        if self.gpiote.is_event_triggered() {
+            self.gpiote.reset_events();
+            let capture = regs.cc[0].read().bits() as u16;
+            let ticks = capture.wrapping_sub(self.last_capture);
+            self.last_capture = capture;
+            // the pin is at the level after the edge, the receiver output is
+            // low during a mark
+            let mark = self.pin.is_high().unwrap_or(true);
+            match self.decoder.pulse(mark, u32::from(ticks) * TICK_US) {
+                Some(Decoded::Code(code)) => {
+                    if let Some(held) = self.held.replace(code) {
+                        self.push(IrEvent::Released(held));
+                    }
+                    self.push(IrEvent::Pressed(code));
+                    self.arm_release(capture);
+                }
+                Some(Decoded::Repeat) => {
+                    if let Some(held) = self.held {
+                        self.push(IrEvent::Repeated(held));
+                        self.arm_release(capture);
+                    }
+                }
+                None => {}
+            }
+        }
+
+        let regs = self.timer.as_timer0();
+        if regs.events_compare[1].read().bits() != 0 {
+            regs.events_compare[1].write(|w| unsafe { w.bits(0) });
+            regs.intenclr.write(|w| w.compare1().set_bit());
+            if let Some(held) = self.held.take() {
+                self.push(IrEvent::Released(held));
+            }
+        }
+    }
+
+    fn push(&mut self, event: IrEvent) {
+        // a full queue drops the newest events
+        self.events.push(event).ok();
+    }
+
+    fn arm_release(&mut self, capture: u16) {
+        let regs = self.timer.as_timer0();
+        let release = capture.wrapping_add((RELEASE_US / TICK_US) as u16);
+        regs.cc[1].write(|w| unsafe { w.bits(release.into()) });
+        regs.events_compare[1].write(|w| unsafe { w.bits(0) });
+        regs.intenset.write(|w| w.compare1().set());
+    }
+
+    /// Stop the timer and release the resources
+    pub fn free(mut self) -> (T, Pin<Input<PullUp>>, GpioteChannel, PpiChannel) {
+        self.ppi.disable();
+        self.gpiote.disable_interrupt();
+        self.gpiote.disable();
+        let regs = self.timer.as_timer0();
+        regs.tasks_stop.write(|w| unsafe { w.bits(1) });
+        regs.intenclr.write(|w| unsafe { w.bits(0xffff_ffff) });
+        (self.timer, self.pin, self.gpiote, self.ppi)
+    }
+}
@@ -0,0 +1,310 @@
+//! Quadrature rotary encoders
+//!
+//! A rotary encoder has two outputs, A and B, which switch in turn with a
+//! quarter of a cycle between them while the knob is turned. Which of them
+//! switches first gives the direction. Most encoders with detents, like the
+//! KY-040, go through a full cycle of four changes from one detent to the
+//! next.
+//!
+//! [`Encoder`] decodes the signals in software from GPIOTE edge events and
+//! works with any pair of edge pins. Call [`Encoder::handle_encoder_event()`]
+//! in the GPIOTE interrupt handler.
+#![cfg_attr(
+    feature = "v2",
+    doc = "\n[`QdecEncoder`] uses the QDEC peripheral of the nRF52833 instead, which samples and debounces the signals in hardware."
+)]
+//!
+//! ## Example
+//!
+//! ```no_run
+//! # use microbit_common as microbit;
+//! use microbit::{
+//!     channels::GpioteChannels,
+//!     input::encoder::{Direction, Encoder},
+//!     Board,
+//! };
+//!
+//! let board = Board::take().unwrap();
+//! let gpiote = GpioteChannels::new(board.GPIOTE);
+//! let mut encoder = Encoder::new(
+//!     board.edge.e00.into_pullup_input(),
+//!     board.edge.e01.into_pullup_input(),
+//!     [gpiote.channel0, gpiote.channel1],
+//! );
+//!
+//! // in the GPIOTE interrupt
+//! {
+//!     encoder.handle_encoder_event();
+//! }
+//!
+//! while let Some(direction) = encoder.next_event() {
+//!     match direction {
+//!         Direction::Clockwise => {}
+//!         Direction::CounterClockwise => {}
+//!     }
+//! }
+//! ```
+use embedded_hal::digital::v2::InputPin;
+
+use crate::{
+    channels::GpioteChannel,
+    hal::{
+        gpio::{Input, Pin, PullUp},
+        gpiote::EventPolarity,
+    },
+    input::Queue,
+};
+
+#[cfg(feature = "v2")]
+use crate::hal::{
+    pac::QDEC,
+    qdec::{NumSamples, Qdec, SamplePeriod},
+};
+
+/// Number of turn events kept until they are read
+pub const QUEUE_LEN: usize = 16;
+
+/// Changes per detent of most encoders
+pub const DEFAULT_STEPS_PER_DETENT: u8 = 4;
+
+/// Direction of a turn by one detent
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Direction {
+    /// A changes before B
+    Clockwise,
+    /// B changes before A
+    CounterClockwise,
+}
+
+/// Quadrature decoder state machine
+///
+/// ```
+/// # use microbit_common as microbit;
+/// use microbit::input::encoder::Quadrature;
+///
+/// let mut quadrature = Quadrature::new(false, false);
+/// // A leads B
+/// let steps: i32 = [(true, false), (true, true), (false, true), (false, false)]
+///     .into_iter()
+///     .map(|(a, b)| i32::from(quadrature.update(a, b)))
+///     .sum();
+/// assert_eq!(steps, 4);
+/// // B leads A
+/// assert_eq!(quadrature.update(false, true), -1);
+/// // both changed at once, the direction is unknown
+/// assert_eq!(quadrature.update(true, false), 0);
+/// ```
+#[derive(Copy, Clone, Debug)]
+pub struct Quadrature {
+    state: u8,
+}
+
+impl Quadrature {
+    /// Start with the current levels of A and B
+    pub fn new(a: bool, b: bool) -> Self {
+        Self {
+            state: Self::encode(a, b),
+        }
+    }
+
+    /// Feed new levels, returns the step taken: -1, 0 or 1
+    pub fn update(&mut self, a: bool, b: bool) -> i8 {
+        // indexed by the previous and the new state in gray code order
+        const STEPS: [i8; 16] = [0, 1, -1, 0, -1, 0, 0, 1, 1, 0, 0, -1, 0, -1, 1, 0];
+        let state = Self::encode(a, b);
+        let step = STEPS[usize::from(self.state << 2 | state)];
+        self.state = state;
+        step
+    }
+
+    fn encode(a: bool, b: bool) -> u8 {
+        u8::from(a) | u8::from(b) << 1
+    }
+}
+
+/// Counts steps into detents and queues a turn event for each
+struct Detents {
+    steps: i32,
+    steps_per_detent: u8,
+    events: Queue<Direction, QUEUE_LEN>,
+}
+
+impl Detents {
+    fn new() -> Self {
+        Self {
+            steps: 0,
+            steps_per_detent: DEFAULT_STEPS_PER_DETENT,
+            events: Queue::new(),
+        }
+    }
+
+    fn position(&self) -> i32 {
+        self.steps.div_euclid(self.steps_per_detent.into())
+    }
+
+    fn add(&mut self, steps: i32) {
+        let before = self.position();
+        self.steps = self.steps.wrapping_add(steps);
+        let after = self.position();
+        let direction = if after > before {
+            Direction::Clockwise
+        } else {
+            Direction::CounterClockwise
+        };
+        for _ in 0..(after - before).unsigned_abs() {
+            // a full queue drops the newest turns, the position stays right
+            self.events.push(direction).ok();
+        }
+    }
+}
+
+/// Rotary encoder decoded from GPIOTE edge events
+pub struct Encoder {
+    a: Pin<Input<PullUp>>,
+    b: Pin<Input<PullUp>>,
+    channels: [GpioteChannel; 2],
+    quadrature: Quadrature,
+    detents: Detents,
+}
+
+impl Encoder {
+    /// Watch both edges of A and B and enable their GPIOTE interrupts
+    ///
+    /// The pins can be given as the edge pins of the board or degraded.
+    pub fn new(
+        a: impl Into<Pin<Input<PullUp>>>,
+        b: impl Into<Pin<Input<PullUp>>>,
+        mut channels: [GpioteChannel; 2],
+    ) -> Self {
+        let (a, b) = (a.into(), b.into());
+        for (channel, pin) in channels.iter_mut().zip([&a, &b]) {
+            channel.input_pin(pin, EventPolarity::Toggle);
+            channel.reset_events();
+            channel.enable_interrupt();
+        }
+        let quadrature =
+            Quadrature::new(a.is_high().unwrap_or(false), b.is_high().unwrap_or(false));
+        Self {
+            a,
+            b,
+            channels,
+            quadrature,
+            detents: Detents::new(),
+        }
+    }
+
+    /// Set the number of changes from one detent to the next, at least 1
+    pub fn set_steps_per_detent(&mut self, steps: u8) {
+        self.detents.steps_per_detent = steps.max(1);
+    }
+
+    /// Position in detents, clockwise is positive
+    pub fn position(&self) -> i32 {
+        self.detents.position()
+    }
+
+    /// Redefine the current position
+    pub fn set_position(&mut self, position: i32) {
+        self.detents.steps = position * i32::from(self.detents.steps_per_detent);
+    }
+
+    /// Oldest turn which has not been read yet
+    pub fn next_event(&mut self) -> Option<Direction> {
+        self.detents.events.pop()
+    }
+
+    /// Decode the changes of A and B
+    ///
+    /// Call this in the GPIOTE interrupt handler. It clears the events of
+    /// the encoder's GPIOTE channels.
+    pub fn handle_encoder_event(&mut self) {
+        let mut changed = false;
+        for channel in &self.channels {
+            if channel.is_event_triggered() {
+                channel.reset_events();
+                changed = true;
+            }
+        }
+        if changed {
+            let step = self.quadrature.update(
+                self.a.is_high().unwrap_or(false),
+                self.b.is_high().unwrap_or(false),
+            );
+            self.detents.add(step.into());
+        }
+    }
+
+    /// Stop watching the pins and release them with the GPIOTE channels
+    pub fn free(mut self) -> (Pin<Input<PullUp>>, Pin<Input<PullUp>>, [GpioteChannel; 2]) {
+        for channel in &mut self.channels {
+            channel.disable_interrupt();
+            channel.disable();
+        }
+        (self.a, self.b, self.channels)
+    }
+}
+
+/// Rotary encoder decoded by the QDEC peripheral
+#[cfg(feature = "v2")]
+pub struct QdecEncoder {
+    qdec: Qdec,
+    detents: Detents,
+}
+
+#[cfg(feature = "v2")]
+impl QdecEncoder {
+    /// Start sampling A and B every 128 µs with the debounce filter on
+    ///
+    /// The QDEC interrupt fires after every 10 samples with a change.
+    pub fn new(
+        qdec: QDEC,
+        a: impl Into<Pin<Input<PullUp>>>,
+        b: impl Into<Pin<Input<PullUp>>>,
+    ) -> Self {
+        let qdec = Qdec::new(qdec, a.into(), b.into(), None, SamplePeriod::_128us);
+        qdec.debounce(true).enable_interrupt(NumSamples::_10smpl);
+        qdec.enable();
+        Self {
+            qdec,
+            detents: Detents::new(),
+        }
+    }
+
+    /// Set the number of changes from one detent to the next, at least 1
+    pub fn set_steps_per_detent(&mut self, steps: u8) {
+        self.detents.steps_per_detent = steps.max(1);
+    }
+
+    /// Position in detents, clockwise is positive
+    pub fn position(&self) -> i32 {
+        self.detents.position()
+    }
+
+    /// Redefine the current position
+    pub fn set_position(&mut self, position: i32) {
+        self.detents.steps = position * i32::from(self.detents.steps_per_detent);
+    }
+
+    /// Oldest turn which has not been read yet
+    pub fn next_event(&mut self) -> Option<Direction> {
+        self.detents.events.pop()
+    }
+
+    /// Collect the changes counted by the QDEC
+    ///
+    /// Call this in the QDEC interrupt handler or periodically, at least
+    /// every 1024 changes. It clears the events of the QDEC.
+    pub fn handle_encoder_event(&mut self) {
+        self.qdec.reset_events();
+        let steps = self.qdec.read();
+        self.detents.add(steps.into());
+    }
+
+    /// Stop the QDEC and release it with the pins
+    pub fn free(self) -> (QDEC, Pin<Input<PullUp>>, Pin<Input<PullUp>>) {
+        self.qdec.disable_interrupt();
+        self.qdec.disable();
+        let (qdec, a, b, _) = self.qdec.free();
+        (qdec, a, b)
+    }
+}
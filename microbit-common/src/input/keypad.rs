@@ -0,0 +1,263 @@
+//! Matrix keypads
+//!
+//! A membrane keypad connects the keys of each row to the column lines.
+//! [`Keypad`] pulls one row low at a time and reads which columns follow.
+//! The columns have pull-ups and the rows are open drain outputs, so keys
+//! pressed at the same time cannot short two outputs.
+//!
+//! Call [`Keypad::scan()`] periodically, e.g. every millisecond from a timer
+//! interrupt. Each call reads one row and selects the next, which gives the
+//! lines time to settle. A key changes its state after it has read the same
+//! for [`DEBOUNCE_SCANS`] scans of its row, and each change is queued as a
+//! [`KeyEvent`].
+//!
+//! ## Example
+//!
+//! A 3x4 keypad with the rows on the LED columns 1 to 4 and the columns on
+//! the first three pads.
+//!
+//! ```no_run
+//! # use microbit_common as microbit;
+//! use microbit::{
+//!     hal::gpio::{Level, OpenDrainConfig},
+//!     input::keypad::{KeyEvent, Keypad3x4, KEYMAP_3X4},
+//!     Board,
+//! };
+//!
+//! let board = Board::take().unwrap();
+//! let pins = board.display_pins;
+//! let row = |pin: microbit::hal::gpio::Pin<_>| {
+//!     pin.into_open_drain_output(OpenDrainConfig::Standard0Disconnect1, Level::High)
+//! };
+//! let mut keypad = Keypad3x4::new(
+//!     [
+//!         row(pins.col1.degrade()),
+//!         row(pins.col2.degrade()),
+//!         row(pins.col3.degrade()),
+//!         row(pins.col4.degrade()),
+//!     ],
+//!     [
+//!         board.edge.e00.into_pullup_input().degrade(),
+//!         board.edge.e01.into_pullup_input().degrade(),
+//!         board.edge.e02.into_pullup_input().degrade(),
+//!     ],
+//! );
+//!
+//! // every millisecond
+//! {
+//!     keypad.scan();
+//! }
+//!
+//! while let Some(event) = keypad.next_event() {
+//!     if let KeyEvent::Pressed(key) = event {
+//!         let _digit = key.char(&KEYMAP_3X4);
+//!     }
+//! }
+//! ```
+use embedded_hal::digital::v2::{InputPin, OutputPin};
+
+use crate::{
+    hal::gpio::{Input, OpenDrain, Output, Pin, PullUp},
+    input::Queue,
+};
+
+/// Number of equal reads before a key changes its state
+pub const DEBOUNCE_SCANS: u8 = 4;
+
+/// Number of key events kept until they are read
+pub const QUEUE_LEN: usize = 16;
+
+/// Labels of a 3x4 telephone keypad
+pub const KEYMAP_3X4: [[char; 3]; 4] = [
+    ['1', '2', '3'],
+    ['4', '5', '6'],
+    ['7', '8', '9'],
+    ['*', '0', '#'],
+];
+
+/// Labels of a 4x4 keypad
+pub const KEYMAP_4X4: [[char; 4]; 4] = [
+    ['1', '2', '3', 'A'],
+    ['4', '5', '6', 'B'],
+    ['7', '8', '9', 'C'],
+    ['*', '0', '#', 'D'],
+];
+
+/// Position of a key
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Key {
+    /// Row, from the top
+    pub row: u8,
+    /// Column, from the left
+    pub col: u8,
+}
+
+impl Key {
+    /// Label of the key in a keymap
+    ///
+    /// ```
+    /// # use microbit_common as microbit;
+    /// use microbit::input::keypad::{Key, KEYMAP_3X4};
+    ///
+    /// assert_eq!(Key { row: 3, col: 1 }.char(&KEYMAP_3X4), '0');
+    /// ```
+    pub fn char<const COLS: usize>(self, keymap: &[[char; COLS]]) -> char {
+        keymap[usize::from(self.row)][usize::from(self.col)]
+    }
+}
+
+/// Change of a key
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum KeyEvent {
+    /// The key has been pressed
+    Pressed(Key),
+    /// The key has been released
+    Released(Key),
+}
+
+/// Debounced state of all keys of a keypad
+///
+/// ```
+/// # use microbit_common as microbit;
+/// use microbit::input::keypad::{Debouncer, Key, KeyEvent, DEBOUNCE_SCANS};
+///
+/// let mut debouncer: Debouncer<4, 3> = Debouncer::new();
+/// let key = Key { row: 0, col: 2 };
+/// // a bounce is ignored
+/// assert_eq!(debouncer.update(key, true), None);
+/// assert_eq!(debouncer.update(key, false), None);
+/// for _ in 1..DEBOUNCE_SCANS {
+///     assert_eq!(debouncer.update(key, true), None);
+/// }
+/// assert_eq!(debouncer.update(key, true), Some(KeyEvent::Pressed(key)));
+/// assert!(debouncer.is_pressed(key));
+/// ```
+#[derive(Clone, Debug)]
+pub struct Debouncer<const ROWS: usize, const COLS: usize> {
+    pressed: [[bool; COLS]; ROWS],
+    counts: [[u8; COLS]; ROWS],
+}
+
+impl<const ROWS: usize, const COLS: usize> Debouncer<ROWS, COLS> {
+    /// All keys released
+    pub const fn new() -> Self {
+        Self {
+            pressed: [[false; COLS]; ROWS],
+            counts: [[0; COLS]; ROWS],
+        }
+    }
+
+    /// Debounced state of a key
+    pub fn is_pressed(&self, key: Key) -> bool {
+        self.pressed[usize::from(key.row)][usize::from(key.col)]
+    }
+
+    /// Feed a raw read of a key, returns the event once it has settled
+    pub fn update(&mut self, key: Key, pressed: bool) -> Option<KeyEvent> {
+        let (row, col) = (usize::from(key.row), usize::from(key.col));
+        let count = &mut self.counts[row][col];
+        if pressed == self.pressed[row][col] {
+            *count = 0;
+            return None;
+        }
+        *count += 1;
+        if *count < DEBOUNCE_SCANS {
+            return None;
+        }
+        *count = 0;
+        self.pressed[row][col] = pressed;
+        Some(if pressed {
+            KeyEvent::Pressed(key)
+        } else {
+            KeyEvent::Released(key)
+        })
+    }
+}
+
+impl<const ROWS: usize, const COLS: usize> Default for Debouncer<ROWS, COLS> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Row output of a keypad
+///
+/// The rows and columns are held in arrays, so they are degraded to one
+/// type whatever edge or LED pins they are on.
+pub type RowPin = Pin<Output<OpenDrain>>;
+
+/// Column input of a keypad
+pub type ColPin = Pin<Input<PullUp>>;
+
+/// Keypad with `ROWS` rows and `COLS` columns
+pub struct Keypad<const ROWS: usize, const COLS: usize> {
+    rows: [RowPin; ROWS],
+    cols: [ColPin; COLS],
+    row: usize,
+    debouncer: Debouncer<ROWS, COLS>,
+    events: Queue<KeyEvent, QUEUE_LEN>,
+}
+
+/// Telephone style keypad with 4 rows and 3 columns
+pub type Keypad3x4 = Keypad<4, 3>;
+
+/// Keypad with 4 rows and 4 columns
+pub type Keypad4x4 = Keypad<4, 4>;
+
+impl<const ROWS: usize, const COLS: usize> Keypad<ROWS, COLS> {
+    /// Start scanning with the first row
+    pub fn new(mut rows: [RowPin; ROWS], cols: [ColPin; COLS]) -> Self {
+        for (index, row) in rows.iter_mut().enumerate() {
+            if index == 0 {
+                row.set_low().ok();
+            } else {
+                row.set_high().ok();
+            }
+        }
+        Self {
+            rows,
+            cols,
+            row: 0,
+            debouncer: Debouncer::new(),
+            events: Queue::new(),
+        }
+    }
+
+    /// Read the selected row and select the next one
+    pub fn scan(&mut self) {
+        for (col, pin) in self.cols.iter().enumerate() {
+            let key = Key {
+                row: self.row as u8,
+                col: col as u8,
+            };
+            let pressed = pin.is_low().unwrap_or(false);
+            if let Some(event) = self.debouncer.update(key, pressed) {
+                // a full queue drops the newest events
+                self.events.push(event).ok();
+            }
+        }
+        self.rows[self.row].set_high().ok();
+        self.row = (self.row + 1) % ROWS;
+        self.rows[self.row].set_low().ok();
+    }
+
+    /// Debounced state of a key
+    pub fn is_pressed(&self, key: Key) -> bool {
+        self.debouncer.is_pressed(key)
+    }
+
+    /// Oldest key event which has not been read yet
+    pub fn next_event(&mut self) -> Option<KeyEvent> {
+        self.events.pop()
+    }
+
+    /// Release the row and column pins
+    ///
+    /// All rows are left floating.
+    pub fn free(mut self) -> ([RowPin; ROWS], [ColPin; COLS]) {
+        for row in &mut self.rows {
+            row.set_high().ok();
+        }
+        (self.rows, self.cols)
+    }
+}
@@ -0,0 +1,15 @@
+//! Rotary encoders, matrix keypads and pin-change notifications
+//!
+//! The drivers in this module turn pin changes into events which are
+//! collected in a [`Queue`] from an interrupt handler or a periodic task and
+//! read by the application at its own pace.
+//!
+//! - [`encoder`]: quadrature rotary encoders
+//! - [`keypad`]: 3x4 and 4x4 membrane keypads
+//! - [`watch`]: edge and press events of any edge pin
+pub mod encoder;
+pub mod keypad;
+mod queue;
+pub mod watch;
+
+pub use queue::Queue;
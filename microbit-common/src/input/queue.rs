@@ -0,0 +1,73 @@
+/// Fixed size first in, first out queue of events
+///
+/// ```
+/// # use microbit_common as microbit;
+/// use microbit::input::Queue;
+///
+/// let mut queue: Queue<u8, 2> = Queue::new();
+/// assert_eq!(queue.push(1), Ok(()));
+/// assert_eq!(queue.push(2), Ok(()));
+/// assert_eq!(queue.push(3), Err(3));
+/// assert_eq!(queue.pop(), Some(1));
+/// assert_eq!(queue.pop(), Some(2));
+/// assert_eq!(queue.pop(), None);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Queue<T: Copy, const N: usize> {
+    items: [Option<T>; N],
+    head: usize,
+    len: usize,
+}
+
+impl<T: Copy, const N: usize> Queue<T, N> {
+    /// Create an empty queue
+    pub const fn new() -> Self {
+        Self {
+            items: [None; N],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    /// Append an item, returns it if the queue is full
+    pub fn push(&mut self, item: T) -> Result<(), T> {
+        if self.len == N {
+            return Err(item);
+        }
+        self.items[(self.head + self.len) % N] = Some(item);
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Remove the oldest item
+    pub fn pop(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        let item = self.items[self.head].take();
+        self.head = (self.head + 1) % N;
+        self.len -= 1;
+        item
+    }
+
+    /// Number of items in the queue
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// The queue holds no items
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Remove all items
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+}
+
+impl<T: Copy, const N: usize> Default for Queue<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,541 @@
+//! Pin-change notifications for edge pins
+//!
+//! [`PinWatcher`] watches up to [`MAX_WATCHES`] pins for rising, falling or
+//! any edges and queues a [`PinEvent`] for each change. Every watched pin
+//! needs a GPIOTE channel of its own.
+#![cfg_attr(
+    feature = "v2",
+    doc = "On the nRF52833 pins can also be watched through the PORT event with [`PinWatcher::watch_port`], which needs no channel and has no limit but the number of watches."
+)]
+//!
+//! Changes can be debounced: after an edge the watcher waits until the pin
+//! has been quiet for the debounce time and only then compares its level to
+//! the last one. The time is counted in 1 ms ticks of a TIMER which only
+//! runs while a pin is settling.
+//!
+//! [`Trigger::Pressed`] gives the "on pin pressed" semantics of the touch
+//! pads P0 to P3: a finger pulls the floating pad low, which is reported as
+//! [`PinEvent::Pressed`], letting go as [`PinEvent::Released`]. It works the
+//! same for a button to GND with a pull-up.
+//!
+//! Call [`PinWatcher::handle_pin_event()`] in the GPIOTE interrupt handler
+//! and in the handler of the TIMER.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use microbit_common as microbit;
+//! use microbit::{
+//!     channels::GpioteChannels,
+//!     input::watch::{PinEvent, PinWatcher, Pull, Trigger},
+//!     Board,
+//! };
+//!
+//! let board = Board::take().unwrap();
+//! let gpiote = GpioteChannels::new(board.GPIOTE);
+//! let mut watcher = PinWatcher::new(board.TIMER1);
+//!
+//! let pad = watcher
+//!     .watch(
+//!         board.edge.e00.into_disconnected().degrade(),
+//!         Pull::None,
+//!         Trigger::Pressed,
+//!         gpiote.channel0,
+//!     )
+//!     .ok()
+//!     .unwrap();
+//! let sensor = watcher
+//!     .watch(
+//!         board.edge.e01.into_disconnected().degrade(),
+//!         Pull::Up,
+//!         Trigger::Falling,
+//!         gpiote.channel1,
+//!     )
+//!     .ok()
+//!     .unwrap();
+//!
+//! // in the GPIOTE and TIMER1 interrupts
+//! {
+//!     watcher.handle_pin_event();
+//! }
+//!
+//! while let Some(event) = watcher.next_event() {
+//!     match event {
+//!         PinEvent::Pressed(id) if id == pad => {}
+//!         PinEvent::Falling(id) if id == sensor => {}
+//!         _ => {}
+//!     }
+//! }
+//! ```
+use embedded_hal::digital::v2::InputPin;
+
+use crate::{
+    channels::GpioteChannel,
+    hal::{
+        gpio::{Disconnected, Floating, Input, Pin, PullDown, PullUp},
+        gpiote::EventPolarity,
+        timer::Instance,
+    },
+    input::Queue,
+};
+
+#[cfg(feature = "v2")]
+use crate::{hal::gpio::Port, pac};
+
+/// Number of pins that can be watched at once
+pub const MAX_WATCHES: usize = 8;
+
+/// Number of pin events kept until they are read
+pub const QUEUE_LEN: usize = 16;
+
+/// Debounce time of [`Trigger::Pressed`] watches
+pub const PRESS_DEBOUNCE_MS: u8 = 20;
+
+/// Pull resistor of a watched pin
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Pull {
+    /// Floating, e.g. for the touch pads
+    None,
+    /// Pull-up
+    Up,
+    /// Pull-down
+    Down,
+}
+
+/// Changes that are reported
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Trigger {
+    /// Low to high
+    Rising,
+    /// High to low
+    Falling,
+    /// Both directions
+    Any,
+    /// Both directions as press and release of an active low input
+    Pressed,
+}
+
+impl Trigger {
+    /// Event for a change of watch `id` to the level `high`, if it is
+    /// reported
+    ///
+    /// ```
+    /// # use microbit_common as microbit;
+    /// use microbit::input::watch::{PinEvent, Trigger};
+    ///
+    /// assert_eq!(Trigger::Rising.event(2, true), Some(PinEvent::Rising(2)));
+    /// assert_eq!(Trigger::Rising.event(2, false), None);
+    /// assert_eq!(Trigger::Any.event(0, false), Some(PinEvent::Falling(0)));
+    /// // a touched pad is pulled low
+    /// assert_eq!(Trigger::Pressed.event(1, false), Some(PinEvent::Pressed(1)));
+    /// assert_eq!(Trigger::Pressed.event(1, true), Some(PinEvent::Released(1)));
+    /// ```
+    pub fn event(self, id: usize, high: bool) -> Option<PinEvent> {
+        match (self, high) {
+            (Trigger::Rising | Trigger::Any, true) => Some(PinEvent::Rising(id)),
+            (Trigger::Falling | Trigger::Any, false) => Some(PinEvent::Falling(id)),
+            (Trigger::Pressed, false) => Some(PinEvent::Pressed(id)),
+            (Trigger::Pressed, true) => Some(PinEvent::Released(id)),
+            _ => None,
+        }
+    }
+
+    fn polarity(self) -> EventPolarity {
+        match self {
+            Trigger::Rising => EventPolarity::LoToHi,
+            Trigger::Falling => EventPolarity::HiToLo,
+            Trigger::Any | Trigger::Pressed => EventPolarity::Toggle,
+        }
+    }
+}
+
+/// A change of a watched pin, identified by the id returned by
+/// [`PinWatcher::watch`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PinEvent {
+    /// The pin went high
+    Rising(usize),
+    /// The pin went low
+    Falling(usize),
+    /// The pin of a [`Trigger::Pressed`] watch went low
+    Pressed(usize),
+    /// The pin of a [`Trigger::Pressed`] watch went high
+    Released(usize),
+}
+
+/// A watched pin with the channel it was watched through, returned by
+/// [`PinWatcher::unwatch`]
+pub type Unwatched = (Pin<Disconnected>, Option<GpioteChannel>);
+
+enum WatchedPin {
+    Floating(Pin<Input<Floating>>),
+    Up(Pin<Input<PullUp>>),
+    Down(Pin<Input<PullDown>>),
+}
+
+impl WatchedPin {
+    fn new(pin: Pin<Disconnected>, pull: Pull) -> Self {
+        match pull {
+            Pull::None => WatchedPin::Floating(pin.into_floating_input()),
+            Pull::Up => WatchedPin::Up(pin.into_pullup_input()),
+            Pull::Down => WatchedPin::Down(pin.into_pulldown_input()),
+        }
+    }
+
+    fn is_high(&self) -> bool {
+        match self {
+            WatchedPin::Floating(pin) => pin.is_high(),
+            WatchedPin::Up(pin) => pin.is_high(),
+            WatchedPin::Down(pin) => pin.is_high(),
+        }
+        .unwrap_or(false)
+    }
+
+    fn watch(&self, channel: &mut GpioteChannel, polarity: EventPolarity) {
+        match self {
+            WatchedPin::Floating(pin) => channel.input_pin(pin, polarity),
+            WatchedPin::Up(pin) => channel.input_pin(pin, polarity),
+            WatchedPin::Down(pin) => channel.input_pin(pin, polarity),
+        }
+    }
+
+    #[cfg(feature = "v2")]
+    fn port_pin(&self) -> (&'static pac::p0::RegisterBlock, u8) {
+        let (port, index) = match self {
+            WatchedPin::Floating(pin) => (pin.port(), pin.pin()),
+            WatchedPin::Up(pin) => (pin.port(), pin.pin()),
+            WatchedPin::Down(pin) => (pin.port(), pin.pin()),
+        };
+        (port_regs(port), index)
+    }
+
+    fn into_disconnected(self) -> Pin<Disconnected> {
+        match self {
+            WatchedPin::Floating(pin) => pin.into_disconnected(),
+            WatchedPin::Up(pin) => pin.into_disconnected(),
+            WatchedPin::Down(pin) => pin.into_disconnected(),
+        }
+    }
+}
+
+struct Watch {
+    pin: WatchedPin,
+    // `None` for pins watched through the PORT event
+    channel: Option<GpioteChannel>,
+    trigger: Trigger,
+    debounce_ms: u8,
+    // ticks left until the pin has settled, 0 when it is not settling
+    countdown: u8,
+    high: bool,
+}
+
+impl Watch {
+    /// Reports each edge directly instead of comparing levels
+    fn is_direct(&self) -> bool {
+        self.channel.is_some()
+            && self.debounce_ms == 0
+            && matches!(self.trigger, Trigger::Rising | Trigger::Falling)
+    }
+
+    fn configure(&mut self) {
+        let polarity = if self.is_direct() {
+            self.trigger.polarity()
+        } else {
+            EventPolarity::Toggle
+        };
+        if let Some(channel) = &mut self.channel {
+            self.pin.watch(channel, polarity);
+            channel.reset_events();
+        }
+    }
+
+    /// Compare the level to the last one and report a change
+    fn settle(&mut self, id: usize, events: &mut Queue<PinEvent, QUEUE_LEN>) {
+        let high = self.pin.is_high();
+        if high != self.high {
+            self.high = high;
+            if let Some(event) = self.trigger.event(id, high) {
+                // a full queue drops the newest events
+                events.push(event).ok();
+            }
+        }
+    }
+
+    /// Handle an edge, returns whether the pin started settling
+    fn edge(&mut self, id: usize, events: &mut Queue<PinEvent, QUEUE_LEN>) -> bool {
+        if self.is_direct() {
+            self.high = self.trigger == Trigger::Rising;
+            if let Some(event) = self.trigger.event(id, self.high) {
+                events.push(event).ok();
+            }
+            false
+        } else if self.debounce_ms == 0 {
+            self.settle(id, events);
+            false
+        } else {
+            // every edge restarts the debounce time
+            self.countdown = self.debounce_ms;
+            true
+        }
+    }
+}
+
+/// Pin-change notifications through GPIOTE channels, debounced with a TIMER
+pub struct PinWatcher<T: Instance> {
+    timer: T,
+    watches: [Option<Watch>; MAX_WATCHES],
+    events: Queue<PinEvent, QUEUE_LEN>,
+}
+
+impl<T: Instance> PinWatcher<T> {
+    /// Take the timer, it runs at 1 MHz with a 1 ms tick while pins settle
+    pub fn new(timer: T) -> Self {
+        let regs = timer.as_timer0();
+        regs.tasks_stop.write(|w| unsafe { w.bits(1) });
+        regs.tasks_clear.write(|w| unsafe { w.bits(1) });
+        regs.mode.write(|w| w.mode().timer());
+        regs.bitmode.write(|w| w.bitmode()._16bit());
+        // 16 MHz / 2^4 = 1 MHz
+        regs.prescaler.write(|w| unsafe { w.bits(4) });
+        regs.cc[0].write(|w| unsafe { w.bits(1000) });
+        regs.shorts.write(|w| w.compare0_clear().enabled());
+        regs.intenclr.write(|w| unsafe { w.bits(0xffff_ffff) });
+        regs.intenset.write(|w| w.compare0().set());
+        Self {
+            timer,
+            watches: Default::default(),
+            events: Queue::new(),
+        }
+    }
+
+    /// Watch `pin` through a GPIOTE channel and enable its interrupt
+    ///
+    /// Returns the id of the watch which is carried by its events, or the
+    /// pin and the channel if all watches are taken. [`Trigger::Pressed`]
+    /// watches start with a debounce time of [`PRESS_DEBOUNCE_MS`], all
+    /// others without one.
+    pub fn watch(
+        &mut self,
+        pin: Pin<Disconnected>,
+        pull: Pull,
+        trigger: Trigger,
+        mut channel: GpioteChannel,
+    ) -> Result<usize, (Pin<Disconnected>, GpioteChannel)> {
+        let Some(id) = self.free_id() else {
+            return Err((pin, channel));
+        };
+        channel.enable_interrupt();
+        self.insert(id, WatchedPin::new(pin, pull), Some(channel), trigger);
+        Ok(id)
+    }
+
+    /// Watch `pin` through the PORT event and enable its interrupt
+    ///
+    /// The pin's SENSE setting follows its level so that each change sets
+    /// its bit in the LATCH register of the port. Returns the id of the
+    /// watch or the pin if all watches are taken.
+    #[cfg(feature = "v2")]
+    pub fn watch_port(
+        &mut self,
+        pin: Pin<Disconnected>,
+        pull: Pull,
+        trigger: Trigger,
+    ) -> Result<usize, Pin<Disconnected>> {
+        let Some(id) = self.free_id() else {
+            return Err(pin);
+        };
+        let pin = WatchedPin::new(pin, pull);
+        let (port, index) = pin.port_pin();
+        port.detectmode.write(|w| w.detectmode().ldetect());
+        sense(port, index, pin.is_high());
+        let gpiote = gpiote();
+        gpiote.intenset.write(|w| w.port().set());
+        self.insert(id, pin, None, trigger);
+        Ok(id)
+    }
+
+    fn free_id(&self) -> Option<usize> {
+        self.watches.iter().position(Option::is_none)
+    }
+
+    fn insert(
+        &mut self,
+        id: usize,
+        pin: WatchedPin,
+        channel: Option<GpioteChannel>,
+        trigger: Trigger,
+    ) {
+        let mut watch = Watch {
+            high: pin.is_high(),
+            pin,
+            channel,
+            trigger,
+            debounce_ms: if trigger == Trigger::Pressed {
+                PRESS_DEBOUNCE_MS
+            } else {
+                0
+            },
+            countdown: 0,
+        };
+        watch.configure();
+        self.watches[id] = Some(watch);
+    }
+
+    /// Set the time a pin has to be quiet before its change is reported
+    ///
+    /// 0 reports every change as soon as it is seen.
+    pub fn set_debounce(&mut self, id: usize, debounce_ms: u8) {
+        if let Some(watch) = self.watch_mut(id) {
+            watch.debounce_ms = debounce_ms;
+            watch.countdown = 0;
+            watch.configure();
+        }
+    }
+
+    /// Last reported level of a watched pin
+    pub fn is_high(&self, id: usize) -> Option<bool> {
+        self.watches.get(id)?.as_ref().map(|watch| watch.high)
+    }
+
+    /// Oldest pin event which has not been read yet
+    pub fn next_event(&mut self) -> Option<PinEvent> {
+        self.events.pop()
+    }
+
+    /// Stop watching a pin and release it with its GPIOTE channel
+    pub fn unwatch(&mut self, id: usize) -> Option<Unwatched> {
+        let watch = self.watches.get_mut(id)?.take()?;
+        let channel = watch.channel.map(|mut channel| {
+            channel.disable_interrupt();
+            channel.disable();
+            channel
+        });
+        #[cfg(feature = "v2")]
+        if channel.is_none() {
+            let (port, index) = watch.pin.port_pin();
+            port.pin_cnf[usize::from(index)].modify(|_, w| w.sense().disabled());
+            port.latch.write(|w| unsafe { w.bits(1 << index) });
+            let port_watches = self.watches.iter().flatten();
+            if port_watches.filter(|watch| watch.channel.is_none()).count() == 0 {
+                gpiote().intenclr.write(|w| w.port().clear());
+            }
+        }
+        Some((watch.pin.into_disconnected(), channel))
+    }
+
+    /// Queue the changes of the watched pins
+    ///
+    /// Call this in the GPIOTE interrupt handler and in the handler of the
+    /// TIMER. It clears the events of the watches' GPIOTE channels, the
+    /// PORT event and the TIMER events.
+    pub fn handle_pin_event(&mut self) {
+        let mut settling = false;
+        for (id, watch) in self.watches.iter_mut().enumerate() {
+            let Some(watch) = watch else { continue };
+            if let Some(channel) = &watch.channel {
+                if channel.is_event_triggered() {
+                    channel.reset_events();
+                    settling |= watch.edge(id, &mut self.events);
+                }
+            }
+        }
+        #[cfg(feature = "v2")]
+        {
+            settling |= self.handle_port_event();
+        }
+
+        let regs = self.timer.as_timer0();
+        if regs.events_compare[0].read().bits() != 0 {
+            regs.events_compare[0].write(|w| unsafe { w.bits(0) });
+            for (id, watch) in self.watches.iter_mut().enumerate() {
+                let Some(watch) = watch else { continue };
+                if watch.countdown > 0 {
+                    watch.countdown -= 1;
+                    if watch.countdown == 0 {
+                        watch.settle(id, &mut self.events);
+                    }
+                }
+            }
+        }
+
+        let running = self
+            .watches
+            .iter()
+            .flatten()
+            .any(|watch| watch.countdown > 0);
+        if !running {
+            regs.tasks_stop.write(|w| unsafe { w.bits(1) });
+            regs.tasks_clear.write(|w| unsafe { w.bits(1) });
+        } else if settling {
+            regs.tasks_start.write(|w| unsafe { w.bits(1) });
+        }
+    }
+
+    #[cfg(feature = "v2")]
+    fn handle_port_event(&mut self) -> bool {
+        let gpiote = gpiote();
+        if gpiote.events_port.read().bits() == 0 {
+            return false;
+        }
+        gpiote.events_port.write(|w| unsafe { w.bits(0) });
+        let mut settling = false;
+        for (id, watch) in self.watches.iter_mut().enumerate() {
+            let Some(watch) = watch else { continue };
+            if watch.channel.is_some() {
+                continue;
+            }
+            let (port, index) = watch.pin.port_pin();
+            if port.latch.read().bits() & 1 << index != 0 {
+                // sense the opposite level before clearing the latch, a
+                // change in between sets it again
+                sense(port, index, watch.pin.is_high());
+                port.latch.write(|w| unsafe { w.bits(1 << index) });
+                settling |= watch.edge(id, &mut self.events);
+            }
+        }
+        settling
+    }
+
+    fn watch_mut(&mut self, id: usize) -> Option<&mut Watch> {
+        self.watches.get_mut(id)?.as_mut()
+    }
+
+    /// Stop the timer and release it with all watched pins
+    pub fn free(mut self) -> (T, [Option<Unwatched>; MAX_WATCHES]) {
+        let regs = self.timer.as_timer0();
+        regs.tasks_stop.write(|w| unsafe { w.bits(1) });
+        regs.intenclr.write(|w| unsafe { w.bits(0xffff_ffff) });
+        let mut pins: [Option<Unwatched>; MAX_WATCHES] = Default::default();
+        for (id, pin) in pins.iter_mut().enumerate() {
+            *pin = self.unwatch(id);
+        }
+        (self.timer, pins)
+    }
+}
+
+#[cfg(feature = "v2")]
+fn gpiote() -> &'static pac::gpiote::RegisterBlock {
+    // only the PORT event and its interrupt, no GPIOTE channel uses them
+    unsafe { &*pac::GPIOTE::ptr() }
+}
+
+#[cfg(feature = "v2")]
+fn port_regs(port: Port) -> &'static pac::p0::RegisterBlock {
+    // only the SENSE field of watched pins, their LATCH bits and DETECTMODE
+    match port {
+        Port::Port0 => unsafe { &*pac::P0::ptr() },
+        Port::Port1 => unsafe { &*pac::P1::ptr() },
+    }
+}
+
+/// Sense the level opposite to the current one
+#[cfg(feature = "v2")]
+fn sense(port: &pac::p0::RegisterBlock, index: u8, high: bool) {
+    port.pin_cnf[usize::from(index)].modify(|_, w| {
+        if high {
+            w.sense().low()
+        } else {
+            w.sense().high()
+        }
+    });
+}
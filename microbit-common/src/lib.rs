@@ -20,8 +20,31 @@ pub use hal::pac::Peripherals;
 
 pub mod adc;
 pub mod board;
+pub mod channels;
+pub mod dht;
 pub mod display;
 pub mod gpio;
+pub mod i2c;
+pub mod infrared;
+pub mod input;
+pub mod microphone;
+#[cfg(feature = "v1")]
+pub mod motor;
+pub mod onewire;
+#[cfg(feature = "v1")]
+pub mod pins;
+pub mod power;
+pub mod pulse;
+pub mod pwm;
+pub mod ranger;
+#[cfg(feature = "v1")]
+pub mod serial;
+pub mod servo;
+pub mod sound;
+pub mod spi;
+pub mod ssd1306;
+pub mod stepper;
+pub mod tm1637;
 
 pub use board::Board;
 
@@ -0,0 +1,294 @@
+//! Detection of tones, pitch and claps in microphone samples
+//!
+//! The helpers work on 10 bit samples as they come from the
+//! [`Sampler`](super::Sampler), evenly spaced at a known sample rate. They
+//! remove the DC offset of the microphone themselves and only use integer
+//! math, so they run on the nRF51 without a floating point unit.
+//!
+//! - [`Goertzel`]: amplitude of a single frequency in a block of samples,
+//!   to detect a whistle or a beep
+//! - [`pitch_hz`]: frequency of the loudest tone in a block of samples,
+//!   estimated from its zero crossings
+//! - [`ClapDetector`]: sudden loud sounds, like claps or knocks, in a
+//!   stream of samples
+
+use super::{Envelope, ONE};
+
+/// Quarter of a sine wave in 64 steps, 16384 is 1
+const SINE: [i32; 65] = [
+    0, 402, 804, 1205, 1606, 2006, 2404, 2801, 3196, 3590, 3981, 4370, 4756, 5139, 5520, 5897,
+    6270, 6639, 7005, 7366, 7723, 8076, 8423, 8765, 9102, 9434, 9760, 10080, 10394, 10702, 11003,
+    11297, 11585, 11866, 12140, 12406, 12665, 12916, 13160, 13395, 13623, 13842, 14053, 14256,
+    14449, 14635, 14811, 14978, 15137, 15286, 15426, 15557, 15679, 15791, 15893, 15986, 16069,
+    16143, 16207, 16261, 16305, 16340, 16364, 16379, 16384,
+];
+
+/// Fixed point values of the Goertzel filter have 14 fractional bits
+const SHIFT: u32 = 14;
+
+/// Sine of `turn` in 1/65536 of a full turn, 16384 is 1
+fn sine(turn: u16) -> i32 {
+    let quadrant = turn >> 14;
+    let mut within = i32::from(turn & 0x3fff);
+    if quadrant & 1 == 1 {
+        within = 0x4000 - within;
+    }
+    let (index, fraction) = ((within >> 8) as usize, within & 0xff);
+    let value = if index == 64 {
+        SINE[64]
+    } else {
+        SINE[index] + (((SINE[index + 1] - SINE[index]) * fraction) >> 8)
+    };
+    if quadrant >= 2 {
+        -value
+    } else {
+        value
+    }
+}
+
+/// Mean of the samples, the DC offset of the microphone
+fn mean(samples: &[i16]) -> i32 {
+    if samples.is_empty() {
+        return 0;
+    }
+    let sum: i32 = samples.iter().map(|&sample| i32::from(sample)).sum();
+    sum / samples.len() as i32
+}
+
+/// Square root rounded down
+fn sqrt(x: u64) -> u64 {
+    if x < 2 {
+        return x;
+    }
+    // Newton's method from above, starting at a power of 2 >= sqrt(x)
+    let mut root = 1 << ((64 - x.leading_zeros()) / 2 + 1);
+    loop {
+        let next = (root + x / root) / 2;
+        if next >= root {
+            return root;
+        }
+        root = next;
+    }
+}
+
+/// Goertzel filter for a single frequency
+///
+/// It is tuned to the frequency once and then measures the amplitude of
+/// that frequency in blocks of samples. The longer the block, the narrower
+/// the band of frequencies it responds to: about `sample_rate_hz / len` Hz
+/// around the tuned one.
+///
+/// ```
+/// # use microbit_common as microbit;
+/// use microbit::microphone::dsp::Goertzel;
+///
+/// // 200 samples of a 1 kHz tone with an amplitude of 200 at 8 kHz
+/// let tone = |frequency_hz: f64| -> Vec<i16> {
+///     (0..200)
+///         .map(|i| {
+///             let phase = 2.0 * std::f64::consts::PI * frequency_hz * i as f64 / 8000.0;
+///             (512.0 + 200.0 * phase.sin()) as i16
+///         })
+///         .collect()
+/// };
+///
+/// let goertzel = Goertzel::new(8000, 1000);
+/// assert!((195..=205).contains(&goertzel.amplitude(&tone(1000.0))));
+/// assert!(goertzel.amplitude(&tone(1500.0)) < 10);
+/// assert!(goertzel.amplitude(&[512; 200]) < 2);
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Goertzel {
+    // 2 cos(2π frequency / sample rate), fixed point
+    coefficient: i32,
+    // cos and sin of the same angle
+    cosine: i32,
+    sine: i32,
+}
+
+impl Goertzel {
+    /// Tune to `frequency_hz` in samples taken at `sample_rate_hz`
+    ///
+    /// Frequencies above half the sample rate can not be told apart from
+    /// lower ones.
+    pub fn new(sample_rate_hz: u32, frequency_hz: u32) -> Self {
+        let sample_rate_hz = u64::from(sample_rate_hz.max(1));
+        let turn = (u64::from(frequency_hz) << 16) / sample_rate_hz;
+        let turn = turn as u16;
+        let cosine = sine(turn.wrapping_add(0x4000));
+        Self {
+            coefficient: 2 * cosine,
+            cosine,
+            sine: sine(turn),
+        }
+    }
+
+    /// Amplitude of the tuned frequency in `samples`
+    ///
+    /// A sine wave at the tuned frequency with an amplitude of `a` gives
+    /// about `a`, if the block holds a whole number of its periods.
+    pub fn amplitude(&self, samples: &[i16]) -> u32 {
+        let offset = mean(samples);
+        let (mut s1, mut s2) = (0i64, 0i64);
+        for &sample in samples {
+            let s0 = i64::from(i32::from(sample) - offset)
+                + ((i64::from(self.coefficient) * s1) >> SHIFT)
+                - s2;
+            s2 = s1;
+            s1 = s0;
+        }
+        // real and imaginary part of the frequency bin
+        let real = s1 - ((i64::from(self.cosine) * s2) >> SHIFT);
+        let imaginary = (i64::from(self.sine) * s2) >> SHIFT;
+        let magnitude = sqrt((real * real + imaginary * imaginary) as u64);
+        (2 * magnitude / samples.len().max(1) as u64) as u32
+    }
+}
+
+/// Frequency of the loudest tone in `samples`, from its zero crossings
+///
+/// The samples only count as crossing zero, after the DC offset is
+/// removed, when they swing from below `-hysteresis` to above
+/// `hysteresis`, which keeps noise from adding crossings. Returns `None`
+/// if there are less than two crossings, because the block is too short or
+/// the sound too quiet.
+///
+/// Harmonics and noise make the estimate unreliable, it is meant for
+/// whistles and hummed notes.
+///
+/// ```
+/// # use microbit_common as microbit;
+/// use microbit::microphone::dsp::pitch_hz;
+///
+/// let tone: Vec<i16> = (0..400)
+///     .map(|i| {
+///         let phase = 2.0 * std::f64::consts::PI * 440.0 * i as f64 / 8000.0;
+///         (512.0 + 100.0 * phase.sin()) as i16
+///     })
+///     .collect();
+/// let pitch = pitch_hz(&tone, 8000, 20).unwrap();
+/// assert!((435..=445).contains(&pitch));
+///
+/// // too quiet for the hysteresis
+/// assert_eq!(pitch_hz(&tone, 8000, 200), None);
+/// assert_eq!(pitch_hz(&[512; 400], 8000, 20), None);
+/// ```
+pub fn pitch_hz(samples: &[i16], sample_rate_hz: u32, hysteresis: u16) -> Option<u32> {
+    let offset = mean(samples);
+    let hysteresis = i32::from(hysteresis);
+    let mut below = false;
+    let mut first = None;
+    let mut last = 0;
+    let mut crossings = 0;
+    for (index, &sample) in samples.iter().enumerate() {
+        let sample = i32::from(sample) - offset;
+        if sample < -hysteresis {
+            below = true;
+        } else if below && sample > hysteresis {
+            below = false;
+            first.get_or_insert(index);
+            last = index;
+            crossings += 1;
+        }
+    }
+    let first = first?;
+    if crossings < 2 {
+        return None;
+    }
+    let periods = crossings - 1;
+    let span = (last - first) as u32;
+    Some((periods * sample_rate_hz + span / 2) / span)
+}
+
+/// Default amplitude a clap has to reach
+pub const DEFAULT_CLAP_THRESHOLD: u16 = 150;
+
+/// A clap is at least this many times louder than the background
+const CLAP_RATIO: i32 = 4;
+
+/// The background level follows the signal with a time constant of
+/// 2^BACKGROUND_SHIFT samples
+const BACKGROUND_SHIFT: u32 = 10;
+
+/// Detector of claps, knocks and other short, loud sounds
+///
+/// A clap is a sample which reaches the threshold and is several times
+/// louder than the background level, so a constant noise does not count.
+/// After a clap the detector waits for 100 ms and until the sound has
+/// faded to half the threshold before it detects the next one.
+///
+/// ```
+/// # use microbit_common as microbit;
+/// use microbit::microphone::dsp::ClapDetector;
+///
+/// let mut detector = ClapDetector::new(8000);
+/// // background noise
+/// let noise = |i: usize| 512 + [0, 12, -7, 20, -15, 5][i % 6];
+/// assert!((0..8000).all(|i| !detector.update(noise(i))));
+///
+/// // two claps, decaying bursts of 20 ms, a quarter of a second apart
+/// let clap = |i: usize| noise(i) + (if i % 2 == 0 { 400 } else { -400 }) * 20 / (20 + i as i16);
+/// let claps = (0..4000)
+///     .filter(|&i| detector.update(if i % 2000 < 160 { clap(i % 2000) } else { noise(i) }))
+///     .count();
+/// assert_eq!(claps, 2);
+///
+/// // a loud tone only counts once, when it starts
+/// let tone = |i: usize| 512 + if i % 8 < 4 { 300 } else { -300 };
+/// assert_eq!((0..8000).filter(|&i| detector.update(tone(i))).count(), 1);
+/// ```
+#[derive(Clone, Debug)]
+pub struct ClapDetector {
+    envelope: Envelope,
+    // mean amplitude, fixed point
+    background: i32,
+    threshold: u16,
+    // samples of the pause after a clap
+    holdoff: u32,
+    remaining: u32,
+    armed: bool,
+}
+
+impl ClapDetector {
+    /// Detect claps in samples taken at `sample_rate_hz`
+    pub fn new(sample_rate_hz: u32) -> Self {
+        Self {
+            envelope: Envelope::new(),
+            background: 0,
+            threshold: DEFAULT_CLAP_THRESHOLD,
+            holdoff: sample_rate_hz / 10,
+            remaining: 0,
+            armed: true,
+        }
+    }
+
+    /// Amplitude from the DC offset a clap has to reach, in samples
+    pub fn threshold(&self) -> u16 {
+        self.threshold
+    }
+
+    /// Set the amplitude a clap has to reach, lower is more sensitive
+    pub fn set_threshold(&mut self, threshold: u16) {
+        self.threshold = threshold;
+    }
+
+    /// Add a sample, returns whether it starts a clap
+    pub fn update(&mut self, sample: i16) -> bool {
+        let amplitude = self.envelope.update(sample.into());
+        let threshold = i32::from(self.threshold) * ONE;
+        let clap =
+            self.armed && amplitude >= threshold && amplitude >= CLAP_RATIO * self.background;
+
+        self.background += (amplitude - self.background) >> BACKGROUND_SHIFT;
+
+        if clap {
+            self.armed = false;
+            self.remaining = self.holdoff;
+        } else if self.remaining > 0 {
+            self.remaining -= 1;
+        } else if !self.armed && self.envelope.peak() < threshold / 2 {
+            self.armed = true;
+        }
+        clap
+    }
+}
@@ -0,0 +1,364 @@
+//! Sound level from the microphone
+//!
+//! [`Microphone`] samples the microphone with the ADC and feeds a
+//! [`LevelMeter`], which removes the DC offset of the microphone amplifier,
+//! follows the envelope of the signal and reports the sound level on a scale
+//! from 0 to 255 and in dB. Loud and quiet sounds raise a [`SoundEvent`]
+//! once each: a loud sound only counts again after the level has dropped to
+//! the quiet threshold.
+//!
+//! The ADC has to be configured for 10 bit samples, see
+//! [`AdcConfig::default_10bit`](crate::adc::Default::default_10bit).
+#![cfg_attr(
+    feature = "v2",
+    doc = "On the nRF52 board the microphone amplifier is powered through `mic_run` while the `Microphone` exists."
+)]
+//!
+//! The meter works on any stream of samples, the time constants are counted
+//! in samples: sample at roughly 1 to 10 kHz for speech and claps.
+//!
+//! Reading the ADC in a loop gives irregular sample timing. A [`Sampler`]
+//! samples at a fixed rate instead: a TIMER triggers each conversion
+//! through PPI and the ADC interrupt moves the sample into a
+//! [`SampleRing`]. Call [`Sampler::handle_adc_event()`] in the interrupt
+//! handler of the ADC. The [`dsp`] module detects tones, pitch and claps in
+//! the samples.
+//!
+//! A [`SoundTrigger`] waits for sounds with the low power comparator
+//! LPCOMP instead, which draws a few µA while the rest of the chip sleeps.
+//! It raises [`SoundEvent`]s when the microphone signal rises above a
+//! reference, set in eighths of the supply, and falls below it minus a
+//! hysteresis again: call [`SoundTrigger::handle_lpcomp_event()`] in the
+//! interrupt handler of LPCOMP. [`SoundTrigger::system_off()`] switches the
+//! chip off until a sound wakes it up, which restarts the program with a
+//! [`ResetReason`](crate::power::ResetReason) of
+//! [`ResetReason::SoundWake`](crate::power::ResetReason::SoundWake).
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use microbit_common as microbit;
+//! use microbit::{
+//!     adc::{Adc, AdcConfig, Default},
+//!     microphone::{Microphone, SoundEvent},
+//!     Board,
+//! };
+//!
+//! let board = Board::take().unwrap();
+//! let adc = Adc::new(board.ADC, AdcConfig::default_10bit());
+//! let mut microphone = Microphone::new(adc, board.microphone_pins);
+//! microphone.set_thresholds(160, 60);
+//!
+//! loop {
+//!     match microphone.sample() {
+//!         Some(SoundEvent::Loud) => { /* clap */ }
+//!         Some(SoundEvent::Quiet) => { /* silence again */ }
+//!         None => {}
+//!     }
+//! }
+//! ```
+//!
+//! Fixed rate sampling:
+//!
+//! ```no_run
+//! # use microbit_common as microbit;
+//! use microbit::{
+//!     channels::PpiChannels,
+//!     microphone::{dsp::ClapDetector, SampleRing, Sampler},
+//!     Board,
+//! };
+//!
+//! static mut RING: SampleRing = SampleRing::new();
+//!
+//! let board = Board::take().unwrap();
+//! let ppi = PpiChannels::new(board.PPI);
+//! // the ring is only handed out once
+//! let ring = unsafe { &mut *core::ptr::addr_of_mut!(RING) };
+//! let mut sampler = Sampler::new(
+//!     board.TIMER1,
+//!     board.ADC,
+//!     board.microphone_pins,
+//!     ppi.ppi0,
+//!     ring,
+//!     8000,
+//! );
+//! let mut claps = ClapDetector::new(sampler.sample_rate_hz());
+//! sampler.start();
+//! // call `sampler.handle_adc_event()` in the ADC interrupt handler
+//!
+//! let mut block = [0; 64];
+//! let count = sampler.read(&mut block);
+//! for &sample in &block[..count] {
+//!     if claps.update(sample) { /* clap */ }
+//! }
+//! ```
+//!
+//! Sleeping until a sound:
+//!
+//! ```no_run
+//! # use microbit_common as microbit;
+//! use microbit::{
+//!     microphone::SoundTrigger,
+//!     power::ResetReason,
+//!     Board,
+//! };
+//!
+//! let board = Board::take().unwrap();
+//! if ResetReason::take(&board.POWER) == ResetReason::SoundWake {
+//!     // count the noise, then go back to sleep
+//! }
+//! let mut trigger = SoundTrigger::new(board.LPCOMP, board.microphone_pins);
+//! trigger.set_threshold(6);
+//! trigger.system_off(&board.POWER);
+//! ```
+use embedded_hal::adc::OneShot;
+
+use crate::{adc::Adc, gpio::MicrophonePins};
+
+pub mod dsp;
+mod sampler;
+mod trigger;
+
+pub use sampler::{SampleRing, Sampler, MAX_SAMPLE_RATE_HZ, MIN_SAMPLE_RATE_HZ, RING_LEN};
+pub use trigger::{SoundTrigger, DEFAULT_HYSTERESIS, DEFAULT_THRESHOLD};
+
+#[cfg(feature = "v2")]
+use embedded_hal::digital::v2::OutputPin;
+
+/// Amplitude of a full scale 10 bit sine wave
+pub const FULL_SCALE_AMPLITUDE: u32 = 512;
+
+/// Sound pressure level in dB of a full scale signal, an approximation for
+/// the microphone and amplifier of the board
+pub const FULL_SCALE_DB: u8 = 100;
+
+/// Level in dB shown as 0, quieter sounds are shown as 0 too
+pub const MIN_DB: u8 = 35;
+
+/// Level in dB shown as 255, louder sounds are shown as 255 too
+pub const MAX_DB: u8 = 100;
+
+/// Default level at which a [`SoundEvent::Loud`] is raised
+pub const DEFAULT_LOUD_THRESHOLD: u8 = 128;
+
+/// Default level at which a [`SoundEvent::Quiet`] is raised
+pub const DEFAULT_QUIET_THRESHOLD: u8 = 64;
+
+/// The DC offset follows the signal with a time constant of 2^DC_SHIFT
+/// samples
+const DC_SHIFT: u32 = 10;
+
+/// The envelope decays with a time constant of 2^DECAY_SHIFT samples
+const DECAY_SHIFT: u32 = 7;
+
+/// Fixed point values have 8 fractional bits
+pub(crate) const ONE: i32 = 256;
+
+/// Envelope of a stream of samples around their DC offset
+///
+/// Shared by the [`LevelMeter`] and the [`ClapDetector`](dsp::ClapDetector).
+#[derive(Clone, Debug)]
+pub(crate) struct Envelope {
+    // DC offset, fixed point, None before the first sample
+    dc: Option<i32>,
+    // peak amplitude with decay, fixed point
+    peak: i32,
+}
+
+impl Envelope {
+    pub(crate) const fn new() -> Self {
+        Self { dc: None, peak: 0 }
+    }
+
+    /// Add a sample, returns its distance from the DC offset in fixed
+    /// point
+    pub(crate) fn update(&mut self, sample: i32) -> i32 {
+        let sample = sample * ONE;
+        let dc = self.dc.get_or_insert(sample);
+        *dc += (sample - *dc) >> DC_SHIFT;
+        let amplitude = (sample - *dc).abs();
+        if amplitude > self.peak {
+            self.peak = amplitude;
+        } else {
+            self.peak -= self.peak >> DECAY_SHIFT;
+        }
+        amplitude
+    }
+
+    /// The envelope in fixed point
+    pub(crate) fn peak(&self) -> i32 {
+        self.peak
+    }
+}
+
+/// Change of the sound level
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SoundEvent {
+    /// The level reached the loud threshold
+    Loud,
+    /// The level dropped to the quiet threshold after a loud sound
+    Quiet,
+}
+
+/// Sound level of a stream of 10 bit samples
+///
+/// ```
+/// # use microbit_common as microbit;
+/// use microbit::microphone::{LevelMeter, SoundEvent};
+///
+/// let mut meter = LevelMeter::new();
+/// // the DC offset is learned from the first sample on
+/// for _ in 0..100 {
+///     assert_eq!(meter.update(512), None);
+/// }
+/// assert_eq!(meter.level(), 0);
+///
+/// // a square wave at half of full scale
+/// let mut events = (0..200).filter_map(|i| meter.update(if i % 2 == 0 { 768 } else { 256 }));
+/// assert_eq!(events.next(), Some(SoundEvent::Loud));
+/// assert_eq!(events.next(), None);
+/// assert_eq!(meter.level_db(), 94);
+/// assert_eq!(meter.level(), 231);
+///
+/// // silence again
+/// let mut events = (0..2000).filter_map(|_| meter.update(512));
+/// assert_eq!(events.next(), Some(SoundEvent::Quiet));
+/// assert_eq!(events.next(), None);
+/// assert_eq!(meter.level(), 0);
+/// ```
+#[derive(Clone, Debug)]
+pub struct LevelMeter {
+    envelope: Envelope,
+    loud_threshold: u8,
+    quiet_threshold: u8,
+    loud: bool,
+}
+
+impl Default for LevelMeter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LevelMeter {
+    /// Start without a sound and with the default thresholds
+    pub const fn new() -> Self {
+        Self {
+            envelope: Envelope::new(),
+            loud_threshold: DEFAULT_LOUD_THRESHOLD,
+            quiet_threshold: DEFAULT_QUIET_THRESHOLD,
+            loud: false,
+        }
+    }
+
+    /// Set the levels from 0 to 255 of the events
+    ///
+    /// The quiet threshold is lowered to the loud one if it is higher. The
+    /// distance between them keeps a level around one threshold from
+    /// raising events over and over.
+    pub fn set_thresholds(&mut self, loud: u8, quiet: u8) {
+        self.loud_threshold = loud;
+        self.quiet_threshold = quiet.min(loud);
+    }
+
+    /// Add a sample, returns an event if it changes the level across a
+    /// threshold
+    pub fn update(&mut self, sample: u16) -> Option<SoundEvent> {
+        self.envelope.update(sample.into());
+        let level = self.level();
+        if !self.loud && level >= self.loud_threshold {
+            self.loud = true;
+            Some(SoundEvent::Loud)
+        } else if self.loud && level <= self.quiet_threshold {
+            self.loud = false;
+            Some(SoundEvent::Quiet)
+        } else {
+            None
+        }
+    }
+
+    /// Sound pressure level in dB, 0 for silence
+    pub fn level_db(&self) -> u8 {
+        let amplitude = (self.envelope.peak() / ONE) as u32;
+        if amplitude == 0 {
+            return 0;
+        }
+        // 20 log10(x) = 20 log10(2) log2(x), 20 log10(2) = 1541 / 256
+        let relative = (log2_fixed(amplitude) - log2_fixed(FULL_SCALE_AMPLITUDE)) * 1541 / ONE;
+        let db = i32::from(FULL_SCALE_DB) * ONE + relative;
+        ((db + ONE / 2) / ONE).clamp(0, 255) as u8
+    }
+
+    /// Sound level from 0 at [`MIN_DB`] to 255 at [`MAX_DB`]
+    pub fn level(&self) -> u8 {
+        let db = self.level_db().clamp(MIN_DB, MAX_DB);
+        (u32::from(db - MIN_DB) * 255 / u32::from(MAX_DB - MIN_DB)) as u8
+    }
+}
+
+/// log2 of `x` > 0 with 8 fractional bits, linear between powers of 2
+fn log2_fixed(x: u32) -> i32 {
+    let exponent = 31 - x.leading_zeros() as i32;
+    let mantissa = if exponent >= 8 {
+        x >> (exponent - 8)
+    } else {
+        x << (8 - exponent)
+    };
+    exponent * ONE + (mantissa as i32 & (ONE - 1))
+}
+
+/// The microphone sampled by the ADC
+pub struct Microphone {
+    adc: Adc,
+    pins: MicrophonePins,
+    meter: LevelMeter,
+}
+
+impl Microphone {
+    /// Take the ADC, configured for 10 bit samples, and the microphone pins
+    pub fn new(adc: Adc, pins: MicrophonePins) -> Self {
+        #[cfg(feature = "v2")]
+        let pins = {
+            let mut pins = pins;
+            pins.mic_run.set_high().ok();
+            pins
+        };
+        Self {
+            adc,
+            pins,
+            meter: LevelMeter::new(),
+        }
+    }
+
+    /// Take a sample and add it to the level
+    ///
+    /// Returns an event if the sample changes the level across a threshold.
+    pub fn sample(&mut self) -> Option<SoundEvent> {
+        let sample = nb::block!(self.adc.read(&mut self.pins.mic_in)).ok()?;
+        self.meter.update(sample.max(0) as u16)
+    }
+
+    /// Sound level from 0 to 255, see [`LevelMeter::level`]
+    pub fn level(&self) -> u8 {
+        self.meter.level()
+    }
+
+    /// Sound pressure level in dB, 0 for silence
+    pub fn level_db(&self) -> u8 {
+        self.meter.level_db()
+    }
+
+    /// Set the levels of the events, see [`LevelMeter::set_thresholds`]
+    pub fn set_thresholds(&mut self, loud: u8, quiet: u8) {
+        self.meter.set_thresholds(loud, quiet);
+    }
+
+    /// Release the ADC and the pins
+    pub fn free(self) -> (Adc, MicrophonePins) {
+        #[cfg_attr(feature = "v1", allow(unused_mut))]
+        let mut pins = self.pins;
+        #[cfg(feature = "v2")]
+        pins.mic_run.set_low().ok();
+        (self.adc, pins)
+    }
+}
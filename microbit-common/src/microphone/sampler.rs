@@ -0,0 +1,344 @@
+use crate::{channels::PpiChannel, gpio::MicrophonePins, hal::timer::Instance};
+
+#[cfg(feature = "v1")]
+use crate::pac::ADC;
+
+#[cfg(feature = "v2")]
+use crate::pac::SAADC as ADC;
+
+#[cfg(feature = "v2")]
+use core::sync::atomic::{compiler_fence, Ordering};
+
+#[cfg(feature = "v2")]
+use embedded_hal::digital::v2::OutputPin;
+
+/// Samples held by a [`SampleRing`]
+pub const RING_LEN: usize = 512;
+
+/// Lowest sample rate, lower ones are raised to it
+pub const MIN_SAMPLE_RATE_HZ: u32 = 100;
+
+/// Highest sample rate, higher ones are lowered to it
+///
+/// A 10 bit conversion of the nRF51 ADC takes 68 µs.
+pub const MAX_SAMPLE_RATE_HZ: u32 = 10_000;
+
+/// TIMER clock
+const CLOCK_HZ: u32 = 1_000_000;
+
+/// Ring buffer of samples, filled by a [`Sampler`]
+///
+/// When it is full, new samples overwrite the oldest ones and are counted
+/// as overruns.
+///
+/// ```
+/// # use microbit_common as microbit;
+/// use microbit::microphone::{SampleRing, RING_LEN};
+///
+/// let mut ring = SampleRing::new();
+/// for sample in 0..RING_LEN as i16 + 2 {
+///     ring.push(sample);
+/// }
+/// assert_eq!(ring.len(), RING_LEN);
+/// assert_eq!(ring.overruns(), 2);
+/// assert_eq!(ring.pop(), Some(2));
+///
+/// let mut block = [0; 4];
+/// assert_eq!(ring.read(&mut block), 4);
+/// assert_eq!(block, [3, 4, 5, 6]);
+/// assert_eq!(ring.len(), RING_LEN - 5);
+///
+/// ring.clear();
+/// assert!(ring.is_empty());
+/// assert_eq!(ring.pop(), None);
+/// ```
+pub struct SampleRing {
+    samples: [i16; RING_LEN],
+    // index of the oldest sample
+    start: usize,
+    len: usize,
+    overruns: u32,
+}
+
+impl SampleRing {
+    /// An empty ring, for use in a `static`
+    pub const fn new() -> Self {
+        Self {
+            samples: [0; RING_LEN],
+            start: 0,
+            len: 0,
+            overruns: 0,
+        }
+    }
+
+    /// Number of samples that have not been read
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether all samples have been read
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of samples that were overwritten before they were read
+    pub fn overruns(&self) -> u32 {
+        self.overruns
+    }
+
+    /// Drop all samples and reset the overruns
+    pub fn clear(&mut self) {
+        self.start = 0;
+        self.len = 0;
+        self.overruns = 0;
+    }
+
+    /// Add a sample, overwriting the oldest one if the ring is full
+    pub fn push(&mut self, sample: i16) {
+        *self.slot() = sample;
+        self.commit();
+    }
+
+    /// Take the oldest sample
+    pub fn pop(&mut self) -> Option<i16> {
+        if self.len == 0 {
+            return None;
+        }
+        let sample = self.samples[self.start];
+        self.start = (self.start + 1) % RING_LEN;
+        self.len -= 1;
+        Some(sample)
+    }
+
+    /// Move the oldest samples into `block`, returns how many there were
+    pub fn read(&mut self, block: &mut [i16]) -> usize {
+        let mut count = 0;
+        for value in block.iter_mut() {
+            match self.pop() {
+                Some(sample) => *value = sample,
+                None => break,
+            }
+            count += 1;
+        }
+        count
+    }
+
+    /// Where the next sample goes
+    fn slot(&mut self) -> &mut i16 {
+        &mut self.samples[(self.start + self.len) % RING_LEN]
+    }
+
+    /// Add the sample written to the slot
+    fn commit(&mut self) {
+        if self.len == RING_LEN {
+            self.start = (self.start + 1) % RING_LEN;
+            self.overruns = self.overruns.wrapping_add(1);
+        } else {
+            self.len += 1;
+        }
+    }
+}
+
+impl Default for SampleRing {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The microphone sampled at a fixed rate
+///
+/// See the [module documentation](crate::microphone) for how it works.
+pub struct Sampler<T: Instance> {
+    timer: T,
+    adc: ADC,
+    pins: MicrophonePins,
+    ppi: PpiChannel,
+    ring: &'static mut SampleRing,
+    period: u32,
+}
+
+impl<T: Instance> Sampler<T> {
+    /// Take a TIMER, the ADC, the microphone pins, a PPI channel and the
+    /// ring buffer, and prepare to sample at `sample_rate_hz`
+    ///
+    /// The rate is rounded to a whole number of µs between two samples and
+    /// clamped from [`MIN_SAMPLE_RATE_HZ`] to [`MAX_SAMPLE_RATE_HZ`].
+    pub fn new(
+        timer: T,
+        adc: ADC,
+        pins: MicrophonePins,
+        mut ppi: PpiChannel,
+        ring: &'static mut SampleRing,
+        sample_rate_hz: u32,
+    ) -> Self {
+        let sample_rate_hz = sample_rate_hz.clamp(MIN_SAMPLE_RATE_HZ, MAX_SAMPLE_RATE_HZ);
+        let period = (CLOCK_HZ + sample_rate_hz / 2) / sample_rate_hz;
+
+        let regs = timer.as_timer0();
+        regs.tasks_stop.write(|w| unsafe { w.bits(1) });
+        regs.tasks_clear.write(|w| unsafe { w.bits(1) });
+        regs.mode.write(|w| w.mode().timer());
+        regs.bitmode.write(|w| w.bitmode()._16bit());
+        // 1 MHz, the longest period of 10000 µs fits the 16 bit TIMER1 and
+        // TIMER2 of the nRF51
+        regs.prescaler.write(|w| unsafe { w.bits(4) });
+        regs.cc[0].write(|w| unsafe { w.bits(period) });
+        regs.shorts.write(|w| w.compare0_clear().enabled());
+        regs.intenclr.write(|w| unsafe { w.bits(0xffff_ffff) });
+        regs.events_compare[0].write(|w| unsafe { w.bits(0) });
+
+        // 0 V reads as 0 and VDD as 1023, like the `Microphone`
+        #[cfg(feature = "v1")]
+        {
+            while adc.busy.read().busy().is_busy() {}
+            adc.config.write(|w| {
+                w.res()._10bit();
+                w.inpsel().analog_input_one_third_prescaling();
+                w.refsel().supply_one_third_prescaling();
+                w.psel().analog_input4()
+            });
+            adc.enable.write(|w| w.enable().enabled());
+            ppi.set_task_endpoint(&adc.tasks_start);
+        }
+        #[cfg(feature = "v2")]
+        let pins = {
+            adc.enable.write(|w| w.enable().enabled());
+            adc.resolution.write(|w| w.val()._10bit());
+            adc.oversample.write(|w| w.oversample().bypass());
+            adc.samplerate.write(|w| w.mode().task());
+            adc.ch[0].config.write(|w| {
+                w.refsel().vdd1_4();
+                w.gain().gain1_4();
+                w.tacq()._10us();
+                w.mode().se();
+                w.resp().bypass();
+                w.resn().bypass();
+                w.burst().disabled()
+            });
+            adc.ch[0].pselp.write(|w| w.pselp().analog_input3());
+            adc.ch[0].pseln.write(|w| w.pseln().nc());
+            adc.result.maxcnt.write(|w| unsafe { w.maxcnt().bits(1) });
+            ppi.set_task_endpoint(&adc.tasks_sample);
+
+            let mut pins = pins;
+            pins.mic_run.set_high().ok();
+            pins
+        };
+        adc.intenclr.write(|w| unsafe { w.bits(0xffff_ffff) });
+        adc.events_end.write(|w| unsafe { w.bits(0) });
+        ppi.set_event_endpoint(&regs.events_compare[0]);
+
+        Self {
+            timer,
+            adc,
+            pins,
+            ppi,
+            ring,
+            period,
+        }
+    }
+
+    /// Sample rate that is used, rounded to Hz
+    pub fn sample_rate_hz(&self) -> u32 {
+        (CLOCK_HZ + self.period / 2) / self.period
+    }
+
+    /// Start sampling, the ring keeps the samples it holds
+    pub fn start(&mut self) {
+        self.stop();
+        #[cfg(feature = "v2")]
+        self.arm();
+        self.adc.intenset.write(|w| w.end().set());
+        self.ppi.enable();
+        let regs = self.timer.as_timer0();
+        regs.tasks_clear.write(|w| unsafe { w.bits(1) });
+        regs.tasks_start.write(|w| unsafe { w.bits(1) });
+    }
+
+    /// Stop sampling
+    pub fn stop(&mut self) {
+        self.timer
+            .as_timer0()
+            .tasks_stop
+            .write(|w| unsafe { w.bits(1) });
+        self.ppi.disable();
+        self.adc.intenclr.write(|w| w.end().clear());
+        #[cfg(feature = "v1")]
+        {
+            self.adc.tasks_stop.write(|w| unsafe { w.bits(1) });
+            while self.adc.busy.read().busy().is_busy() {}
+        }
+        #[cfg(feature = "v2")]
+        {
+            self.adc.events_stopped.write(|w| unsafe { w.bits(0) });
+            self.adc.tasks_stop.write(|w| unsafe { w.bits(1) });
+            while self.adc.events_stopped.read().bits() == 0 {}
+        }
+        self.adc.events_end.write(|w| unsafe { w.bits(0) });
+    }
+
+    /// Point the SAADC at the next slot of the ring and wait until it is
+    /// ready for the next sample
+    #[cfg(feature = "v2")]
+    fn arm(&mut self) {
+        let slot = self.ring.slot() as *mut i16;
+        self.adc
+            .result
+            .ptr
+            .write(|w| unsafe { w.ptr().bits(slot as u32) });
+        compiler_fence(Ordering::SeqCst);
+        self.adc.events_started.write(|w| unsafe { w.bits(0) });
+        self.adc.tasks_start.write(|w| unsafe { w.bits(1) });
+        while self.adc.events_started.read().bits() == 0 {}
+    }
+
+    /// Move the converted sample into the ring
+    ///
+    /// Call this in the interrupt handler of the ADC. It clears the END
+    /// event.
+    pub fn handle_adc_event(&mut self) {
+        if self.adc.events_end.read().bits() == 0 {
+            return;
+        }
+        self.adc.events_end.write(|w| unsafe { w.bits(0) });
+        #[cfg(feature = "v1")]
+        self.ring
+            .push(self.adc.result.read().result().bits() as i16);
+        #[cfg(feature = "v2")]
+        {
+            // the SAADC has written the sample into the slot
+            compiler_fence(Ordering::SeqCst);
+            self.ring.commit();
+            self.arm();
+        }
+    }
+
+    /// Number of samples waiting to be read
+    pub fn available(&self) -> usize {
+        self.ring.len()
+    }
+
+    /// Number of samples that were overwritten before they were read
+    pub fn overruns(&self) -> u32 {
+        self.ring.overruns()
+    }
+
+    /// Move the oldest samples into `block`, returns how many there were
+    pub fn read(&mut self, block: &mut [i16]) -> usize {
+        self.ring.read(block)
+    }
+
+    /// Stop sampling and release the TIMER, the ADC, the microphone pins
+    /// and the PPI channel
+    pub fn free(mut self) -> (T, ADC, MicrophonePins, PpiChannel) {
+        self.stop();
+        self.timer
+            .as_timer0()
+            .shorts
+            .write(|w| unsafe { w.bits(0) });
+        self.adc.enable.write(|w| w.enable().disabled());
+        #[cfg(feature = "v2")]
+        self.pins.mic_run.set_low().ok();
+        (self.timer, self.adc, self.pins, self.ppi)
+    }
+}
@@ -0,0 +1,222 @@
+use crate::{
+    gpio::MicrophonePins,
+    pac::{LPCOMP, POWER},
+};
+
+use super::SoundEvent;
+
+#[cfg(feature = "v2")]
+use embedded_hal::digital::v2::OutputPin;
+
+/// Default reference of a [`SoundTrigger`] in eighths of the supply
+pub const DEFAULT_THRESHOLD: u8 = 6;
+
+/// Default hysteresis of a [`SoundTrigger`] in eighths of the supply
+pub const DEFAULT_HYSTERESIS: u8 = 1;
+
+/// Level of the microphone amplifier without a sound, in eighths of the
+/// supply
+const RESTING_LEVEL: u8 = 4;
+
+/// The microphone watched by the low power comparator
+///
+/// See the [module documentation](crate::microphone) for how it works.
+pub struct SoundTrigger {
+    lpcomp: LPCOMP,
+    pins: MicrophonePins,
+    threshold: u8,
+    hysteresis: u8,
+    loud: bool,
+}
+
+impl SoundTrigger {
+    /// Take the LPCOMP peripheral and the microphone pins
+    ///
+    /// The comparator is configured with [`DEFAULT_THRESHOLD`] and
+    /// [`DEFAULT_HYSTERESIS`] but not started.
+    pub fn new(lpcomp: LPCOMP, pins: MicrophonePins) -> Self {
+        #[cfg(feature = "v1")]
+        lpcomp.psel.write(|w| w.psel().analog_input4());
+        #[cfg(feature = "v2")]
+        let pins = {
+            lpcomp.psel.write(|w| w.psel().analog_input3());
+            // 50 mV against chatter while the signal is at the reference
+            lpcomp.hyst.write(|w| w.hyst().enabled());
+            let mut pins = pins;
+            pins.mic_run.set_high().ok();
+            pins
+        };
+        lpcomp.intenclr.write(|w| unsafe { w.bits(0xffff_ffff) });
+        Self {
+            lpcomp,
+            pins,
+            threshold: DEFAULT_THRESHOLD,
+            hysteresis: DEFAULT_HYSTERESIS,
+            loud: false,
+        }
+    }
+
+    /// Reference a sound has to rise above, in eighths of the supply
+    pub fn threshold(&self) -> u8 {
+        self.threshold
+    }
+
+    /// Set the reference a sound has to rise above, from 5 to 7 eighths of
+    /// the supply
+    ///
+    /// The microphone amplifier rests at about half the supply, so lower
+    /// references would always be exceeded.
+    pub fn set_threshold(&mut self, eighths: u8) {
+        self.threshold = eighths.clamp(RESTING_LEVEL + 1, 7);
+        self.reconfigure();
+    }
+
+    /// Eighths of the supply below the threshold a sound has to fall to
+    /// before it counts as quiet
+    pub fn hysteresis(&self) -> u8 {
+        self.hysteresis
+    }
+
+    /// Set how far below the threshold a sound has to fall before it
+    /// counts as quiet, in eighths of the supply
+    ///
+    /// The reference of a quiet sound stays above the resting level of the
+    /// amplifier at half the supply, where the noise of the microphone
+    /// would cross it over and over: it is at least 5 eighths, so a
+    /// threshold of 5 has no hysteresis.
+    pub fn set_hysteresis(&mut self, eighths: u8) {
+        self.hysteresis = eighths;
+        self.reconfigure();
+    }
+
+    /// Whether the last event was [`SoundEvent::Loud`]
+    pub fn is_loud(&self) -> bool {
+        self.loud
+    }
+
+    /// Reference of the current state, 5 to 7 eighths
+    fn reference(&self) -> u8 {
+        if self.loud {
+            self.threshold
+                .saturating_sub(self.hysteresis)
+                .max(RESTING_LEVEL + 1)
+        } else {
+            self.threshold
+        }
+    }
+
+    /// Start comparing and raise LPCOMP interrupts on events
+    pub fn start(&mut self) {
+        self.lpcomp
+            .intenclr
+            .write(|w| unsafe { w.bits(0xffff_ffff) });
+        self.restart();
+        self.lpcomp
+            .intenset
+            .write(|w| w.ready().set().up().set().down().set());
+    }
+
+    /// Stop comparing
+    pub fn stop(&mut self) {
+        self.lpcomp
+            .intenclr
+            .write(|w| unsafe { w.bits(0xffff_ffff) });
+        self.lpcomp.tasks_stop.write(|w| unsafe { w.bits(1) });
+        self.lpcomp.enable.write(|w| w.enable().disabled());
+        self.clear_events();
+    }
+
+    /// Set the reference of the state, only while the comparator runs
+    fn reconfigure(&mut self) {
+        if self.lpcomp.enable.read().enable().is_enabled() {
+            self.restart();
+        }
+    }
+
+    /// Restart the comparator with the reference of the state
+    ///
+    /// It raises READY once it has settled, without waiting for it here.
+    fn restart(&mut self) {
+        let lpcomp = &self.lpcomp;
+        lpcomp.tasks_stop.write(|w| unsafe { w.bits(1) });
+        lpcomp.enable.write(|w| w.enable().disabled());
+        // 0 to 6 are 1 to 7 eighths of the supply
+        lpcomp
+            .refsel
+            .write(|w| unsafe { w.bits(u32::from(self.reference() - 1)) });
+        lpcomp.enable.write(|w| w.enable().enabled());
+        self.clear_events();
+        lpcomp.tasks_start.write(|w| unsafe { w.bits(1) });
+    }
+
+    fn clear_events(&self) {
+        let lpcomp = &self.lpcomp;
+        lpcomp.events_ready.write(|w| unsafe { w.bits(0) });
+        lpcomp.events_down.write(|w| unsafe { w.bits(0) });
+        lpcomp.events_up.write(|w| unsafe { w.bits(0) });
+        lpcomp.events_cross.write(|w| unsafe { w.bits(0) });
+    }
+
+    /// Report a crossing of the reference
+    ///
+    /// Call this in the interrupt handler of LPCOMP. It clears the events
+    /// and moves the reference to the other side of the hysteresis. The
+    /// comparator settles at the new reference before it reports the next
+    /// crossing, which limits the rate of the interrupts, and the signal is
+    /// compared against the new reference once it is ready.
+    pub fn handle_lpcomp_event(&mut self) -> Option<SoundEvent> {
+        let lpcomp = &self.lpcomp;
+        let crossed = if lpcomp.events_ready.read().bits() != 0 {
+            // the signal may have crossed the reference while it settled
+            lpcomp.tasks_sample.write(|w| unsafe { w.bits(1) });
+            lpcomp.result.read().result().is_above() != self.loud
+        } else if self.loud {
+            lpcomp.events_down.read().bits() != 0
+        } else {
+            lpcomp.events_up.read().bits() != 0
+        };
+        self.clear_events();
+        if !crossed {
+            return None;
+        }
+        self.loud = !self.loud;
+        self.restart();
+        Some(if self.loud {
+            SoundEvent::Loud
+        } else {
+            SoundEvent::Quiet
+        })
+    }
+
+    /// Switch the chip to System OFF until a sound rises above the
+    /// threshold
+    ///
+    /// Everything but the comparator is switched off and the chip restarts
+    /// from reset when it wakes up, with [`ResetReason::SoundWake`](crate::power::ResetReason::SoundWake). The
+    /// outputs keep their levels, so the microphone stays powered.
+    ///
+    /// While a debugger is attached the chip only emulates System OFF, and
+    /// this function spins instead of returning.
+    pub fn system_off(mut self, power: &POWER) -> ! {
+        self.loud = false;
+        self.lpcomp
+            .intenclr
+            .write(|w| unsafe { w.bits(0xffff_ffff) });
+        self.lpcomp.anadetect.write(|w| w.anadetect().up());
+        self.restart();
+        while self.lpcomp.events_ready.read().bits() == 0 {}
+        power.systemoff.write(|w| w.systemoff().enter());
+        loop {
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Stop comparing and release the LPCOMP peripheral and the
+    /// microphone pins
+    pub fn free(mut self) -> (LPCOMP, MicrophonePins) {
+        self.stop();
+        #[cfg(feature = "v2")]
+        self.pins.mic_run.set_low().ok();
+        (self.lpcomp, self.pins)
+    }
+}
@@ -0,0 +1,453 @@
+//! DC motors on the DRV8837 H-bridge
+//!
+//! The H-bridge of the Calliope mini drives either the speaker or motors.
+//! [`BeeperMotorPins`] turn into a [`Beeper`](crate::sound::Beeper), a
+//! [`Motor`] or [`Motors`] with the same TIMER, GPIOTE and PPI channels,
+//! and each of them hands them back when it is freed, so the speaker and
+//! the motors can not be used at the same time.
+//!
+//! - [`Motor`]: one motor between the two outputs, which runs in both
+//!   directions
+//! - [`Motors`]: two motors, each between one output and ground, like
+//!   MakeCode's motor A and B, which only run forward
+//!
+//! The speed is set by PWM at [`PWM_FREQUENCY_HZ`], above the audible
+//! range: the TIMER runs at 16 MHz and CC\[3\] holds the period. The pulse
+//! of OUT1 starts with the period and ends at CC\[0\], the pulse of OUT2
+//! starts at CC\[1\] and ends with the period. The DRV8837 brakes while
+//! both inputs are high, so the pulses of [`Motors`] must not overlap:
+//! their speeds share one period and add up to at most 100, higher ones
+//! are scaled down together by [`motors_duties`]. A running motor is
+//! switched between driving and coasting. New speeds restart the period, which a
+//! motor does not notice.
+//!
+//! Speed changes are ramped to avoid current spikes, by default in
+//! [`DEFAULT_RAMP_MS`] from standstill to full speed. The ramp moves in
+//! 1 ms steps: while `is_ramping()` is true, call `step_ramp()` every
+//! millisecond, e.g. from a timer interrupt.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use microbit_common as microbit;
+//! use microbit::{
+//!     channels::{GpioteChannels, PpiChannels},
+//!     motor::Output,
+//!     Board,
+//! };
+//!
+//! let board = Board::take().unwrap();
+//! let gpiote = GpioteChannels::new(board.GPIOTE);
+//! let ppi = PpiChannels::new(board.PPI);
+//!
+//! let mut motor = board.beeper_motor_pins.into_motor(
+//!     board.TIMER0,
+//!     [gpiote.channel0, gpiote.channel1],
+//!     [ppi.ppi0, ppi.ppi1, ppi.ppi2, ppi.ppi3],
+//! );
+//! // half speed in reverse, reached after 100 ms
+//! motor.set_speed(-50);
+//! // every millisecond
+//! while motor.is_ramping() {
+//!     motor.step_ramp();
+//! }
+//! motor.brake();
+//!
+//! let (timer, pins, gpiote, ppi) = motor.free();
+//! let mut motors = pins.into_motors(timer, gpiote, ppi);
+//! // driven with 76 and 23, which add up to at most 100
+//! motors.set_speed(Output::A, 100);
+//! motors.set_speed(Output::B, 30);
+//! ```
+use embedded_hal::digital::v2::OutputPin;
+
+use crate::{
+    channels::{GpioteChannel, PpiChannel},
+    gpio::BeeperMotorPins,
+    hal::{gpio::Level, gpiote::TaskOutPolarity, timer::Instance},
+};
+
+/// Frequency of the PWM signals
+pub const PWM_FREQUENCY_HZ: u32 = 20_000;
+
+/// Time from standstill to full speed unless set otherwise
+pub const DEFAULT_RAMP_MS: u16 = 200;
+
+/// Highest speed
+pub const MAX_SPEED: u8 = 100;
+
+/// Duty cycles of the outputs driving [`Motors`] at `speeds`
+///
+/// The two pulses share one period, so speeds adding up to more than
+/// [`MAX_SPEED`] are scaled down in the same ratio to add up to at most
+/// [`MAX_SPEED`].
+///
+/// ```
+/// # use microbit_common as microbit;
+/// use microbit::motor::motors_duties;
+///
+/// assert_eq!(motors_duties([40, 50]), [40, 50]);
+/// assert_eq!(motors_duties([100, 30]), [76, 23]);
+/// assert_eq!(motors_duties([100, 100]), [50, 50]);
+/// ```
+pub fn motors_duties(speeds: [u8; 2]) -> [u8; 2] {
+    let speeds = speeds.map(|speed| u16::from(speed.min(MAX_SPEED)));
+    let sum = speeds[0] + speeds[1];
+    if sum <= u16::from(MAX_SPEED) {
+        return speeds.map(|speed| speed as u8);
+    }
+    speeds.map(|speed| (speed * u16::from(MAX_SPEED) / sum) as u8)
+}
+
+/// TIMER ticks per PWM period at 16 MHz
+const PERIOD_TICKS: u32 = 16_000_000 / PWM_FREQUENCY_HZ;
+
+/// Compare register holding the period
+const PERIOD_CC: usize = 3;
+
+impl BeeperMotorPins {
+    /// Drive one motor between the two outputs
+    pub fn into_motor<T: Instance>(
+        self,
+        timer: T,
+        gpiote: [GpioteChannel; 2],
+        ppi: [PpiChannel; 4],
+    ) -> Motor<T> {
+        Motor {
+            bridge: Bridge::new(timer, self, gpiote, ppi),
+            ramp: Ramp::default(),
+        }
+    }
+
+    /// Drive two motors, each between one output and ground
+    pub fn into_motors<T: Instance>(
+        self,
+        timer: T,
+        gpiote: [GpioteChannel; 2],
+        ppi: [PpiChannel; 4],
+    ) -> Motors<T> {
+        Motors {
+            bridge: Bridge::new(timer, self, gpiote, ppi),
+            ramps: Default::default(),
+        }
+    }
+}
+
+/// Speed moving towards its target, 1 ms at a time
+#[derive(Copy, Clone, Debug)]
+struct Ramp {
+    // current speed in 1/1000
+    milli: i32,
+    target: i8,
+    ramp_ms: u16,
+}
+
+impl Default for Ramp {
+    fn default() -> Self {
+        Self {
+            milli: 0,
+            target: 0,
+            ramp_ms: DEFAULT_RAMP_MS,
+        }
+    }
+}
+
+impl Ramp {
+    fn speed(&self) -> i8 {
+        (self.milli / 1000) as i8
+    }
+
+    fn is_done(&self) -> bool {
+        self.milli == i32::from(self.target) * 1000
+    }
+
+    fn set_target(&mut self, target: i8) {
+        self.target = target;
+        if self.ramp_ms == 0 {
+            self.stop_at(target);
+        }
+    }
+
+    fn stop_at(&mut self, speed: i8) {
+        self.target = speed;
+        self.milli = i32::from(speed) * 1000;
+    }
+
+    /// Move 1 ms towards the target, true if the speed changed
+    fn tick(&mut self) -> bool {
+        let before = self.speed();
+        let step = (i32::from(MAX_SPEED) * 1000 / i32::from(self.ramp_ms.max(1))).max(1);
+        let target = i32::from(self.target) * 1000;
+        self.milli = if self.milli < target {
+            (self.milli + step).min(target)
+        } else {
+            (self.milli - step).max(target)
+        };
+        self.speed() != before
+    }
+}
+
+/// The H-bridge with PWM on both inputs
+struct Bridge<T: Instance> {
+    timer: T,
+    pins: BeeperMotorPins,
+    gpiote: [GpioteChannel; 2],
+    ppi: [PpiChannel; 4],
+}
+
+impl<T: Instance> Bridge<T> {
+    fn new(
+        timer: T,
+        pins: BeeperMotorPins,
+        gpiote: [GpioteChannel; 2],
+        mut ppi: [PpiChannel; 4],
+    ) -> Self {
+        let regs = timer.as_timer0();
+        regs.tasks_stop.write(|w| unsafe { w.bits(1) });
+        regs.tasks_clear.write(|w| unsafe { w.bits(1) });
+        regs.mode.write(|w| w.mode().timer());
+        regs.bitmode.write(|w| w.bitmode()._16bit());
+        regs.prescaler.write(|w| unsafe { w.bits(0) });
+        regs.cc[PERIOD_CC].write(|w| unsafe { w.bits(PERIOD_TICKS) });
+        regs.shorts.write(|w| w.compare3_clear().enabled());
+        regs.intenclr.write(|w| unsafe { w.bits(0xffff_ffff) });
+
+        // IN1 toggles at CC[0] and the period, IN2 at CC[1] and the period
+        for (index, ppi) in ppi.iter_mut().enumerate() {
+            let (output, cc) = (index / 2, [index / 2, PERIOD_CC][index % 2]);
+            ppi.set_event_endpoint(&regs.events_compare[cc]);
+            ppi.set_task_endpoint(gpiote[output].task_out());
+        }
+
+        let mut bridge = Self {
+            timer,
+            pins,
+            gpiote,
+            ppi,
+        };
+        bridge.sleep();
+        bridge
+    }
+
+    /// Drive the inputs with duty cycles from 0 to [`MAX_SPEED`] and wake
+    /// the H-bridge
+    ///
+    /// IN1 is high at the start of the period and IN2 at its end, so they
+    /// are never high together while the duty cycles add up to at most
+    /// [`MAX_SPEED`].
+    fn drive(&mut self, duties: [u8; 2]) {
+        let regs = self.timer.as_timer0();
+        regs.tasks_stop.write(|w| unsafe { w.bits(1) });
+        regs.tasks_clear.write(|w| unsafe { w.bits(1) });
+        for (output, duty) in duties.into_iter().enumerate() {
+            let ppi = &mut self.ppi[2 * output..2 * output + 2];
+            for ppi in ppi.iter_mut() {
+                ppi.disable();
+            }
+            self.gpiote[output].disable();
+            let level = match duty.min(MAX_SPEED) {
+                0 => Level::Low,
+                MAX_SPEED => Level::High,
+                duty => {
+                    let high = PERIOD_TICKS * u32::from(duty) / u32::from(MAX_SPEED);
+                    let (toggle, initial) = if output == 0 {
+                        (high, Level::High)
+                    } else {
+                        (PERIOD_TICKS - high, Level::Low)
+                    };
+                    regs.cc[output].write(|w| unsafe { w.bits(toggle) });
+                    self.gpiote[output].output_pin(
+                        &self.pins.gpiote_in_pins()[output],
+                        TaskOutPolarity::Toggle,
+                        initial,
+                    );
+                    for ppi in ppi.iter_mut() {
+                        ppi.enable();
+                    }
+                    continue;
+                }
+            };
+            // without GPIOTE the pin follows its GPIO output
+            match (output, level) {
+                (0, Level::Low) => self.pins.motor_in1.set_low().ok(),
+                (0, Level::High) => self.pins.motor_in1.set_high().ok(),
+                (_, Level::Low) => self.pins.motor_in2.set_low().ok(),
+                (_, Level::High) => self.pins.motor_in2.set_high().ok(),
+            };
+        }
+        self.pins.motor_nsleep.set_high().ok();
+        regs.tasks_start.write(|w| unsafe { w.bits(1) });
+    }
+
+    fn sleep(&mut self) {
+        self.drive([0, 0]);
+        let regs = self.timer.as_timer0();
+        regs.tasks_stop.write(|w| unsafe { w.bits(1) });
+        self.pins.motor_nsleep.set_low().ok();
+    }
+
+    fn free(mut self) -> (T, BeeperMotorPins, [GpioteChannel; 2], [PpiChannel; 4]) {
+        self.sleep();
+        (self.timer, self.pins, self.gpiote, self.ppi)
+    }
+}
+
+/// One motor between the two outputs of the H-bridge
+pub struct Motor<T: Instance> {
+    bridge: Bridge<T>,
+    ramp: Ramp,
+}
+
+impl<T: Instance> Motor<T> {
+    /// Current speed from -100 in reverse to 100 forward
+    pub fn speed(&self) -> i8 {
+        self.ramp.speed()
+    }
+
+    /// Ramp to a speed from -100 in reverse to 100 forward
+    ///
+    /// Forward drives OUT1, reverse OUT2, while the other output is low. A
+    /// speed of 0 lets the motor coast.
+    pub fn set_speed(&mut self, speed: i8) {
+        self.ramp
+            .set_target(speed.clamp(-(MAX_SPEED as i8), MAX_SPEED as i8));
+        self.apply();
+    }
+
+    /// Set the time from standstill to full speed, 0 to change the speed
+    /// at once
+    pub fn set_ramp_ms(&mut self, ramp_ms: u16) {
+        self.ramp.ramp_ms = ramp_ms;
+    }
+
+    /// Short the motor through the low side, it stops quickly
+    pub fn brake(&mut self) {
+        self.ramp.stop_at(0);
+        self.bridge.drive([MAX_SPEED, MAX_SPEED]);
+    }
+
+    /// Drive both outputs low, the motor runs down
+    pub fn coast(&mut self) {
+        self.ramp.stop_at(0);
+        self.bridge.drive([0, 0]);
+    }
+
+    /// Put the H-bridge to sleep, its outputs float until the next speed
+    /// is set
+    pub fn sleep(&mut self) {
+        self.ramp.stop_at(0);
+        self.bridge.sleep();
+    }
+
+    /// Whether the speed is still ramping towards the last one set
+    pub fn is_ramping(&self) -> bool {
+        !self.ramp.is_done()
+    }
+
+    /// Move the speed 1 ms further towards the last one set
+    ///
+    /// Call this every millisecond while [`is_ramping`](Self::is_ramping)
+    /// is true.
+    pub fn step_ramp(&mut self) {
+        if self.ramp.tick() {
+            self.apply();
+        }
+    }
+
+    fn apply(&mut self) {
+        let speed = self.ramp.speed();
+        let duty = speed.unsigned_abs();
+        self.bridge
+            .drive(if speed >= 0 { [duty, 0] } else { [0, duty] });
+    }
+
+    /// Put the H-bridge to sleep and release the pins and the resources
+    pub fn free(self) -> (T, BeeperMotorPins, [GpioteChannel; 2], [PpiChannel; 4]) {
+        self.bridge.free()
+    }
+}
+
+/// Output of the H-bridge driving one of [`Motors`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Output {
+    /// OUT1, driven by MOTOR_IN1
+    A,
+    /// OUT2, driven by MOTOR_IN2
+    B,
+}
+
+/// Two motors, each between one output of the H-bridge and ground
+pub struct Motors<T: Instance> {
+    bridge: Bridge<T>,
+    ramps: [Ramp; 2],
+}
+
+impl<T: Instance> Motors<T> {
+    /// Current speed of the motor on `output` from 0 to 100
+    pub fn speed(&self, output: Output) -> u8 {
+        self.ramps[output as usize].speed() as u8
+    }
+
+    /// Ramp the motor on `output` to a speed from 0 to 100
+    ///
+    /// While the speeds of both motors add up to more than 100, they are
+    /// driven slower in the same ratio, see [`motors_duties`].
+    pub fn set_speed(&mut self, output: Output, speed: u8) {
+        self.ramps[output as usize].set_target(speed.min(MAX_SPEED) as i8);
+        self.apply();
+    }
+
+    /// Set the time from standstill to full speed of both motors, 0 to
+    /// change the speeds at once
+    pub fn set_ramp_ms(&mut self, ramp_ms: u16) {
+        for ramp in &mut self.ramps {
+            ramp.ramp_ms = ramp_ms;
+        }
+    }
+
+    /// Stop both motors at once, their outputs are driven low
+    pub fn stop(&mut self) {
+        for ramp in &mut self.ramps {
+            ramp.stop_at(0);
+        }
+        self.bridge.drive([0, 0]);
+    }
+
+    /// Put the H-bridge to sleep, both motors run down
+    pub fn sleep(&mut self) {
+        for ramp in &mut self.ramps {
+            ramp.stop_at(0);
+        }
+        self.bridge.sleep();
+    }
+
+    /// Whether a speed is still ramping towards the last one set
+    pub fn is_ramping(&self) -> bool {
+        !self.ramps.iter().all(Ramp::is_done)
+    }
+
+    /// Move the speeds 1 ms further towards the last ones set
+    ///
+    /// Call this every millisecond while [`is_ramping`](Self::is_ramping)
+    /// is true.
+    pub fn step_ramp(&mut self) {
+        let mut changed = false;
+        for ramp in &mut self.ramps {
+            changed |= ramp.tick();
+        }
+        if changed {
+            self.apply();
+        }
+    }
+
+    fn apply(&mut self) {
+        let [a, b] = self.ramps;
+        self.bridge
+            .drive(motors_duties([a.speed() as u8, b.speed() as u8]));
+    }
+
+    /// Put the H-bridge to sleep and release the pins and the resources
+    pub fn free(self) -> (T, BeeperMotorPins, [GpioteChannel; 2], [PpiChannel; 4]) {
+        self.bridge.free()
+    }
+}
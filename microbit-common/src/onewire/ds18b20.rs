@@ -0,0 +1,191 @@
+//! DS18B20 temperature sensors
+//!
+//! A conversion takes up to 750 ms at the default resolution of 12 bits.
+//! Start it, wait, then read the result, or use [`Ds18b20::measure`] to do
+//! all three. [`Ds18b20::start_all_conversions`] starts every sensor on the
+//! bus at once.
+//!
+//! The sensors have to be powered from their VDD pin, parasitic power is not
+//! supported.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use microbit_common as microbit;
+//! use microbit::{
+//!     hal::Timer,
+//!     onewire::{ds18b20::Ds18b20, OneWire},
+//!     Board,
+//! };
+//!
+//! let board = Board::take().unwrap();
+//! let mut delay = Timer::new(board.TIMER0);
+//! let mut bus = OneWire::new(board.TIMER1, board.edge.e01.into_floating_input().degrade());
+//!
+//! let rom = bus.read_rom().unwrap();
+//! let sensor = Ds18b20::new(rom).unwrap();
+//! let _millidegrees = sensor.measure(&mut bus, &mut delay);
+//! ```
+use embedded_hal::blocking::delay::DelayMs;
+
+use super::{crc8, Error, OneWire, Rom};
+use crate::hal::timer::Instance;
+
+/// Family code of the DS18B20
+pub const FAMILY_CODE: u8 = 0x28;
+
+const CONVERT_T: u8 = 0x44;
+const READ_SCRATCHPAD: u8 = 0xbe;
+const WRITE_SCRATCHPAD: u8 = 0x4e;
+
+/// Conversion resolution
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Resolution {
+    /// 0.5 °C
+    Bits9,
+    /// 0.25 °C
+    Bits10,
+    /// 0.125 °C
+    Bits11,
+    /// 0.0625 °C, the default
+    Bits12,
+}
+
+impl Resolution {
+    /// Longest conversion time
+    pub fn conversion_time_ms(self) -> u16 {
+        match self {
+            Resolution::Bits9 => 94,
+            Resolution::Bits10 => 188,
+            Resolution::Bits11 => 375,
+            Resolution::Bits12 => 750,
+        }
+    }
+
+    fn from_config(config: u8) -> Self {
+        match (config >> 5) & 0b11 {
+            0 => Resolution::Bits9,
+            1 => Resolution::Bits10,
+            2 => Resolution::Bits11,
+            _ => Resolution::Bits12,
+        }
+    }
+
+    fn config(self) -> u8 {
+        (self as u8) << 5 | 0x1f
+    }
+}
+
+/// Temperature in m°C from the 9 bytes of the scratchpad
+///
+/// The bits below the configured resolution are undefined and ignored. The
+/// CRC is not checked.
+///
+/// ```
+/// # use microbit_common as microbit;
+/// use microbit::onewire::ds18b20::scratchpad_to_millidegrees;
+///
+/// // +25.0625 °C
+/// let scratchpad = [0x91, 0x01, 0x4b, 0x46, 0x7f, 0xff, 0x0f, 0x10, 0x00];
+/// assert_eq!(scratchpad_to_millidegrees(&scratchpad), 25062);
+/// // -10.125 °C
+/// let scratchpad = [0x5e, 0xff, 0x4b, 0x46, 0x7f, 0xff, 0x0f, 0x10, 0x00];
+/// assert_eq!(scratchpad_to_millidegrees(&scratchpad), -10125);
+/// ```
+pub fn scratchpad_to_millidegrees(scratchpad: &[u8; 9]) -> i32 {
+    let raw = i16::from_le_bytes([scratchpad[0], scratchpad[1]]);
+    let undefined = 3 - Resolution::from_config(scratchpad[4]) as u8;
+    let raw = raw & !((1 << undefined) - 1);
+    // 1/16 °C per LSB
+    i32::from(raw) * 625 / 10
+}
+
+/// A DS18B20 on a 1-Wire bus
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Ds18b20 {
+    rom: Rom,
+}
+
+impl Ds18b20 {
+    /// The sensor with this ROM code, `None` if it is not a DS18B20
+    pub fn new(rom: Rom) -> Option<Self> {
+        (rom.family() == FAMILY_CODE).then_some(Self { rom })
+    }
+
+    /// ROM code of the sensor
+    pub fn rom(&self) -> Rom {
+        self.rom
+    }
+
+    /// Start a conversion on every sensor on the bus
+    pub fn start_all_conversions<T: Instance>(bus: &mut OneWire<T>) -> Result<(), Error> {
+        bus.select(None)?;
+        bus.write_byte(CONVERT_T);
+        Ok(())
+    }
+
+    /// Start a conversion
+    pub fn start_conversion<T: Instance>(&self, bus: &mut OneWire<T>) -> Result<(), Error> {
+        bus.select(Some(&self.rom))?;
+        bus.write_byte(CONVERT_T);
+        Ok(())
+    }
+
+    /// Read the 9 bytes of the scratchpad and check their CRC
+    pub fn read_scratchpad<T: Instance>(&self, bus: &mut OneWire<T>) -> Result<[u8; 9], Error> {
+        bus.select(Some(&self.rom))?;
+        bus.write_byte(READ_SCRATCHPAD);
+        let mut scratchpad = [0; 9];
+        bus.read_bytes(&mut scratchpad);
+        if crc8(&scratchpad) == 0 {
+            Ok(scratchpad)
+        } else {
+            Err(Error::Crc)
+        }
+    }
+
+    /// Temperature of the last conversion in m°C
+    ///
+    /// The sensor reports 85 °C until its first conversion.
+    pub fn read_millidegrees<T: Instance>(&self, bus: &mut OneWire<T>) -> Result<i32, Error> {
+        self.read_scratchpad(bus)
+            .map(|scratchpad| scratchpad_to_millidegrees(&scratchpad))
+    }
+
+    /// Configured resolution
+    pub fn resolution<T: Instance>(&self, bus: &mut OneWire<T>) -> Result<Resolution, Error> {
+        self.read_scratchpad(bus)
+            .map(|scratchpad| Resolution::from_config(scratchpad[4]))
+    }
+
+    /// Change the resolution, the alarm thresholds are kept
+    ///
+    /// The setting is lost when the sensor loses power.
+    pub fn set_resolution<T: Instance>(
+        &self,
+        bus: &mut OneWire<T>,
+        resolution: Resolution,
+    ) -> Result<(), Error> {
+        let scratchpad = self.read_scratchpad(bus)?;
+        bus.select(Some(&self.rom))?;
+        bus.write_bytes(&[
+            WRITE_SCRATCHPAD,
+            scratchpad[2],
+            scratchpad[3],
+            resolution.config(),
+        ]);
+        Ok(())
+    }
+
+    /// Start a conversion, wait for it and read the temperature in m°C
+    pub fn measure<T: Instance, D: DelayMs<u16>>(
+        &self,
+        bus: &mut OneWire<T>,
+        delay: &mut D,
+    ) -> Result<i32, Error> {
+        let resolution = self.resolution(bus)?;
+        self.start_conversion(bus)?;
+        delay.delay_ms(resolution.conversion_time_ms());
+        self.read_millidegrees(bus)
+    }
+}
@@ -0,0 +1,376 @@
+//! 1-Wire bus
+//!
+//! [`OneWire`] is a bit-banged 1-Wire master on any edge pin. The bus needs
+//! a pull-up resistor of about 4.7 kΩ to 3.3V. The pin only ever pulls the
+//! bus low, it is released to let the pull-up or a device drive it.
+//!
+//! The time slots are measured with a TIMER running at 1 MHz. A slot is
+//! corrupted if an interrupt delays the master by more than a few µs in the
+//! middle of it, so keep long interrupt handlers away from bus transfers.
+//!
+//! Devices are addressed by their 64 bit [`Rom`] code, which can be found
+//! with [`OneWire::search`]. Drivers for the devices are in the submodules.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use microbit_common as microbit;
+//! use microbit::{
+//!     onewire::{OneWire, SearchState},
+//!     Board,
+//! };
+//!
+//! let board = Board::take().unwrap();
+//! let mut bus = OneWire::new(board.TIMER1, board.edge.e01.into_floating_input().degrade());
+//!
+//! let mut search = SearchState::new();
+//! while let Ok(Some(rom)) = bus.search(&mut search) {
+//!     let _family = rom.family();
+//! }
+//! ```
+use embedded_hal::digital::v2::InputPin;
+
+use crate::{
+    hal::{
+        gpio::{Floating, Input, Pin},
+        timer::Instance,
+    },
+    pac,
+};
+
+#[cfg(feature = "v2")]
+use crate::hal::gpio::Port;
+
+pub mod ds18b20;
+
+/// ROM command: find the ROM codes of all devices
+pub const SEARCH_ROM: u8 = 0xf0;
+/// ROM command: read the ROM code of the only device on the bus
+pub const READ_ROM: u8 = 0x33;
+/// ROM command: address the device with the following ROM code
+pub const MATCH_ROM: u8 = 0x55;
+/// ROM command: address all devices
+pub const SKIP_ROM: u8 = 0xcc;
+
+/// 1-Wire errors
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// No device answered the reset pulse
+    NoPresence,
+    /// The bus is held low, check the pull-up resistor and the wiring
+    BusLow,
+    /// The data read does not match its CRC
+    Crc,
+}
+
+/// Dallas/Maxim CRC8 of 1-Wire ROM codes and scratchpads
+///
+/// The CRC over data including its CRC byte is 0.
+///
+/// ```
+/// # use microbit_common as microbit;
+/// use microbit::onewire::crc8;
+///
+/// let rom = [0x02, 0x1c, 0xb8, 0x01, 0x00, 0x00, 0x00, 0xa2];
+/// assert_eq!(crc8(&rom[..7]), 0xa2);
+/// assert_eq!(crc8(&rom), 0);
+/// ```
+pub fn crc8(data: &[u8]) -> u8 {
+    let mut crc = 0u8;
+    for byte in data {
+        let mut byte = *byte;
+        for _ in 0..8 {
+            let mix = (crc ^ byte) & 1;
+            crc >>= 1;
+            if mix != 0 {
+                crc ^= 0x8c;
+            }
+            byte >>= 1;
+        }
+    }
+    crc
+}
+
+/// 64 bit ROM code of a device: family code, serial number and CRC
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Rom(pub [u8; 8]);
+
+impl Rom {
+    /// Check the CRC of a ROM code
+    ///
+    /// ```
+    /// # use microbit_common as microbit;
+    /// use microbit::onewire::Rom;
+    ///
+    /// let rom = Rom::from_bytes([0x02, 0x1c, 0xb8, 0x01, 0x00, 0x00, 0x00, 0xa2]).unwrap();
+    /// assert_eq!(rom.family(), 0x02);
+    /// assert!(Rom::from_bytes([0x02, 0x1c, 0xb8, 0x01, 0x00, 0x00, 0x00, 0xa3]).is_err());
+    /// ```
+    pub fn from_bytes(bytes: [u8; 8]) -> Result<Self, Error> {
+        if crc8(&bytes) == 0 {
+            Ok(Self(bytes))
+        } else {
+            Err(Error::Crc)
+        }
+    }
+
+    /// Family code, the type of the device
+    pub fn family(&self) -> u8 {
+        self.0[0]
+    }
+}
+
+/// Progress of a [`OneWire::search`] over several calls
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SearchState {
+    rom: [u8; 8],
+    last_discrepancy: u8,
+    done: bool,
+}
+
+impl SearchState {
+    /// Start with the first device
+    pub const fn new() -> Self {
+        Self {
+            rom: [0; 8],
+            last_discrepancy: 0,
+            done: false,
+        }
+    }
+
+    fn bit(&self, index: u8) -> bool {
+        self.rom[usize::from(index / 8)] & (1 << (index % 8)) != 0
+    }
+
+    fn set_bit(&mut self, index: u8, value: bool) {
+        let byte = &mut self.rom[usize::from(index / 8)];
+        if value {
+            *byte |= 1 << (index % 8);
+        } else {
+            *byte &= !(1 << (index % 8));
+        }
+    }
+}
+
+/// Bit-banged 1-Wire master using a TIMER for the slot timing
+pub struct OneWire<T: Instance> {
+    timer: T,
+    pin: Pin<Input<Floating>>,
+}
+
+impl<T: Instance> OneWire<T> {
+    /// Take the timer and the bus pin, the bus is released
+    pub fn new(timer: T, pin: Pin<Input<Floating>>) -> Self {
+        let regs = timer.as_timer0();
+        regs.tasks_stop.write(|w| unsafe { w.bits(1) });
+        regs.tasks_clear.write(|w| unsafe { w.bits(1) });
+        regs.mode.write(|w| w.mode().timer());
+        regs.bitmode.write(|w| w.bitmode()._16bit());
+        // 16 MHz / 2^4 = 1 MHz
+        regs.prescaler.write(|w| unsafe { w.bits(4) });
+        regs.shorts.reset();
+        regs.intenclr.write(|w| unsafe { w.bits(0xffff_ffff) });
+        regs.tasks_start.write(|w| unsafe { w.bits(1) });
+
+        let bus = Self { timer, pin };
+        // the pin drives low whenever it is an output
+        bus.release();
+        bus.gpio()
+            .outclr
+            .write(|w| unsafe { w.bits(1 << bus.pin.pin()) });
+        bus
+    }
+
+    /// Send a reset pulse and check that a device answers
+    pub fn reset(&mut self) -> Result<(), Error> {
+        if !self.is_high() {
+            return Err(Error::BusLow);
+        }
+        self.pull_low();
+        self.wait_us(480);
+        self.release();
+        self.wait_us(70);
+        let present = !self.is_high();
+        self.wait_us(410);
+        if !self.is_high() {
+            Err(Error::BusLow)
+        } else if present {
+            Ok(())
+        } else {
+            Err(Error::NoPresence)
+        }
+    }
+
+    /// Write a single bit
+    pub fn write_bit(&mut self, bit: bool) {
+        self.pull_low();
+        if bit {
+            self.wait_us(6);
+            self.release();
+            self.wait_us(64);
+        } else {
+            self.wait_us(60);
+            self.release();
+            self.wait_us(10);
+        }
+    }
+
+    /// Read a single bit
+    pub fn read_bit(&mut self) -> bool {
+        self.pull_low();
+        self.wait_us(6);
+        self.release();
+        self.wait_us(9);
+        let bit = self.is_high();
+        self.wait_us(55);
+        bit
+    }
+
+    /// Write a byte, least significant bit first
+    pub fn write_byte(&mut self, byte: u8) {
+        for index in 0..8 {
+            self.write_bit(byte & (1 << index) != 0);
+        }
+    }
+
+    /// Read a byte, least significant bit first
+    pub fn read_byte(&mut self) -> u8 {
+        (0..8).fold(0, |byte, index| byte | u8::from(self.read_bit()) << index)
+    }
+
+    /// Write several bytes
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.write_byte(*byte);
+        }
+    }
+
+    /// Fill `buffer` with bytes read from the bus
+    pub fn read_bytes(&mut self, buffer: &mut [u8]) {
+        for byte in buffer {
+            *byte = self.read_byte();
+        }
+    }
+
+    /// Reset the bus and address a single device, or all devices if `rom` is
+    /// `None`
+    ///
+    /// Send the function command of the device next.
+    pub fn select(&mut self, rom: Option<&Rom>) -> Result<(), Error> {
+        self.reset()?;
+        match rom {
+            Some(rom) => {
+                self.write_byte(MATCH_ROM);
+                self.write_bytes(&rom.0);
+            }
+            None => self.write_byte(SKIP_ROM),
+        }
+        Ok(())
+    }
+
+    /// Read the ROM code of the only device on the bus
+    pub fn read_rom(&mut self) -> Result<Rom, Error> {
+        self.reset()?;
+        self.write_byte(READ_ROM);
+        let mut bytes = [0; 8];
+        self.read_bytes(&mut bytes);
+        Rom::from_bytes(bytes)
+    }
+
+    /// Find the next device on the bus
+    ///
+    /// Returns `None` once all devices have been found. Start with a new
+    /// [`SearchState`] to search again.
+    pub fn search(&mut self, state: &mut SearchState) -> Result<Option<Rom>, Error> {
+        if state.done {
+            return Ok(None);
+        }
+        match self.reset() {
+            Err(Error::NoPresence) => return Ok(None),
+            result => result?,
+        }
+        self.write_byte(SEARCH_ROM);
+
+        let mut last_zero = 0;
+        for index in 1..=64 {
+            let bit = self.read_bit();
+            let complement = self.read_bit();
+            let direction = match (bit, complement) {
+                // the device left during the search
+                (true, true) => return Ok(None),
+                // all remaining devices agree
+                (bit, complement) if bit != complement => bit,
+                // a discrepancy: devices with 0 and with 1 at this position
+                _ => {
+                    let direction = if index < state.last_discrepancy {
+                        state.bit(index - 1)
+                    } else {
+                        index == state.last_discrepancy
+                    };
+                    if !direction {
+                        last_zero = index;
+                    }
+                    direction
+                }
+            };
+            state.set_bit(index - 1, direction);
+            self.write_bit(direction);
+        }
+
+        state.last_discrepancy = last_zero;
+        state.done = last_zero == 0;
+        Rom::from_bytes(state.rom).map(Some)
+    }
+
+    /// Release the timer and the pin
+    pub fn free(self) -> (T, Pin<Input<Floating>>) {
+        self.release();
+        self.timer
+            .as_timer0()
+            .tasks_stop
+            .write(|w| unsafe { w.bits(1) });
+        (self.timer, self.pin)
+    }
+
+    fn is_high(&self) -> bool {
+        self.pin.is_high().unwrap_or(false)
+    }
+
+    fn pull_low(&self) {
+        self.gpio()
+            .dirset
+            .write(|w| unsafe { w.bits(1 << self.pin.pin()) });
+    }
+
+    fn release(&self) {
+        self.gpio()
+            .dirclr
+            .write(|w| unsafe { w.bits(1 << self.pin.pin()) });
+    }
+
+    fn wait_us(&self, us: u16) {
+        let start = self.now();
+        while self.now().wrapping_sub(start) < us {}
+    }
+
+    fn now(&self) -> u16 {
+        let regs = self.timer.as_timer0();
+        regs.tasks_capture[0].write(|w| unsafe { w.bits(1) });
+        regs.cc[0].read().bits() as u16
+    }
+
+    #[cfg(feature = "v1")]
+    fn gpio(&self) -> &pac::gpio::RegisterBlock {
+        // only the DIR and OUT bits of the bus pin are changed
+        unsafe { &*pac::GPIO::ptr() }
+    }
+
+    #[cfg(feature = "v2")]
+    fn gpio(&self) -> &pac::p0::RegisterBlock {
+        // only the DIR and OUT bits of the bus pin are changed
+        match self.pin.port() {
+            Port::Port0 => unsafe { &*pac::P0::ptr() },
+            Port::Port1 => unsafe { &*pac::P1::ptr() },
+        }
+    }
+}
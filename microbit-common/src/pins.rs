@@ -0,0 +1,2 @@
+//! Edge connector pins addressed by their Calliope mini label at runtime
+pub use crate::v1::pins::*;
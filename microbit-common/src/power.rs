@@ -0,0 +1,71 @@
+//! Reset and wake up reasons of the chip
+//!
+//! [`ResetReason`] tells why the program started: after switching the
+//! supply on, a reset, or a wake up from System OFF, e.g. by the
+//! [`SoundTrigger`](crate::microphone::SoundTrigger).
+use crate::pac::POWER;
+
+/// Why the chip started, from the RESETREAS register of POWER
+///
+/// The register collects the reasons until it is cleared, so several can
+/// be set: the one that explains a wake up best is reported.
+///
+/// ```
+/// # use microbit_common as microbit;
+/// use microbit::power::ResetReason;
+///
+/// assert_eq!(ResetReason::from_bits(0), ResetReason::PowerOn);
+/// assert_eq!(ResetReason::from_bits(1 << 17), ResetReason::SoundWake);
+/// // a wake up by sound while a debugger is attached
+/// assert_eq!(ResetReason::from_bits(1 << 17 | 1 << 18), ResetReason::SoundWake);
+/// assert_eq!(ResetReason::from_bits(1 << 0), ResetReason::Pin);
+/// assert_eq!(ResetReason::from_bits(1 << 0 | 1 << 3), ResetReason::Lockup);
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ResetReason {
+    /// The supply was switched on, no other reason is recorded
+    PowerOn,
+    /// Woken from System OFF by the low power comparator
+    SoundWake,
+    /// Woken from System OFF by a GPIO pin, like a button
+    PinWake,
+    /// Woken from System OFF by the debug interface
+    DebugWake,
+    /// The reset pin, like the reset button
+    Pin,
+    /// The watchdog timer
+    Watchdog,
+    /// A soft reset by the CPU
+    Soft,
+    /// The CPU locked up
+    Lockup,
+}
+
+impl ResetReason {
+    /// Decode the bits of the RESETREAS register
+    pub fn from_bits(bits: u32) -> Self {
+        const REASONS: [(u32, ResetReason); 7] = [
+            (1 << 17, ResetReason::SoundWake),
+            (1 << 16, ResetReason::PinWake),
+            (1 << 3, ResetReason::Lockup),
+            (1 << 1, ResetReason::Watchdog),
+            (1 << 2, ResetReason::Soft),
+            (1 << 0, ResetReason::Pin),
+            (1 << 18, ResetReason::DebugWake),
+        ];
+        REASONS
+            .iter()
+            .find(|(mask, _)| bits & mask != 0)
+            .map_or(ResetReason::PowerOn, |&(_, reason)| reason)
+    }
+
+    /// Read and clear the reason of the last start
+    ///
+    /// Call this early after the start: the register is only cleared here.
+    pub fn take(power: &POWER) -> Self {
+        let bits = power.resetreas.read().bits();
+        // the bits are cleared by writing 1s
+        power.resetreas.write(|w| unsafe { w.bits(bits) });
+        Self::from_bits(bits)
+    }
+}
@@ -0,0 +1,185 @@
+//! Pulse length measurement on edge pins
+//!
+//! [`PulseIn`] measures how long a pin stays at a level, like Arduino's
+//! `pulseIn`. The edges are timestamped in hardware: a GPIOTE channel
+//! watching the pin triggers a capture of a free-running TIMER through PPI.
+//! The CPU only has to notice each edge before the next one arrives, so the
+//! result is accurate to the microsecond regardless of interrupt latency.
+//!
+//! The timer runs at 1 MHz in 16 bit mode which works on every TIMER
+//! instance. Pulses can therefore be at most [`MAX_PULSE_US`] long.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use microbit_common as microbit;
+//! use microbit::{
+//!     channels::{GpioteChannels, PpiChannels},
+//!     hal::gpio::Level,
+//!     pulse::PulseIn,
+//!     Board,
+//! };
+//!
+//! let board = Board::take().unwrap();
+//! let gpiote = GpioteChannels::new(board.GPIOTE);
+//! let ppi = PpiChannels::new(board.PPI);
+//! let pin = board.edge.e01.into_floating_input().degrade();
+//!
+//! let mut pulse_in = PulseIn::new(board.TIMER1, gpiote.channel0, ppi.ppi0);
+//! let _high_us = pulse_in.measure(&pin, Level::High, 100_000);
+//! ```
+use embedded_hal::digital::v2::InputPin;
+
+use crate::{
+    channels::{GpioteChannel, PpiChannel},
+    hal::{
+        gpio::Level,
+        gpiote::{EventPolarity, GpioteInputPin},
+        timer::Instance,
+    },
+    pac::timer0::RegisterBlock,
+};
+
+/// Longest pulse in µs that can be measured
+pub const MAX_PULSE_US: u32 = 0xffff;
+
+/// Pulse measurement errors
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The pulse did not start or end before the timeout
+    Timeout,
+    /// The pulse is longer than [`MAX_PULSE_US`]
+    TooLong,
+}
+
+/// Measures pulses on a pin using a TIMER, a GPIOTE and a PPI channel
+pub struct PulseIn<T: Instance> {
+    timer: T,
+    gpiote: GpioteChannel,
+    ppi: PpiChannel,
+}
+
+impl<T: Instance> PulseIn<T> {
+    /// Take the resources and start the timer
+    pub fn new(timer: T, gpiote: GpioteChannel, mut ppi: PpiChannel) -> Self {
+        let regs = timer.as_timer0();
+        regs.tasks_stop.write(|w| unsafe { w.bits(1) });
+        regs.tasks_clear.write(|w| unsafe { w.bits(1) });
+        regs.mode.write(|w| w.mode().timer());
+        regs.bitmode.write(|w| w.bitmode()._16bit());
+        // 16 MHz / 2^4 = 1 MHz
+        regs.prescaler.write(|w| unsafe { w.bits(4) });
+        regs.shorts.reset();
+        regs.intenclr.write(|w| unsafe { w.bits(0xffff_ffff) });
+        regs.tasks_start.write(|w| unsafe { w.bits(1) });
+
+        // every edge seen by the GPIOTE channel captures the counter into CC[0]
+        ppi.set_event_endpoint(gpiote.event());
+        ppi.set_task_endpoint(&regs.tasks_capture[0]);
+        ppi.enable();
+
+        Self { timer, gpiote, ppi }
+    }
+
+    /// Measure the length of the next pulse at `level` on `pin` in µs
+    ///
+    /// A pulse which is already in progress is skipped. `timeout_us` covers
+    /// both waiting for the pulse and the pulse itself.
+    pub fn measure<P>(&mut self, pin: &P, level: Level, timeout_us: u32) -> Result<u32, Error>
+    where
+        P: GpioteInputPin + InputPin,
+    {
+        let pulse_high = level == Level::High;
+        let at_level = |pin: &P| pin.is_high().is_ok_and(|high| high == pulse_high);
+
+        self.gpiote.input_pin(pin, EventPolarity::Toggle);
+        let result = self.time_pulse(timeout_us, || at_level(pin));
+        self.gpiote.disable();
+        result
+    }
+
+    fn time_pulse(&self, timeout_us: u32, at_level: impl Fn() -> bool) -> Result<u32, Error> {
+        let mut clock = Clock::new(self.timer.as_timer0());
+
+        // edges from here on are kept, so a pulse which starts right away
+        // is not missed
+        self.gpiote.reset_events();
+        if at_level() && !self.gpiote.is_event_triggered() {
+            // skip the pulse in progress
+            self.wait_for_edge(&mut clock, timeout_us, || !at_level())?;
+        }
+
+        let start = self.wait_for_edge(&mut clock, timeout_us, &at_level)?;
+        let since_start = clock.elapsed;
+        let end = self.wait_for_edge(&mut clock, timeout_us, || !at_level())?;
+
+        if clock.elapsed - since_start > MAX_PULSE_US {
+            return Err(Error::TooLong);
+        }
+        Ok(u32::from(end.wrapping_sub(start)))
+    }
+
+    /// Wait for an edge after which `done` holds and return its timestamp
+    fn wait_for_edge(
+        &self,
+        clock: &mut Clock,
+        timeout_us: u32,
+        done: impl Fn() -> bool,
+    ) -> Result<u16, Error> {
+        loop {
+            clock.check(timeout_us)?;
+            if self.gpiote.is_event_triggered() {
+                self.gpiote.reset_events();
+                if done() {
+                    return Ok(self.timer.as_timer0().cc[0].read().bits() as u16);
+                }
+            }
+        }
+    }
+
+    /// Stop the timer and release the resources
+    pub fn free(mut self) -> (T, GpioteChannel, PpiChannel) {
+        self.ppi.disable();
+        self.timer
+            .as_timer0()
+            .tasks_stop
+            .write(|w| unsafe { w.bits(1) });
+        (self.timer, self.gpiote, self.ppi)
+    }
+}
+
+/// Software view of the 16 bit timer which keeps counting beyond the wrap
+struct Clock<'a> {
+    timer: &'a RegisterBlock,
+    last: u16,
+    elapsed: u32,
+}
+
+impl<'a> Clock<'a> {
+    fn new(timer: &'a RegisterBlock) -> Self {
+        let mut clock = Self {
+            timer,
+            last: 0,
+            elapsed: 0,
+        };
+        clock.last = clock.now();
+        clock
+    }
+
+    fn now(&self) -> u16 {
+        self.timer.tasks_capture[1].write(|w| unsafe { w.bits(1) });
+        self.timer.cc[1].read().bits() as u16
+    }
+
+    /// Advance the elapsed time and fail once it exceeds `timeout_us`
+    fn check(&mut self, timeout_us: u32) -> Result<(), Error> {
+        let now = self.now();
+        self.elapsed += u32::from(now.wrapping_sub(self.last));
+        self.last = now;
+        if self.elapsed > timeout_us {
+            Err(Error::Timeout)
+        } else {
+            Ok(())
+        }
+    }
+}
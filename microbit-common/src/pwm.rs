@@ -0,0 +1,323 @@
+//! Software PWM on any edge pin
+//!
+//! The nRF51 has no PWM peripheral. [`Pwm`] generates up to three PWM
+//! signals from one TIMER, in the same way as the `servo` and `beeper`
+//! examples: CC\[3\] defines the period and clears the timer, CC\[0\] to
+//! CC\[2\] define the time each output is high. Two PPI channels per output
+//! toggle the pin through its GPIOTE channel at the start of the period and
+//! at the end of the high time.
+//!
+//! ## Updating the duty cycle
+//!
+//! Changing a compare register while the timer runs can make an output miss
+//! a toggle and invert it. New duty cycles and periods are therefore only
+//! applied at the start of a period from the timer interrupt: call
+//! [`Pwm::handle_pwm_event()`] in the interrupt handler of the timer you are
+//! using. The interrupt is only enabled while a change is pending.
+//!
+//! The interrupt may run late, after the old or the new high time has
+//! already ended. It therefore reads the counter and the level of the pin
+//! and corrects the level before it writes the new compare value. If the
+//! new high time ends too soon after the counter value it read, the pin
+//! is driven low for the rest of the period and the change is applied in
+//! the next one.
+//!
+//! High times below [`MIN_HIGH_US`] are extended to that value to leave the
+//! interrupt enough time to update the compare registers. A high time of 0
+//! or of at least the period drives the pin statically.
+//!
+//! [`Pwm::free`] stops the timer and hands back the pins with their GPIOTE
+//! and PPI channels.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! # use microbit_common as microbit;
+//! use microbit::{
+//!     channels::{GpioteChannels, PpiChannels},
+//!     hal::gpio::Level,
+//!     pwm::{Channel, Pwm, PwmPin},
+//!     Board,
+//! };
+//!
+//! let board = Board::take().unwrap();
+//! let gpiote = GpioteChannels::new(board.GPIOTE);
+//! let ppi = PpiChannels::new(board.PPI);
+//!
+//! // 1 kHz
+//! let mut pwm = Pwm::new(board.TIMER1, 1000);
+//! pwm.attach(
+//!     Channel::C0,
+//!     PwmPin {
+//!         pin: board.edge.e00.into_push_pull_output(Level::Low).degrade(),
+//!         gpiote: gpiote.channel0,
+//!         ppi: [ppi.ppi0, ppi.ppi1],
+//!     },
+//! );
+//! // roughly a quarter of the brightness of an LED
+//! pwm.analog_write(Channel::C0, 256);
+//!
+//! // in the TIMER1 interrupt
+//! {
+//!     pwm.handle_pwm_event();
+//! }
+//! ```
+use embedded_hal::digital::v2::InputPin;
+
+use crate::{
+    channels::{GpioteChannel, PpiChannel},
+    hal::{
+        gpio::{Floating, Input, Level, Output, Pin, PushPull},
+        gpiote::TaskOutPolarity,
+        timer::Instance,
+    },
+};
+
+/// Number of outputs per TIMER
+pub const NUM_CHANNELS: usize = 3;
+
+/// Shortest high time in µs of a PWM signal that is not statically low
+pub const MIN_HIGH_US: u16 = 10;
+
+/// Largest value accepted by [`Pwm::analog_write`]
+pub const ANALOG_MAX: u16 = 1023;
+
+/// Compare register holding the period
+const PERIOD_CC: usize = 3;
+
+/// Time in µs the interrupt needs from checking the counter to writing a
+/// compare register
+const MARGIN_US: u32 = 5;
+
+/// PWM output of a [`Pwm`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Channel {
+    /// Output driven from CC\[0\]
+    C0,
+    /// Output driven from CC\[1\]
+    C1,
+    /// Output driven from CC\[2\]
+    C2,
+}
+
+impl Channel {
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// A pin with the GPIOTE and PPI channels needed to drive it
+pub struct PwmPin {
+    /// Output pin
+    pub pin: Pin<Output<PushPull>>,
+    /// GPIOTE channel toggling the pin
+    pub gpiote: GpioteChannel,
+    /// PPI channels connecting the timer to the GPIOTE channel
+    pub ppi: [PpiChannel; 2],
+}
+
+struct Attached {
+    pwm_pin: PwmPin,
+    high_us: u16,
+    // whether the PPI channels toggle the pin
+    toggling: bool,
+}
+
+/// Software PWM driving up to three pins from one TIMER
+pub struct Pwm<T: Instance> {
+    timer: T,
+    outputs: [Option<Attached>; NUM_CHANNELS],
+    period_us: u16,
+}
+
+impl<T: Instance> Pwm<T> {
+    /// Take the timer and start it with a period in µs
+    pub fn new(timer: T, period_us: u16) -> Self {
+        let regs = timer.as_timer0();
+        regs.tasks_stop.write(|w| unsafe { w.bits(1) });
+        regs.tasks_clear.write(|w| unsafe { w.bits(1) });
+        regs.mode.write(|w| w.mode().timer());
+        regs.bitmode.write(|w| w.bitmode()._16bit());
+        // 16 MHz / 2^4 = 1 MHz
+        regs.prescaler.write(|w| unsafe { w.bits(4) });
+        regs.cc[PERIOD_CC].write(|w| unsafe { w.bits(period_us.into()) });
+        regs.shorts.write(|w| w.compare3_clear().enabled());
+        regs.intenclr.write(|w| unsafe { w.bits(0xffff_ffff) });
+        regs.tasks_start.write(|w| unsafe { w.bits(1) });
+
+        Self {
+            timer,
+            outputs: [None, None, None],
+            period_us,
+        }
+    }
+
+    /// Current period in µs
+    pub fn period_us(&self) -> u16 {
+        self.period_us
+    }
+
+    /// Change the period, applied at the start of the next period
+    ///
+    /// The high times of the outputs are kept.
+    pub fn set_period_us(&mut self, period_us: u16) {
+        self.period_us = period_us;
+        self.request_update();
+    }
+
+    /// Start driving a pin from `channel`
+    ///
+    /// The pin keeps its level until the start of the next period. Returns
+    /// the pin if the channel is already in use.
+    pub fn attach(&mut self, channel: Channel, pwm_pin: PwmPin) -> Result<(), PwmPin> {
+        let slot = &mut self.outputs[channel.index()];
+        if slot.is_some() {
+            return Err(pwm_pin);
+        }
+        *slot = Some(Attached {
+            pwm_pin,
+            high_us: 0,
+            toggling: false,
+        });
+        self.request_update();
+        Ok(())
+    }
+
+    /// Stop driving the pin of `channel` and return it with its channels
+    ///
+    /// The pin keeps the level it had when it was detached.
+    pub fn detach(&mut self, channel: Channel) -> Option<PwmPin> {
+        let Attached { mut pwm_pin, .. } = self.outputs[channel.index()].take()?;
+        for ppi in &mut pwm_pin.ppi {
+            ppi.disable();
+        }
+        pwm_pin.gpiote.disable();
+        Some(pwm_pin)
+    }
+
+    /// Time in µs the output of `channel` is high during each period
+    pub fn high_us(&self, channel: Channel) -> Option<u16> {
+        self.outputs[channel.index()]
+            .as_ref()
+            .map(|output| output.high_us)
+    }
+
+    /// Set the time in µs the output of `channel` is high during each period
+    ///
+    /// Applied at the start of the next period.
+    pub fn set_high_us(&mut self, channel: Channel, high_us: u16) {
+        if let Some(output) = &mut self.outputs[channel.index()] {
+            output.high_us = high_us;
+            self.request_update();
+        }
+    }
+
+    /// Set the duty cycle of `channel` on a scale from 0 to [`ANALOG_MAX`]
+    pub fn analog_write(&mut self, channel: Channel, value: u16) {
+        let value = u32::from(value.min(ANALOG_MAX));
+        let high_us = u32::from(self.period_us) * value / u32::from(ANALOG_MAX);
+        self.set_high_us(channel, high_us as u16);
+    }
+
+    /// Apply pending changes at the start of a period
+    ///
+    /// Call this in the interrupt handler of the timer. It clears the
+    /// timer's event registers.
+    pub fn handle_pwm_event(&mut self) {
+        let regs = self.timer.as_timer0();
+        if regs.events_compare[PERIOD_CC].read().bits() == 0 {
+            return;
+        }
+        regs.events_compare[PERIOD_CC].write(|w| unsafe { w.bits(0) });
+        regs.intenclr.write(|w| w.compare3().set_bit());
+
+        regs.cc[PERIOD_CC].write(|w| unsafe { w.bits(self.period_us.into()) });
+        let mut late = false;
+        for (index, output) in self.outputs.iter_mut().enumerate() {
+            let Some(Attached {
+                pwm_pin,
+                high_us,
+                toggling,
+            }) = output
+            else {
+                continue;
+            };
+            let PwmPin { pin, gpiote, ppi } = pwm_pin;
+            if *high_us == 0 || *high_us >= self.period_us {
+                let level = if *high_us == 0 {
+                    Level::Low
+                } else {
+                    Level::High
+                };
+                for ppi in ppi.iter_mut() {
+                    ppi.disable();
+                }
+                gpiote.output_pin(pin, TaskOutPolarity::Toggle, level);
+                *toggling = false;
+            } else {
+                let high_us = u32::from((*high_us).max(MIN_HIGH_US));
+                // The interrupt may run late, even after the end of the old
+                // high time. Capturing the counter into the compare register
+                // of the output stops its toggles for the rest of the period,
+                // so the pin keeps its level while it is checked.
+                regs.tasks_capture[index].write(|w| unsafe { w.bits(1) });
+                let now = regs.cc[index].read().bits();
+                let on_time = high_us >= now + MARGIN_US;
+                let level = if on_time { Level::High } else { Level::Low };
+                if *toggling {
+                    if is_high(pin) != on_time {
+                        gpiote.output_pin(pin, TaskOutPolarity::Toggle, level);
+                    }
+                } else {
+                    gpiote.output_pin(pin, TaskOutPolarity::Toggle, level);
+                    let [start, end] = ppi;
+                    start.set_event_endpoint(&regs.events_compare[PERIOD_CC]);
+                    start.set_task_endpoint(gpiote.task_out());
+                    start.enable();
+                    end.set_event_endpoint(&regs.events_compare[index]);
+                    end.set_task_endpoint(gpiote.task_out());
+                    end.enable();
+                    *toggling = true;
+                }
+                if on_time {
+                    regs.cc[index].write(|w| unsafe { w.bits(high_us) });
+                } else {
+                    // too late for this period, the pin stays low until the
+                    // next one
+                    late = true;
+                }
+            }
+        }
+        if late {
+            self.request_update();
+        }
+    }
+
+    fn request_update(&mut self) {
+        self.timer
+            .as_timer0()
+            .intenset
+            .write(|w| w.compare3().set());
+    }
+
+    /// Stop the timer and release it with all attached pins
+    pub fn free(mut self) -> (T, [Option<PwmPin>; NUM_CHANNELS]) {
+        let pins = [
+            self.detach(Channel::C0),
+            self.detach(Channel::C1),
+            self.detach(Channel::C2),
+        ];
+        let regs = self.timer.as_timer0();
+        regs.tasks_stop.write(|w| unsafe { w.bits(1) });
+        regs.intenclr.write(|w| unsafe { w.bits(0xffff_ffff) });
+        regs.shorts.reset();
+        (self.timer, pins)
+    }
+}
+
+/// Level of an output pin, as driven by its GPIOTE channel
+fn is_high(pin: &Pin<Output<PushPull>>) -> bool {
+    // the input buffer of the pin stays connected, the alias only reads it
+    let input: Pin<Input<Floating>> = unsafe { Pin::from_psel_bits(pin.psel_bits()) };
+    input.is_high().unwrap_or(false)
+}
@@ -0,0 +1,155 @@
+//! Ultrasonic distance sensors
+//!
+//! Supports the HC-SR04 with separate trigger and echo pins and the Grove
+//! ultrasonic ranger which uses a single signal pin for both. The sensor
+//! answers a trigger pulse with an echo pulse as long as the sound takes to
+//! travel to the obstacle and back, which is measured with
+//! [`PulseIn`](crate::pulse::PulseIn).
+//!
+//! The HC-SR04 needs 5V and its echo output must be level shifted to 3.3V
+//! before it is connected to the Calliope mini.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use microbit_common as microbit;
+//! use microbit::{
+//!     channels::{GpioteChannels, PpiChannels},
+//!     hal::{gpio::Level, Timer},
+//!     pulse::PulseIn,
+//!     ranger::Ranger,
+//!     Board,
+//! };
+//!
+//! let board = Board::take().unwrap();
+//! let gpiote = GpioteChannels::new(board.GPIOTE);
+//! let ppi = PpiChannels::new(board.PPI);
+//! let mut delay = Timer::new(board.TIMER0);
+//!
+//! let pulse_in = PulseIn::new(board.TIMER1, gpiote.channel0, ppi.ppi0);
+//! let mut ranger = Ranger::hc_sr04(
+//!     pulse_in,
+//!     board.edge.e01.into_push_pull_output(Level::Low).degrade(),
+//!     board.edge.e02.into_floating_input().degrade(),
+//! );
+//! let _mm = ranger.distance_mm(&mut delay);
+//! ```
+use embedded_hal::{blocking::delay::DelayUs, digital::v2::OutputPin};
+
+use crate::{
+    hal::{
+        gpio::{Disconnected, Floating, Input, Level, Output, Pin, PushPull},
+        timer::Instance,
+    },
+    pulse::{Error, PulseIn},
+};
+
+/// Echo timeout, a bit more than the round trip of the longest range of 4 m
+pub const ECHO_TIMEOUT_US: u32 = 30_000;
+
+/// Length of the trigger pulse
+const TRIGGER_US: u32 = 10;
+
+/// Convert an echo pulse length to the distance in mm
+///
+/// Sound travels 343 m/s at 20 °C, that is 0.343 mm/µs, and the echo
+/// covers the distance twice.
+///
+/// ```
+/// # use microbit_common as microbit;
+/// use microbit::ranger::echo_to_mm;
+///
+/// assert_eq!(echo_to_mm(5831), 1000);
+/// ```
+pub fn echo_to_mm(echo_us: u32) -> u32 {
+    echo_us * 343 / 2000
+}
+
+enum Wiring {
+    Separate {
+        trigger: Pin<Output<PushPull>>,
+        echo: Pin<Input<Floating>>,
+    },
+    Single(Option<Pin<Disconnected>>),
+}
+
+/// Ultrasonic distance sensor
+pub struct Ranger<T: Instance> {
+    pulse_in: PulseIn<T>,
+    wiring: Wiring,
+}
+
+/// Pins released by [`Ranger::free`]
+pub enum RangerPins {
+    /// HC-SR04 trigger and echo pins
+    Separate(Pin<Output<PushPull>>, Pin<Input<Floating>>),
+    /// Grove signal pin
+    Single(Pin<Disconnected>),
+}
+
+impl<T: Instance> Ranger<T> {
+    /// HC-SR04 style sensor with separate trigger and echo pins
+    pub fn hc_sr04(
+        pulse_in: PulseIn<T>,
+        trigger: Pin<Output<PushPull>>,
+        echo: Pin<Input<Floating>>,
+    ) -> Self {
+        Self {
+            pulse_in,
+            wiring: Wiring::Separate { trigger, echo },
+        }
+    }
+
+    /// Grove style sensor with a single signal pin
+    pub fn grove(pulse_in: PulseIn<T>, signal: Pin<Disconnected>) -> Self {
+        Self {
+            pulse_in,
+            wiring: Wiring::Single(Some(signal)),
+        }
+    }
+
+    /// Measure the length of the echo pulse in µs
+    ///
+    /// Leave at least 60 ms between measurements so that the echoes of the
+    /// previous one have faded.
+    pub fn echo_us<D: DelayUs<u32>>(&mut self, delay: &mut D) -> Result<u32, Error> {
+        match &mut self.wiring {
+            Wiring::Separate { trigger, echo } => {
+                trigger.set_high().ok();
+                delay.delay_us(TRIGGER_US);
+                trigger.set_low().ok();
+                self.pulse_in.measure(echo, Level::High, ECHO_TIMEOUT_US)
+            }
+            Wiring::Single(signal) => {
+                // the slot is only empty while a measurement is running
+                let mut trigger = signal
+                    .take()
+                    .ok_or(Error::Timeout)?
+                    .into_push_pull_output(Level::Low);
+                delay.delay_us(2);
+                trigger.set_high().ok();
+                delay.delay_us(TRIGGER_US);
+                trigger.set_low().ok();
+                let echo = trigger.into_floating_input();
+                let result = self.pulse_in.measure(&echo, Level::High, ECHO_TIMEOUT_US);
+                *signal = Some(echo.into_disconnected());
+                result
+            }
+        }
+    }
+
+    /// Measure the distance to the closest obstacle in mm
+    pub fn distance_mm<D: DelayUs<u32>>(&mut self, delay: &mut D) -> Result<u32, Error> {
+        self.echo_us(delay).map(echo_to_mm)
+    }
+
+    /// Release the pulse measurement and the pins
+    pub fn free(self) -> (PulseIn<T>, RangerPins) {
+        let pins = match self.wiring {
+            Wiring::Separate { trigger, echo } => RangerPins::Separate(trigger, echo),
+            // `echo_us` always puts the pin back
+            Wiring::Single(signal) => RangerPins::Single(signal.unwrap()),
+        };
+        (self.pulse_in, pins)
+    }
+}
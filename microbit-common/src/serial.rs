@@ -0,0 +1,284 @@
+//! Serial port switchable between the debugger and the Grove A1 connector
+//!
+//! The nRF51 has a single UART, which [`UartPins`] connect to the debugger's
+//! USB serial port. [`Serial`] can redirect it to the Grove A1 connector for
+//! GPS and other serial modules and back at runtime, like `serial.redirect`
+//! in MakeCode. The parity and baud rate are kept across a redirection.
+//!
+//! Bytes which are still being sent are finished before the lines switch,
+//! bytes which arrive during the switch are lost. The debugger's TX line is
+//! held high while it is not used so that the debugger does not see a break,
+//! the Grove pins are disconnected again when the UART leaves them.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use microbit_common as microbit;
+//! use core::fmt::Write;
+//!
+//! use microbit::{
+//!     hal::uart::{Baudrate, Parity},
+//!     serial::{Route, Serial},
+//!     Board,
+//! };
+//!
+//! let board = Board::take().unwrap();
+//! let mut serial = Serial::new(
+//!     board.UART0,
+//!     board.uart,
+//!     board.grove_a1,
+//!     Parity::EXCLUDED,
+//!     Baudrate::BAUD9600,
+//! );
+//!
+//! write!(serial, "reading the GPS\r\n").ok();
+//! serial.redirect(Route::Grove);
+//! // read NMEA sentences
+//! serial.redirect(Route::Usb);
+//! ```
+use core::{convert::Infallible, fmt};
+
+use embedded_hal::{digital::v2::OutputPin, serial};
+
+use crate::{
+    board::{GroveA1, UartPins},
+    hal::{
+        gpio::{Disconnected, Floating, Input, Level, Output, Pin, PushPull},
+        uart::{self, Baudrate, Parity},
+    },
+    pac::UART0,
+};
+
+/// Pins the UART is connected to
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Route {
+    /// The debugger's USB serial port, P0_24 and P0_25
+    Usb,
+    /// The Grove A1 connector, RX on C16 and TX on C17
+    Grove,
+}
+
+/// Errors of a received byte
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// A byte arrived before the previous one was read and was lost
+    Overrun,
+    /// The parity bit of the byte is wrong
+    Parity,
+    /// The byte has no valid stop bit
+    Framing,
+    /// The line was held low for longer than a byte
+    Break,
+}
+
+/// Grove A1 pins, connected only while the UART uses them
+enum GrovePins {
+    Disconnected(Pin<Disconnected>, Pin<Disconnected>),
+    Connected(Pin<Input<Floating>>, Pin<Output<PushPull>>),
+}
+
+impl GrovePins {
+    fn into_disconnected(self) -> (Pin<Disconnected>, Pin<Disconnected>) {
+        match self {
+            GrovePins::Disconnected(rx, tx) => (rx, tx),
+            GrovePins::Connected(rx, tx) => (rx.into_disconnected(), tx.into_disconnected()),
+        }
+    }
+}
+
+/// The UART with its debugger and Grove pins
+pub struct Serial {
+    uart: UART0,
+    usb_rx: Pin<Input<Floating>>,
+    usb_tx: Pin<Output<PushPull>>,
+    // the slot is only empty while the UART is being redirected
+    grove: Option<GrovePins>,
+    route: Route,
+    parity: Parity,
+    baudrate: Baudrate,
+    // a byte was written and TXDRDY tells when it is sent
+    sending: bool,
+}
+
+impl Serial {
+    /// Take the UART and start it on the debugger's serial port
+    pub fn new(
+        uart: UART0,
+        usb: UartPins,
+        grove: GroveA1,
+        parity: Parity,
+        baudrate: Baudrate,
+    ) -> Self {
+        let usb = uart::Pins::from(usb);
+        let (grove_rx, grove_tx) = grove.into_digital();
+        let serial = Self {
+            uart,
+            usb_rx: usb.rxd,
+            usb_tx: usb.txd,
+            grove: Some(GrovePins::Disconnected(
+                grove_rx.degrade(),
+                grove_tx.degrade(),
+            )),
+            route: Route::Usb,
+            parity,
+            baudrate,
+            sending: false,
+        };
+        serial.start();
+        serial
+    }
+
+    /// Pins the UART is connected to
+    pub fn route(&self) -> Route {
+        self.route
+    }
+
+    /// Connect the UART to other pins
+    ///
+    /// Waits until the last byte has been sent.
+    pub fn redirect(&mut self, route: Route) {
+        if route == self.route {
+            return;
+        }
+        self.stop();
+        // the slot is only empty during a redirection
+        let (rx, tx) = self.grove.take().unwrap().into_disconnected();
+        self.grove = Some(match route {
+            Route::Grove => {
+                // idle level of the debugger line
+                self.usb_tx.set_high().ok();
+                GrovePins::Connected(
+                    rx.into_floating_input(),
+                    tx.into_push_pull_output(Level::High),
+                )
+            }
+            Route::Usb => GrovePins::Disconnected(rx, tx),
+        });
+        self.route = route;
+        self.start();
+    }
+
+    /// Baud rate
+    pub fn baudrate(&self) -> Baudrate {
+        self.baudrate
+    }
+
+    /// Change the baud rate
+    ///
+    /// Waits until the last byte has been sent.
+    pub fn set_baudrate(&mut self, baudrate: Baudrate) {
+        self.stop();
+        self.baudrate = baudrate;
+        self.start();
+    }
+
+    fn start(&self) {
+        let (rxd, txd) = match &self.grove {
+            Some(GrovePins::Connected(rx, tx)) => (rx.pin(), tx.pin()),
+            _ => (self.usb_rx.pin(), self.usb_tx.pin()),
+        };
+        let uart = &self.uart;
+        uart.pselrxd.write(|w| unsafe { w.bits(rxd.into()) });
+        uart.pseltxd.write(|w| unsafe { w.bits(txd.into()) });
+        uart.pselcts.write(|w| unsafe { w.bits(0xffff_ffff) });
+        uart.pselrts.write(|w| unsafe { w.bits(0xffff_ffff) });
+        uart.baudrate.write(|w| w.baudrate().variant(self.baudrate));
+        uart.config
+            .write(|w| w.hwfc().disabled().parity().variant(self.parity));
+        uart.enable.write(|w| w.enable().enabled());
+        uart.tasks_starttx.write(|w| unsafe { w.bits(1) });
+        uart.tasks_startrx.write(|w| unsafe { w.bits(1) });
+    }
+
+    fn stop(&mut self) {
+        nb::block!(serial::Write::flush(self)).ok();
+        let uart = &self.uart;
+        uart.tasks_stoptx.write(|w| unsafe { w.bits(1) });
+        uart.tasks_stoprx.write(|w| unsafe { w.bits(1) });
+        uart.enable.write(|w| w.enable().disabled());
+    }
+
+    /// Stop the UART and release it with the debugger pins and the
+    /// disconnected Grove pins
+    pub fn free(mut self) -> (UART0, uart::Pins, Pin<Disconnected>, Pin<Disconnected>) {
+        self.stop();
+        let usb = uart::Pins {
+            rxd: self.usb_rx,
+            txd: self.usb_tx,
+            cts: None,
+            rts: None,
+        };
+        // the slot is only empty during a redirection
+        let (rx, tx) = self.grove.unwrap().into_disconnected();
+        (self.uart, usb, rx, tx)
+    }
+}
+
+impl serial::Read<u8> for Serial {
+    type Error = Error;
+
+    /// Read a received byte
+    ///
+    /// After an overrun the byte which was kept can still be read, after
+    /// the other errors the broken byte is dropped.
+    fn read(&mut self) -> nb::Result<u8, Self::Error> {
+        if self.uart.events_error.read().bits() != 0 {
+            self.uart.events_error.write(|w| unsafe { w.bits(0) });
+            let source = self.uart.errorsrc.read();
+            // the sources are cleared by writing 1s
+            self.uart
+                .errorsrc
+                .write(|w| unsafe { w.bits(source.bits()) });
+            let error = if source.break_().is_present() {
+                Error::Break
+            } else if source.framing().is_present() {
+                Error::Framing
+            } else if source.parity().is_present() {
+                Error::Parity
+            } else {
+                Error::Overrun
+            };
+            if error != Error::Overrun && self.uart.events_rxdrdy.read().bits() != 0 {
+                self.uart.events_rxdrdy.write(|w| unsafe { w.bits(0) });
+                self.uart.rxd.read();
+            }
+            return Err(nb::Error::Other(error));
+        }
+        if self.uart.events_rxdrdy.read().bits() == 0 {
+            return Err(nb::Error::WouldBlock);
+        }
+        self.uart.events_rxdrdy.write(|w| unsafe { w.bits(0) });
+        Ok(self.uart.rxd.read().bits() as u8)
+    }
+}
+
+impl serial::Write<u8> for Serial {
+    type Error = Infallible;
+
+    fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
+        serial::Write::flush(self)?;
+        self.uart.txd.write(|w| unsafe { w.bits(byte.into()) });
+        self.sending = true;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        if self.sending {
+            if self.uart.events_txdrdy.read().bits() == 0 {
+                return Err(nb::Error::WouldBlock);
+            }
+            self.uart.events_txdrdy.write(|w| unsafe { w.bits(0) });
+            self.sending = false;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Write for Serial {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            nb::block!(serial::Write::write(self, byte)).ok();
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,265 @@
+//! Hobby servos
+//!
+//! [`Servos`] drives up to three servos from one TIMER using the
+//! [software PWM](crate::pwm) with the usual 20 ms period. Positions are
+//! given as angles and converted to pulse lengths with a per-servo
+//! [`Calibration`].
+//!
+//! Moves can be speed limited: [`Servos::move_to`] advances the pulse length
+//! a little every period until the target angle is reached. Call
+//! [`Servos::handle_servo_event()`] in the interrupt handler of the timer
+//! you are using, it applies new pulse lengths at the start of a period.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! # use microbit_common as microbit;
+//! use microbit::{
+//!     channels::{GpioteChannels, PpiChannels},
+//!     hal::gpio::Level,
+//!     pwm::{Channel, PwmPin},
+//!     servo::{Calibration, Servos},
+//!     Board,
+//! };
+//!
+//! let board = Board::take().unwrap();
+//! let gpiote = GpioteChannels::new(board.GPIOTE);
+//! let ppi = PpiChannels::new(board.PPI);
+//!
+//! let mut servos = Servos::new(board.TIMER1);
+//! servos.attach(
+//!     Channel::C0,
+//!     PwmPin {
+//!         pin: board.edge.e01.into_push_pull_output(Level::Low).degrade(),
+//!         gpiote: gpiote.channel0,
+//!         ppi: [ppi.ppi0, ppi.ppi1],
+//!     },
+//!     Calibration::default(),
+//! );
+//! servos.set_angle(Channel::C0, 90);
+//! // turn to 180° at 60°/s
+//! servos.move_to(Channel::C0, 180, 60);
+//!
+//! // in the TIMER1 interrupt
+//! {
+//!     servos.handle_servo_event();
+//! }
+//! ```
+use embedded_hal::digital::v2::OutputPin;
+
+use crate::{
+    hal::timer::Instance,
+    pwm::{Channel, Pwm, PwmPin, NUM_CHANNELS},
+};
+
+/// Servo PWM period in µs
+pub const PERIOD_US: u16 = 20_000;
+
+/// Relation between angle and pulse length of a servo
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Calibration {
+    /// Pulse length in µs at 0°
+    pub min_us: u16,
+    /// Pulse length in µs at `range_degrees`
+    pub max_us: u16,
+    /// Largest angle the servo can turn to
+    pub range_degrees: u16,
+}
+
+impl Default for Calibration {
+    /// 0.5 ms to 2.5 ms for 0° to 180°
+    fn default() -> Self {
+        Self {
+            min_us: 500,
+            max_us: 2500,
+            range_degrees: 180,
+        }
+    }
+}
+
+impl Calibration {
+    /// Pulse length in µs for an angle, clamped to the range of the servo
+    ///
+    /// ```
+    /// # use microbit_common as microbit;
+    /// use microbit::servo::Calibration;
+    ///
+    /// let calibration = Calibration::default();
+    /// assert_eq!(calibration.pulse_us(90), 1500);
+    /// assert_eq!(calibration.pulse_us(270), 2500);
+    /// ```
+    pub fn pulse_us(&self, degrees: u16) -> u16 {
+        let degrees = u32::from(degrees.min(self.range_degrees));
+        let span = i32::from(self.max_us) - i32::from(self.min_us);
+        let offset = span * degrees as i32 / i32::from(self.range_degrees.max(1));
+        (i32::from(self.min_us) + offset) as u16
+    }
+
+    /// Angle for a pulse length in µs, rounded down
+    pub fn degrees(&self, pulse_us: u16) -> u16 {
+        let span = i32::from(self.max_us) - i32::from(self.min_us);
+        if span == 0 {
+            return 0;
+        }
+        let offset = i32::from(pulse_us) - i32::from(self.min_us);
+        (offset * i32::from(self.range_degrees) / span).clamp(0, self.range_degrees.into()) as u16
+    }
+
+    /// Pulse length change in µs per period for a speed in °/s, at least
+    /// 1 µs
+    ///
+    /// ```
+    /// # use microbit_common as microbit;
+    /// use microbit::servo::Calibration;
+    ///
+    /// let calibration = Calibration::default();
+    /// assert_eq!(calibration.step_us(60), 13);
+    /// assert_eq!(calibration.step_us(500), 111);
+    /// assert_eq!(calibration.step_us(u16::MAX), 14563);
+    /// assert_eq!(calibration.step_us(0), 1);
+    /// ```
+    pub fn step_us(&self, speed_dps: u16) -> u16 {
+        let span = (i32::from(self.max_us) - i32::from(self.min_us)).unsigned_abs();
+        let step = u64::from(span) * u64::from(speed_dps) * u64::from(PERIOD_US)
+            / (u64::from(self.range_degrees.max(1)) * 1_000_000);
+        step.clamp(1, u16::MAX.into()) as u16
+    }
+}
+
+#[derive(Copy, Clone)]
+struct State {
+    calibration: Calibration,
+    pulse_us: u16,
+    target_us: u16,
+    step_us: u16,
+}
+
+/// Up to three servos driven from one TIMER
+pub struct Servos<T: Instance> {
+    pwm: Pwm<T>,
+    states: [Option<State>; NUM_CHANNELS],
+}
+
+impl<T: Instance> Servos<T> {
+    /// Take the timer and start it with the servo period
+    pub fn new(timer: T) -> Self {
+        Self {
+            pwm: Pwm::new(timer, PERIOD_US),
+            states: [None; NUM_CHANNELS],
+        }
+    }
+
+    /// Connect a servo to `channel`
+    ///
+    /// The servo receives no pulses, and so holds no position, until an
+    /// angle is set. Returns the pin if the channel is already in use.
+    pub fn attach(
+        &mut self,
+        channel: Channel,
+        pwm_pin: PwmPin,
+        calibration: Calibration,
+    ) -> Result<(), PwmPin> {
+        self.pwm.attach(channel, pwm_pin)?;
+        self.states[channel as usize] = Some(State {
+            calibration,
+            pulse_us: 0,
+            target_us: 0,
+            step_us: 0,
+        });
+        Ok(())
+    }
+
+    /// Stop the pulses to the servo and release its pin
+    ///
+    /// The servo is no longer driven and can be turned by hand.
+    pub fn detach(&mut self, channel: Channel) -> Option<PwmPin> {
+        self.states[channel as usize] = None;
+        let mut pwm_pin = self.pwm.detach(channel)?;
+        pwm_pin.pin.set_low().ok();
+        Some(pwm_pin)
+    }
+
+    /// Change the calibration of a servo
+    ///
+    /// The current angle is kept.
+    pub fn set_calibration(&mut self, channel: Channel, calibration: Calibration) {
+        let angle = self.angle(channel);
+        if let Some(state) = &mut self.states[channel as usize] {
+            state.calibration = calibration;
+            if let Some(angle) = angle {
+                self.set_angle(channel, angle);
+            }
+        }
+    }
+
+    /// Current angle of a servo, `None` if it is not driven
+    pub fn angle(&self, channel: Channel) -> Option<u16> {
+        let state = self.states[channel as usize]?;
+        (state.pulse_us != 0).then(|| state.calibration.degrees(state.pulse_us))
+    }
+
+    /// Turn a servo to an angle as fast as it can
+    pub fn set_angle(&mut self, channel: Channel, degrees: u16) {
+        if let Some(state) = &mut self.states[channel as usize] {
+            state.pulse_us = state.calibration.pulse_us(degrees);
+            state.target_us = state.pulse_us;
+            self.pwm.set_high_us(channel, state.pulse_us);
+        }
+    }
+
+    /// Turn a servo to an angle with a speed limit in °/s
+    ///
+    /// A servo which is not driven yet jumps to the angle.
+    pub fn move_to(&mut self, channel: Channel, degrees: u16, speed_dps: u16) {
+        match &mut self.states[channel as usize] {
+            Some(state) if state.pulse_us != 0 => {
+                state.target_us = state.calibration.pulse_us(degrees);
+                state.step_us = state.calibration.step_us(speed_dps);
+                // the first step is taken at the start of the next period
+                self.pwm.set_high_us(channel, state.pulse_us);
+            }
+            _ => self.set_angle(channel, degrees),
+        }
+    }
+
+    /// The servo is still moving towards the target of [`move_to`](Servos::move_to)
+    pub fn is_moving(&self, channel: Channel) -> bool {
+        self.states[channel as usize].is_some_and(|state| state.pulse_us != state.target_us)
+    }
+
+    /// Apply new pulse lengths and advance speed limited moves
+    ///
+    /// Call this in the interrupt handler of the timer. It clears the
+    /// timer's event registers.
+    pub fn handle_servo_event(&mut self) {
+        self.pwm.handle_pwm_event();
+        for (channel, state) in [Channel::C0, Channel::C1, Channel::C2]
+            .into_iter()
+            .zip(self.states.iter_mut())
+        {
+            let Some(state) = state else {
+                continue;
+            };
+            if state.pulse_us == state.target_us {
+                continue;
+            }
+            state.pulse_us = if state.pulse_us < state.target_us {
+                state
+                    .pulse_us
+                    .saturating_add(state.step_us)
+                    .min(state.target_us)
+            } else {
+                state
+                    .pulse_us
+                    .saturating_sub(state.step_us)
+                    .max(state.target_us)
+            };
+            self.pwm.set_high_us(channel, state.pulse_us);
+        }
+    }
+
+    /// Stop the timer and release it with all attached pins
+    pub fn free(self) -> (T, [Option<PwmPin>; NUM_CHANNELS]) {
+        self.pwm.free()
+    }
+}
@@ -0,0 +1,359 @@
+//! Square wave tones on the speaker
+//!
+//! The speaker sits between the two outputs of the DRV8837 H-bridge. The
+//! outputs are driven in antiphase, IN1 high during the first half of each
+//! period and IN2 during the second, which doubles the voltage swing over a
+//! single-ended drive.
+//!
+//! A TIMER generates the waveform without the CPU: CC\[3\] holds the period
+//! and clears the timer, four PPI channels toggle IN1 at CC\[0\] and CC\[1\]
+//! and IN2 at CC\[2\] and CC\[3\] through two GPIOTE channels.
+//!
+//! The volume sets how long each output is high within its half of the
+//! period. The period stays the same, so the frequency does not change with
+//! the volume.
+//!
+//! A new frequency or volume of a tone being played takes effect in place:
+//! the timer stands still for a moment, keeps its position within the
+//! period and only the inputs whose level does not fit the new waveform
+//! are switched. The H-bridge only sleeps while there is no tone.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use microbit_common as microbit;
+//! use microbit::{
+//!     channels::{GpioteChannels, PpiChannels},
+//!     sound::Beeper,
+//!     Board,
+//! };
+//!
+//! let board = Board::take().unwrap();
+//! let gpiote = GpioteChannels::new(board.GPIOTE);
+//! let ppi = PpiChannels::new(board.PPI);
+//! let mut beeper = Beeper::new(
+//!     board.TIMER0,
+//!     board.beeper_motor_pins,
+//!     [gpiote.channel0, gpiote.channel1],
+//!     [ppi.ppi0, ppi.ppi1, ppi.ppi2, ppi.ppi3],
+//! );
+//!
+//! // A4 for half a second, a pause and A5 until it is stopped
+//! beeper.set_volume(64);
+//! beeper.play_tone(440, 500);
+//! beeper.rest(250);
+//! beeper.start_tone(880);
+//! ```
+use embedded_hal::digital::v2::{InputPin, OutputPin, StatefulOutputPin};
+
+use crate::{
+    channels::{GpioteChannel, PpiChannel},
+    gpio::BeeperMotorPins,
+    hal::{
+        gpio::{Floating, Input, Level, Output, Pin, PushPull},
+        gpiote::TaskOutPolarity,
+        timer::Instance,
+    },
+};
+
+/// Lowest frequency that is played, lower ones are raised to it
+pub const MIN_FREQUENCY_HZ: u32 = 20;
+
+/// Highest frequency that is played, higher ones are lowered to it
+pub const MAX_FREQUENCY_HZ: u32 = 20_000;
+
+/// TIMER clock without prescaler
+const CLOCK_HZ: u32 = 16_000_000;
+
+/// Highest TIMER prescaler
+const MAX_PRESCALER: u8 = 9;
+
+/// Compare register holding the period
+const PERIOD_CC: usize = 3;
+
+/// TIMER settings for a tone
+///
+/// The prescaler is the smallest one whose period fits the 16 bit timer,
+/// which gives the finest frequency resolution.
+///
+/// ```
+/// # use microbit_common as microbit;
+/// use microbit::sound::ToneTiming;
+///
+/// let timing = ToneTiming::new(220);
+/// assert_eq!((timing.prescaler, timing.period), (1, 36364));
+/// assert_eq!(timing.frequency_hz(), 220);
+/// // the whole audible range fits the 16 bit timer
+/// assert_eq!(ToneTiming::new(20).prescaler, 4);
+/// assert_eq!(ToneTiming::new(20_000).period, 800);
+/// // out of range frequencies are clamped
+/// assert_eq!(ToneTiming::new(5), ToneTiming::new(20));
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ToneTiming {
+    /// The 16 MHz clock is divided by 2^prescaler
+    pub prescaler: u8,
+    /// Period in timer ticks
+    pub period: u16,
+}
+
+impl ToneTiming {
+    /// Timing of the tone closest to `frequency_hz`
+    pub fn new(frequency_hz: u32) -> Self {
+        let frequency_hz = frequency_hz.clamp(MIN_FREQUENCY_HZ, MAX_FREQUENCY_HZ);
+        let mut prescaler = 0;
+        while prescaler < MAX_PRESCALER && (CLOCK_HZ >> prescaler) / frequency_hz > 0xffff {
+            prescaler += 1;
+        }
+        let period = ((CLOCK_HZ >> prescaler) + frequency_hz / 2) / frequency_hz;
+        Self {
+            prescaler,
+            period: period as u16,
+        }
+    }
+
+    /// Frequency that is played, rounded to Hz
+    pub fn frequency_hz(&self) -> u32 {
+        let period = u32::from(self.period);
+        ((CLOCK_HZ >> self.prescaler) + period / 2) / period
+    }
+
+    /// Compare values at `volume`: IN1 rises and falls, IN2 rises and falls
+    ///
+    /// The outputs fall at the middle and the end of the period and rise
+    /// earlier the higher the volume is. At full volume each one is high for
+    /// nearly half of the period.
+    ///
+    /// ```
+    /// # use microbit_common as microbit;
+    /// use microbit::sound::ToneTiming;
+    ///
+    /// let timing = ToneTiming::new(20_000);
+    /// assert_eq!(timing.compares(255), [1, 400, 401, 800]);
+    /// assert_eq!(timing.compares(128), [200, 400, 600, 800]);
+    /// assert_eq!(timing.compares(1), [399, 400, 799, 800]);
+    /// ```
+    pub fn compares(&self, volume: u8) -> [u32; 4] {
+        let period = u32::from(self.period);
+        let half = period / 2;
+        // CC[0] can not be 0, the compare event would be missed after the
+        // clear
+        let width = ((half - 1) * u32::from(volume) / 255).max(1);
+        [half - width, half, period - width, period]
+    }
+}
+
+impl BeeperMotorPins {
+    /// Drive the speaker, see [`Beeper::new`]
+    pub fn into_beeper<T: Instance>(
+        self,
+        timer: T,
+        gpiote: [GpioteChannel; 2],
+        ppi: [PpiChannel; 4],
+    ) -> Beeper<T> {
+        Beeper::new(timer, self, gpiote, ppi)
+    }
+}
+
+/// Tone generator on the speaker using a TIMER, two GPIOTE and four PPI
+/// channels
+pub struct Beeper<T: Instance> {
+    timer: T,
+    pins: BeeperMotorPins,
+    gpiote: [GpioteChannel; 2],
+    ppi: [PpiChannel; 4],
+    timing: Option<ToneTiming>,
+    volume: u8,
+}
+
+impl<T: Instance> Beeper<T> {
+    /// Take the resources, the H-bridge sleeps until a tone starts
+    pub fn new(
+        timer: T,
+        mut pins: BeeperMotorPins,
+        gpiote: [GpioteChannel; 2],
+        mut ppi: [PpiChannel; 4],
+    ) -> Self {
+        pins.motor_nsleep.set_low().ok();
+        pins.motor_in1.set_low().ok();
+        pins.motor_in2.set_low().ok();
+
+        let regs = timer.as_timer0();
+        regs.tasks_stop.write(|w| unsafe { w.bits(1) });
+        regs.tasks_clear.write(|w| unsafe { w.bits(1) });
+        regs.mode.write(|w| w.mode().timer());
+        regs.bitmode.write(|w| w.bitmode()._16bit());
+        regs.shorts.write(|w| w.compare3_clear().enabled());
+        regs.intenclr.write(|w| unsafe { w.bits(0xffff_ffff) });
+
+        for (index, ppi) in ppi.iter_mut().enumerate() {
+            ppi.set_event_endpoint(&regs.events_compare[index]);
+            ppi.set_task_endpoint(gpiote[index / 2].task_out());
+        }
+
+        Self {
+            timer,
+            pins,
+            gpiote,
+            ppi,
+            timing: None,
+            volume: u8::MAX,
+        }
+    }
+
+    /// Frequency of the tone being played
+    pub fn frequency_hz(&self) -> Option<u32> {
+        self.timing.map(|timing| timing.frequency_hz())
+    }
+
+    /// Volume from 0 for silence to 255, the default
+    pub fn volume(&self) -> u8 {
+        self.volume
+    }
+
+    /// Change the volume, also of the tone being played
+    pub fn set_volume(&mut self, volume: u8) {
+        self.volume = volume;
+        if let Some(timing) = self.timing {
+            self.start(timing);
+        }
+    }
+
+    /// Start a tone which plays until it is stopped
+    ///
+    /// A frequency of 0 stops the output.
+    pub fn start_tone(&mut self, frequency_hz: u32) {
+        if frequency_hz == 0 {
+            self.stop();
+        } else {
+            self.start(ToneTiming::new(frequency_hz));
+        }
+    }
+
+    fn start(&mut self, timing: ToneTiming) {
+        if self.volume > 0 && self.pins.motor_nsleep.is_set_high().unwrap_or(false) {
+            self.retune(timing);
+            return;
+        }
+        self.stop();
+        let regs = self.timer.as_timer0();
+        regs.prescaler
+            .write(|w| unsafe { w.bits(timing.prescaler.into()) });
+        for (cc, value) in regs.cc.iter().zip(timing.compares(self.volume)) {
+            cc.write(|w| unsafe { w.bits(value) });
+        }
+        // at volume 0 the timer only counts the periods
+        if self.volume > 0 {
+            for (gpiote, pin) in self.gpiote.iter_mut().zip(self.pins.gpiote_in_pins()) {
+                gpiote.output_pin(&pin, TaskOutPolarity::Toggle, Level::Low);
+            }
+            for ppi in &mut self.ppi {
+                ppi.enable();
+            }
+            self.pins.motor_nsleep.set_high().ok();
+        }
+        regs.tasks_start.write(|w| unsafe { w.bits(1) });
+        self.timing = Some(timing);
+    }
+
+    /// Switch the tone being played to `timing` at the current volume
+    fn retune(&mut self, timing: ToneTiming) {
+        let regs = self.timer.as_timer0();
+        // the inputs keep their levels while the timer stands still
+        regs.tasks_stop.write(|w| unsafe { w.bits(1) });
+        regs.tasks_capture[0].write(|w| unsafe { w.bits(1) });
+        let mut counter = regs.cc[0].read().bits();
+        let compares = timing.compares(self.volume);
+        if counter >= compares[PERIOD_CC] {
+            regs.tasks_clear.write(|w| unsafe { w.bits(1) });
+            counter = 0;
+        }
+        regs.prescaler
+            .write(|w| unsafe { w.bits(timing.prescaler.into()) });
+        for (cc, value) in regs.cc.iter().zip(compares) {
+            cc.write(|w| unsafe { w.bits(value) });
+        }
+        // levels of IN1 and IN2 at the counter in the new waveform
+        let levels = [
+            (compares[0]..compares[1]).contains(&counter),
+            (compares[2]..compares[3]).contains(&counter),
+        ];
+        let pins = self.pins.gpiote_in_pins();
+        for ((gpiote, pin), high) in self.gpiote.iter_mut().zip(&pins).zip(levels) {
+            if is_high(pin) != high {
+                let level = if high { Level::High } else { Level::Low };
+                gpiote.output_pin(pin, TaskOutPolarity::Toggle, level);
+            }
+        }
+        regs.tasks_start.write(|w| unsafe { w.bits(1) });
+        self.timing = Some(timing);
+    }
+
+    /// Stop the tone and put the H-bridge to sleep
+    pub fn stop(&mut self) {
+        let regs = self.timer.as_timer0();
+        regs.tasks_stop.write(|w| unsafe { w.bits(1) });
+        regs.tasks_clear.write(|w| unsafe { w.bits(1) });
+        regs.events_compare[PERIOD_CC].write(|w| unsafe { w.bits(0) });
+        for ppi in &mut self.ppi {
+            ppi.disable();
+        }
+        // the pins fall back to their low GPIO output
+        for gpiote in &mut self.gpiote {
+            gpiote.disable();
+        }
+        self.pins.motor_nsleep.set_low().ok();
+        self.timing = None;
+    }
+
+    /// Play a tone for `duration_ms` and stop
+    ///
+    /// Blocks while the tone plays, a frequency of 0 rests instead.
+    pub fn play_tone(&mut self, frequency_hz: u32, duration_ms: u32) {
+        if frequency_hz == 0 {
+            self.rest(duration_ms);
+            return;
+        }
+        self.start_tone(frequency_hz);
+        let frequency_hz = self.frequency_hz().unwrap_or(frequency_hz);
+        let periods = (u64::from(frequency_hz) * u64::from(duration_ms) / 1000).max(1);
+        let periods = periods.min(u32::MAX.into()) as u32;
+        self.wait_periods(periods);
+        self.stop();
+    }
+
+    /// Stay silent for `duration_ms`
+    ///
+    /// Blocks for the duration, the timer counts the time.
+    pub fn rest(&mut self, duration_ms: u32) {
+        self.stop();
+        let regs = self.timer.as_timer0();
+        // 16 MHz / 2^4 = 1 MHz, one period per ms
+        regs.prescaler.write(|w| unsafe { w.bits(4) });
+        regs.cc[PERIOD_CC].write(|w| unsafe { w.bits(1000) });
+        regs.tasks_start.write(|w| unsafe { w.bits(1) });
+        self.wait_periods(duration_ms);
+        self.stop();
+    }
+
+    fn wait_periods(&self, periods: u32) {
+        let event = &self.timer.as_timer0().events_compare[PERIOD_CC];
+        for _ in 0..periods {
+            while event.read().bits() == 0 {}
+            event.write(|w| unsafe { w.bits(0) });
+        }
+    }
+
+    /// Stop the tone and release the resources
+    pub fn free(mut self) -> (T, BeeperMotorPins, [GpioteChannel; 2], [PpiChannel; 4]) {
+        self.stop();
+        (self.timer, self.pins, self.gpiote, self.ppi)
+    }
+}
+
+/// Level of an H-bridge input, as driven by its GPIOTE channel
+fn is_high(pin: &Pin<Output<PushPull>>) -> bool {
+    // the input buffer of the pin stays connected, the alias only reads it
+    let input: Pin<Input<Floating>> = unsafe { Pin::from_psel_bits(pin.psel_bits()) };
+    input.is_high().unwrap_or(false)
+}
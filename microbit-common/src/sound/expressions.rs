@@ -0,0 +1,246 @@
+//! Sound expressions like the ones of the micro:bit V2
+//!
+//! A [`SoundEffect`] sweeps the frequency and the volume of a tone from a
+//! start to an end value. The [`Shape`] of the sweep sets how fast the
+//! values move at its start and end, the [`Waveform`] whether the tone is
+//! clean or noisy. An [`Expression`] is a sequence of effects, like the
+//! "giggle" or "sad" sounds of MakeCode.
+//!
+//! The sweeps are computed with integer math by [`SoundEffect::at`].
+#![cfg_attr(
+    feature = "v1",
+    doc = r#"
+[`MelodyPlayer::play_expression`](crate::sound::melody::MelodyPlayer::play_expression)
+plays them in the background, retuning the beeper every [`STEP_MS`].
+
+# Example
+
+```no_run
+# use microbit_common as microbit;
+use microbit::{
+    channels::{GpioteChannels, PpiChannels},
+    sound::{expressions::Expression, melody::MelodyPlayer, Beeper},
+    Board,
+};
+
+let board = Board::take().unwrap();
+let gpiote = GpioteChannels::new(board.GPIOTE);
+let ppi = PpiChannels::new(board.PPI);
+let beeper = Beeper::new(
+    board.TIMER0,
+    board.beeper_motor_pins,
+    [gpiote.channel0, gpiote.channel1],
+    [ppi.ppi0, ppi.ppi1, ppi.ppi2, ppi.ppi3],
+);
+let mut player = MelodyPlayer::new(beeper, board.TIMER1);
+
+player.play_expression(Expression::Giggle);
+// call `player.handle_timer_event()` in the TIMER1 interrupt handler
+```
+"#
+)]
+
+use Shape::{Curve, Linear, Logarithmic};
+use Waveform::{Noise, Square};
+
+/// Time between two retunings of the beeper while an effect plays
+pub const STEP_MS: u32 = 10;
+
+/// Progress of a sweep from 0 to this value
+const PROGRESS_MAX: i32 = 1024;
+
+/// Sound of the tone
+///
+/// The beeper drives square waves, so that is the only clean waveform.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Waveform {
+    /// Clean square wave
+    Square,
+    /// Square wave with a random frequency between half and one and a half
+    /// times the swept one at each step
+    Noise,
+}
+
+/// Course of a sweep from its start to its end value
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Shape {
+    /// Constant speed
+    Linear,
+    /// Slow at the start and the end, fast in the middle
+    Curve,
+    /// Fast at the start, slowing down towards the end
+    Logarithmic,
+}
+
+impl Shape {
+    /// Position of the sweep at `progress` from 0 to 1024, also from 0 to
+    /// 1024
+    ///
+    /// ```
+    /// # use microbit_common as microbit;
+    /// use microbit::sound::expressions::Shape;
+    ///
+    /// for shape in [Shape::Linear, Shape::Curve, Shape::Logarithmic] {
+    ///     assert_eq!(shape.position(0), 0);
+    ///     assert_eq!(shape.position(512), if shape == Shape::Logarithmic { 768 } else { 512 });
+    ///     assert_eq!(shape.position(1024), 1024);
+    /// }
+    /// assert_eq!(Shape::Linear.position(256), 256);
+    /// assert_eq!(Shape::Curve.position(256), 160);
+    /// assert_eq!(Shape::Logarithmic.position(256), 448);
+    /// ```
+    pub fn position(self, progress: u16) -> u16 {
+        let p = i32::from(progress).clamp(0, PROGRESS_MAX);
+        let position = match self {
+            Linear => p,
+            // smoothstep: 3p² - 2p³
+            Curve => p * p / PROGRESS_MAX * (3 * PROGRESS_MAX - 2 * p) / PROGRESS_MAX,
+            Logarithmic => PROGRESS_MAX - (PROGRESS_MAX - p) * (PROGRESS_MAX - p) / PROGRESS_MAX,
+        };
+        position as u16
+    }
+}
+
+/// Sweep of the frequency and the volume of a tone
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SoundEffect {
+    /// Sound of the tone
+    pub waveform: Waveform,
+    /// Course of both sweeps
+    pub shape: Shape,
+    /// Frequency at the start
+    pub start_hz: u16,
+    /// Frequency at the end
+    pub end_hz: u16,
+    /// Volume at the start, from 0 to 255
+    pub start_volume: u8,
+    /// Volume at the end, from 0 to 255
+    pub end_volume: u8,
+    /// Length of the sweep
+    pub duration_ms: u16,
+}
+
+impl SoundEffect {
+    /// A sweep with all its parameters
+    pub const fn new(
+        waveform: Waveform,
+        shape: Shape,
+        (start_hz, end_hz): (u16, u16),
+        (start_volume, end_volume): (u8, u8),
+        duration_ms: u16,
+    ) -> Self {
+        Self {
+            waveform,
+            shape,
+            start_hz,
+            end_hz,
+            start_volume,
+            end_volume,
+            duration_ms,
+        }
+    }
+
+    /// Frequency and volume `elapsed_ms` after the start
+    ///
+    /// The values stay at their end after the sweep.
+    ///
+    /// ```
+    /// # use microbit_common as microbit;
+    /// use microbit::sound::expressions::{Shape, SoundEffect, Waveform};
+    ///
+    /// let effect = SoundEffect::new(Waveform::Square, Shape::Linear, (200, 400), (255, 55), 100);
+    /// assert_eq!(effect.at(0), (200, 255));
+    /// assert_eq!(effect.at(50), (300, 155));
+    /// assert_eq!(effect.at(100), (400, 55));
+    /// assert_eq!(effect.at(500), (400, 55));
+    ///
+    /// let effect = SoundEffect { shape: Shape::Curve, ..effect };
+    /// assert_eq!(effect.at(25), (231, 224));
+    /// let effect = SoundEffect { shape: Shape::Logarithmic, ..effect };
+    /// assert_eq!(effect.at(25), (287, 168));
+    ///
+    /// // falling sweeps
+    /// let effect = SoundEffect::new(Waveform::Square, Shape::Linear, (800, 400), (0, 200), 40);
+    /// assert_eq!(effect.at(10), (700, 50));
+    /// ```
+    pub fn at(&self, elapsed_ms: u32) -> (u32, u8) {
+        let duration_ms = u32::from(self.duration_ms).max(1);
+        let progress = elapsed_ms.min(duration_ms) * PROGRESS_MAX as u32 / duration_ms;
+        let position = i32::from(self.shape.position(progress as u16));
+        let sweep = |start: i32, end: i32| start + (end - start) * position / PROGRESS_MAX;
+        let frequency_hz = sweep(self.start_hz.into(), self.end_hz.into());
+        let volume = sweep(self.start_volume.into(), self.end_volume.into());
+        (frequency_hz as u32, volume as u8)
+    }
+}
+
+const GIGGLE: [SoundEffect; 5] = [
+    SoundEffect::new(Square, Linear, (988, 440), (255, 200), 190),
+    SoundEffect::new(Square, Curve, (660, 990), (200, 230), 70),
+    SoundEffect::new(Square, Curve, (720, 1050), (230, 200), 70),
+    SoundEffect::new(Square, Curve, (780, 1110), (200, 170), 70),
+    SoundEffect::new(Square, Logarithmic, (840, 560), (170, 0), 120),
+];
+
+const HAPPY: [SoundEffect; 3] = [
+    SoundEffect::new(Square, Logarithmic, (520, 780), (220, 255), 120),
+    SoundEffect::new(Square, Curve, (780, 1040), (255, 230), 140),
+    SoundEffect::new(Square, Linear, (1040, 1320), (230, 0), 220),
+];
+
+const SAD: [SoundEffect; 3] = [
+    SoundEffect::new(Square, Curve, (660, 520), (200, 180), 300),
+    SoundEffect::new(Square, Linear, (520, 490), (180, 160), 250),
+    SoundEffect::new(Square, Logarithmic, (490, 300), (160, 0), 600),
+];
+
+const YAWN: [SoundEffect; 3] = [
+    SoundEffect::new(Square, Curve, (300, 560), (80, 200), 500),
+    SoundEffect::new(Noise, Linear, (560, 520), (200, 180), 300),
+    SoundEffect::new(Square, Logarithmic, (520, 180), (180, 0), 900),
+];
+
+const MYSTERIOUS: [SoundEffect; 3] = [
+    SoundEffect::new(Noise, Curve, (220, 440), (0, 160), 800),
+    SoundEffect::new(Square, Linear, (440, 415), (160, 160), 600),
+    SoundEffect::new(Noise, Curve, (415, 110), (160, 0), 1000),
+];
+
+/// The built in sound expressions
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Expression {
+    /// Short rising chirps
+    Giggle,
+    /// Quickly rising tones
+    Happy,
+    /// Slowly falling tones
+    Sad,
+    /// Rising, then long falling and fading
+    Yawn,
+    /// Slow, noisy swells
+    Mysterious,
+}
+
+impl Expression {
+    /// The effects of the expression in the order they are played
+    ///
+    /// ```
+    /// # use microbit_common as microbit;
+    /// use microbit::sound::expressions::Expression;
+    ///
+    /// let sad = Expression::Sad.effects();
+    /// let (start_hz, _) = sad[0].at(0);
+    /// let (end_hz, end_volume) = sad[sad.len() - 1].at(u32::MAX);
+    /// assert!(end_hz < start_hz);
+    /// assert_eq!(end_volume, 0);
+    /// ```
+    pub fn effects(self) -> &'static [SoundEffect] {
+        match self {
+            Expression::Giggle => &GIGGLE,
+            Expression::Happy => &HAPPY,
+            Expression::Sad => &SAD,
+            Expression::Yawn => &YAWN,
+            Expression::Mysterious => &MYSTERIOUS,
+        }
+    }
+}
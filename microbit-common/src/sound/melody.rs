@@ -0,0 +1,622 @@
+//! Melodies from RTTTL ringtones and MakeCode note strings
+//!
+//! [`Melody`] checks a melody when it is created and hands out its notes
+//! through [`Melody::notes`] without copying it. Two formats are understood:
+//!
+//! - RTTTL ringtones like `"Beep:d=4,o=5,b=120:8c,8e,g,2p,c6"`: the header
+//!   sets the default duration, octave and tempo, each note is an optional
+//!   duration (1 is a whole note, 32 a thirty-second), the note `a` to `h`
+//!   or the pause `p`, an optional `#`, optional dot and optional octave.
+//! - MakeCode note strings like `"C5:4 E G R:2 C6"`: each note is the name
+//!   `A` to `G` or the rest `R`, an optional `#` or `b`, an optional octave
+//!   and an optional `:` with the duration in quarter beats. The octave and
+//!   the duration carry over to the following notes and start at 4, the
+//!   tempo starts at 120 beats per minute.
+//!
+//! Octaves 0 to 8 are supported, the A of octave 4 is 440 Hz.
+#![cfg_attr(
+    feature = "v1",
+    doc = r#"
+[`MelodyPlayer`] plays a melody or [sound expressions](crate::sound::expressions)
+on a [`Beeper`] in the background, counting the note durations in 1 ms
+ticks of a second TIMER. Each note is silenced for its last [`RELEASE_MS`]
+so that repeated notes can be told apart.
+
+# Example
+
+```no_run
+# use microbit_common as microbit;
+use microbit::{
+    channels::{GpioteChannels, PpiChannels},
+    sound::{melody::{Melody, MelodyPlayer}, Beeper},
+    Board,
+};
+
+let board = Board::take().unwrap();
+let gpiote = GpioteChannels::new(board.GPIOTE);
+let ppi = PpiChannels::new(board.PPI);
+let beeper = Beeper::new(
+    board.TIMER0,
+    board.beeper_motor_pins,
+    [gpiote.channel0, gpiote.channel1],
+    [ppi.ppi0, ppi.ppi1, ppi.ppi2, ppi.ppi3],
+);
+let mut player = MelodyPlayer::new(beeper, board.TIMER1);
+
+let mut melody = Melody::makecode("C5:4 E G R:2 C6:8").unwrap();
+melody.set_tempo(160);
+player.play_looping(melody);
+// call `player.handle_timer_event()` in the TIMER1 interrupt handler
+```
+"#
+)]
+
+#[cfg(feature = "v1")]
+use crate::{
+    hal::timer::Instance,
+    sound::{
+        expressions::{Expression, SoundEffect, Waveform, STEP_MS},
+        Beeper,
+    },
+};
+
+/// Highest supported octave
+pub const MAX_OCTAVE: u8 = 8;
+
+/// Silence at the end of each note
+#[cfg(feature = "v1")]
+pub const RELEASE_MS: u32 = 10;
+
+/// Frequencies of the notes C to B of octave 8, lower octaves are halved
+const OCTAVE8_HZ: [u32; 12] = [
+    4186, 4435, 4699, 4978, 5274, 5588, 5920, 6272, 6645, 7040, 7459, 7902,
+];
+
+/// Durations are counted in 1/64 whole notes, 16 per beat
+const UNITS_PER_WHOLE: u16 = 64;
+
+/// Milliseconds per minute divided by the units per beat
+const MS_PER_UNIT_BPM: u32 = 60_000 / 16;
+
+/// Melody errors
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The RTTTL header is missing or has an invalid setting
+    Header,
+    /// The note with this index, counted from 0, can not be parsed
+    Note(usize),
+}
+
+/// A tone or a rest
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Note {
+    /// Frequency of the tone, 0 for a rest
+    pub frequency_hz: u32,
+    /// Duration at the tempo of the melody
+    pub duration_ms: u32,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Format {
+    Rtttl,
+    MakeCode,
+}
+
+/// A checked melody
+///
+/// ```
+/// # use microbit_common as microbit;
+/// use microbit::sound::melody::{Error, Melody, Note};
+///
+/// let melody = Melody::rtttl("Beep:d=4,o=5,b=120:8c,8e,g.,2p,c6").unwrap();
+/// let mut notes = melody.notes();
+/// assert_eq!(notes.next(), Some(Note { frequency_hz: 523, duration_ms: 250 }));
+/// assert_eq!(notes.next(), Some(Note { frequency_hz: 659, duration_ms: 250 }));
+/// assert_eq!(notes.next(), Some(Note { frequency_hz: 784, duration_ms: 750 }));
+/// assert_eq!(notes.next(), Some(Note { frequency_hz: 0, duration_ms: 1000 }));
+/// assert_eq!(notes.next(), Some(Note { frequency_hz: 1047, duration_ms: 500 }));
+/// assert_eq!(notes.next(), None);
+///
+/// let mut melody = Melody::makecode("A4:4 A# Bb:2 R C5").unwrap();
+/// melody.set_tempo(60);
+/// let frequencies: Vec<_> = melody.notes().map(|note| note.frequency_hz).collect();
+/// assert_eq!(frequencies, [440, 466, 466, 0, 523]);
+/// let durations: Vec<_> = melody.notes().map(|note| note.duration_ms).collect();
+/// assert_eq!(durations, [1000, 1000, 500, 500, 500]);
+///
+/// assert_eq!(Melody::rtttl("8c,8e"), Err(Error::Header));
+/// assert_eq!(Melody::rtttl("Beep:d=3,o=5,b=120:c"), Err(Error::Header));
+/// assert_eq!(Melody::makecode("C D X E"), Err(Error::Note(2)));
+/// assert_eq!(Melody::makecode("C9"), Err(Error::Note(0)));
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Melody<'a> {
+    notes: &'a str,
+    format: Format,
+    octave: u8,
+    units: u16,
+    tempo_bpm: u16,
+}
+
+impl<'a> Melody<'a> {
+    /// Parse an RTTTL ringtone
+    ///
+    /// Settings missing from the header default to `d=4,o=6,b=63`.
+    pub fn rtttl(ringtone: &'a str) -> Result<Self, Error> {
+        let mut parts = ringtone.splitn(3, ':');
+        let (Some(_name), Some(settings), Some(notes)) = (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(Error::Header);
+        };
+        let mut melody = Self {
+            notes,
+            format: Format::Rtttl,
+            octave: 6,
+            units: UNITS_PER_WHOLE / 4,
+            tempo_bpm: 63,
+        };
+        for setting in settings.split(',').map(str::trim) {
+            if setting.is_empty() {
+                continue;
+            }
+            let (key, value) = setting.split_once('=').ok_or(Error::Header)?;
+            let value: u16 = value.trim().parse().map_err(|_| Error::Header)?;
+            match key.trim() {
+                "d" => melody.units = rtttl_units(value).ok_or(Error::Header)?,
+                "o" if value <= u16::from(MAX_OCTAVE) => melody.octave = value as u8,
+                "b" if value > 0 => melody.tempo_bpm = value,
+                _ => return Err(Error::Header),
+            }
+        }
+        melody.check()
+    }
+
+    /// Parse a MakeCode note string
+    pub fn makecode(notes: &'a str) -> Result<Self, Error> {
+        Self {
+            notes,
+            format: Format::MakeCode,
+            octave: 4,
+            units: 4 * 4,
+            tempo_bpm: 120,
+        }
+        .check()
+    }
+
+    fn check(self) -> Result<Self, Error> {
+        let mut notes = self.notes();
+        let mut index = 0;
+        while let Some(note) = notes.parse_next() {
+            note.ok_or(Error::Note(index))?;
+            index += 1;
+        }
+        Ok(self)
+    }
+
+    /// Tempo in beats, quarter notes, per minute
+    pub fn tempo(&self) -> u16 {
+        self.tempo_bpm
+    }
+
+    /// Change the tempo, it is at least 1 beat per minute
+    pub fn set_tempo(&mut self, bpm: u16) {
+        self.tempo_bpm = bpm.max(1);
+    }
+
+    /// The notes from the start of the melody
+    pub fn notes(&self) -> Notes<'a> {
+        Notes {
+            rest: self.notes,
+            format: self.format,
+            octave: self.octave,
+            units: self.units,
+            tempo_bpm: self.tempo_bpm,
+        }
+    }
+}
+
+/// Iterator over the notes of a [`Melody`]
+#[derive(Clone, Debug)]
+pub struct Notes<'a> {
+    rest: &'a str,
+    format: Format,
+    // defaults of RTTTL, the last values of MakeCode
+    octave: u8,
+    units: u16,
+    tempo_bpm: u16,
+}
+
+impl<'a> Notes<'a> {
+    /// The next note, `Some(None)` if it is invalid
+    fn parse_next(&mut self) -> Option<Option<Note>> {
+        let is_separator = |c: char| c == ',' || c.is_whitespace();
+        let rest = self.rest.trim_start_matches(is_separator);
+        if rest.is_empty() {
+            self.rest = rest;
+            return None;
+        }
+        let end = rest.find(is_separator).unwrap_or(rest.len());
+        let (token, rest) = rest.split_at(end);
+        self.rest = rest;
+        let note = match self.format {
+            Format::Rtttl => self.rtttl_note(token.as_bytes()),
+            Format::MakeCode => self.makecode_note(token.as_bytes()),
+        };
+        Some(note.map(|(frequency_hz, units)| Note {
+            frequency_hz,
+            duration_ms: u32::from(units) * MS_PER_UNIT_BPM / u32::from(self.tempo_bpm),
+        }))
+    }
+
+    fn rtttl_note(&self, token: &[u8]) -> Option<(u32, u16)> {
+        let mut cursor = Cursor { token, pos: 0 };
+        let mut units = match cursor.number() {
+            Some(duration) => rtttl_units(duration)?,
+            None => self.units,
+        };
+        let name = cursor.byte()?.to_ascii_lowercase();
+        let sharp = cursor.eat(b'#');
+        let mut dotted = cursor.eat(b'.');
+        let octave = cursor.number().unwrap_or(self.octave.into());
+        dotted |= cursor.eat(b'.');
+        if !cursor.is_done() {
+            return None;
+        }
+        if dotted {
+            units += units / 2;
+        }
+        let frequency_hz = match name {
+            b'p' => 0,
+            // German notation, H is B
+            b'h' => frequency(octave, 11 + i16::from(sharp))?,
+            _ => frequency(octave, semitone(name)? + i16::from(sharp))?,
+        };
+        Some((frequency_hz, units))
+    }
+
+    fn makecode_note(&mut self, token: &[u8]) -> Option<(u32, u16)> {
+        let mut cursor = Cursor { token, pos: 0 };
+        let name = cursor.byte()?.to_ascii_lowercase();
+        let accidental = if cursor.eat(b'#') {
+            1
+        } else if cursor.eat(b'b') {
+            -1
+        } else {
+            0
+        };
+        let octave = cursor.number();
+        let ticks = if cursor.eat(b':') {
+            Some(
+                cursor
+                    .number()
+                    .filter(|&ticks| (1..=255).contains(&ticks))?,
+            )
+        } else {
+            None
+        };
+        if !cursor.is_done() {
+            return None;
+        }
+        let frequency_hz = match name {
+            b'r' if accidental == 0 && octave.is_none() => 0,
+            _ => frequency(
+                octave.unwrap_or(self.octave.into()),
+                semitone(name)? + accidental,
+            )?,
+        };
+        if let Some(octave) = octave {
+            self.octave = octave as u8;
+        }
+        if let Some(ticks) = ticks {
+            self.units = ticks * 4;
+        }
+        Some((frequency_hz, self.units))
+    }
+}
+
+impl<'a> Iterator for Notes<'a> {
+    type Item = Note;
+
+    fn next(&mut self) -> Option<Note> {
+        // the melody was checked when it was created
+        self.parse_next()?
+    }
+}
+
+/// Reads a note token
+struct Cursor<'t> {
+    token: &'t [u8],
+    pos: usize,
+}
+
+impl<'t> Cursor<'t> {
+    fn byte(&mut self) -> Option<u8> {
+        let byte = *self.token.get(self.pos)?;
+        self.pos += 1;
+        Some(byte)
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        let found = self.token.get(self.pos) == Some(&byte);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn number(&mut self) -> Option<u16> {
+        let start = self.pos;
+        let mut value: u16 = 0;
+        while let Some(digit) = self.token.get(self.pos).filter(|b| b.is_ascii_digit()) {
+            value = value
+                .checked_mul(10)?
+                .checked_add(u16::from(digit - b'0'))?;
+            self.pos += 1;
+        }
+        (self.pos > start).then_some(value)
+    }
+
+    fn is_done(&self) -> bool {
+        self.pos == self.token.len()
+    }
+}
+
+/// Duration of an RTTTL note value in 1/64 whole notes
+fn rtttl_units(duration: u16) -> Option<u16> {
+    matches!(duration, 1 | 2 | 4 | 8 | 16 | 32 | 64).then(|| UNITS_PER_WHOLE / duration)
+}
+
+/// Semitone of a note name above C
+fn semitone(name: u8) -> Option<i16> {
+    Some(match name {
+        b'c' => 0,
+        b'd' => 2,
+        b'e' => 4,
+        b'f' => 5,
+        b'g' => 7,
+        b'a' => 9,
+        b'b' => 11,
+        _ => return None,
+    })
+}
+
+/// Frequency of a note, a semitone outside the octave moves to the next one
+fn frequency(octave: u16, semitone: i16) -> Option<u32> {
+    let note = (octave as i16).checked_mul(12)?.checked_add(semitone)?;
+    if !(0..=i16::from(MAX_OCTAVE) * 12 + 11).contains(&note) {
+        return None;
+    }
+    let shift = MAX_OCTAVE as usize - note as usize / 12;
+    let hz = OCTAVE8_HZ[note as usize % 12];
+    // rounded to the nearest Hz
+    Some((hz + (1 << shift >> 1)) >> shift)
+}
+
+/// Plays a [`Melody`] or [sound effects](crate::sound::expressions) on a
+/// [`Beeper`] from TIMER interrupts
+#[cfg(feature = "v1")]
+pub struct MelodyPlayer<B: Instance, T: Instance> {
+    beeper: Beeper<B>,
+    timer: T,
+    playing: Option<Playing>,
+    volume: u8,
+    // ticks left of the current note or effect
+    remaining_ms: u32,
+    // the tone stops when this many ticks are left
+    release_ms: u32,
+    // ticks left until the effect retunes the beeper
+    step_ms: u32,
+    // xorshift state of the noise waveform
+    noise: u32,
+}
+
+#[cfg(feature = "v1")]
+enum Playing {
+    Melody {
+        melody: Melody<'static>,
+        notes: Notes<'static>,
+        looping: bool,
+    },
+    Effects {
+        // the first one is being played
+        effects: &'static [SoundEffect],
+    },
+}
+
+#[cfg(feature = "v1")]
+impl<B: Instance, T: Instance> MelodyPlayer<B, T> {
+    /// Take the beeper and a timer which ticks every 1 ms while playing
+    ///
+    /// The volume of the beeper becomes the volume of the player.
+    pub fn new(beeper: Beeper<B>, timer: T) -> Self {
+        let regs = timer.as_timer0();
+        regs.tasks_stop.write(|w| unsafe { w.bits(1) });
+        regs.tasks_clear.write(|w| unsafe { w.bits(1) });
+        regs.mode.write(|w| w.mode().timer());
+        regs.bitmode.write(|w| w.bitmode()._16bit());
+        // 16 MHz / 2^4 = 1 MHz
+        regs.prescaler.write(|w| unsafe { w.bits(4) });
+        regs.cc[0].write(|w| unsafe { w.bits(1000) });
+        regs.shorts.write(|w| w.compare0_clear().enabled());
+        regs.intenclr.write(|w| unsafe { w.bits(0xffff_ffff) });
+        regs.intenset.write(|w| w.compare0().set());
+        Self {
+            volume: beeper.volume(),
+            beeper,
+            timer,
+            playing: None,
+            remaining_ms: 0,
+            release_ms: 0,
+            step_ms: 0,
+            noise: 0x2545_f491,
+        }
+    }
+
+    /// Play `melody` once, replacing the sound being played
+    pub fn play(&mut self, melody: Melody<'static>) {
+        self.start(Playing::Melody {
+            melody,
+            notes: melody.notes(),
+            looping: false,
+        });
+    }
+
+    /// Play `melody` until it is stopped, replacing the sound being played
+    pub fn play_looping(&mut self, melody: Melody<'static>) {
+        self.start(Playing::Melody {
+            melody,
+            notes: melody.notes(),
+            looping: true,
+        });
+    }
+
+    /// Play a sound expression, replacing the sound being played
+    pub fn play_expression(&mut self, expression: Expression) {
+        self.play_effects(expression.effects());
+    }
+
+    /// Play sound effects one after the other, replacing the sound being
+    /// played
+    ///
+    /// The volumes of the effects are scaled by the volume of the player.
+    pub fn play_effects(&mut self, effects: &'static [SoundEffect]) {
+        self.start(Playing::Effects { effects });
+    }
+
+    fn start(&mut self, playing: Playing) {
+        self.stop();
+        self.playing = Some(playing);
+        self.next();
+        if self.playing.is_some() {
+            let regs = self.timer.as_timer0();
+            regs.tasks_start.write(|w| unsafe { w.bits(1) });
+        }
+    }
+
+    /// Change the volume from 0 to 255, see [`Beeper::set_volume`]
+    pub fn set_volume(&mut self, volume: u8) {
+        self.volume = volume;
+        if let Some(Playing::Melody { .. }) = self.playing {
+            self.beeper.set_volume(volume);
+        }
+    }
+
+    /// Whether a melody or effects are being played
+    pub fn is_playing(&self) -> bool {
+        self.playing.is_some()
+    }
+
+    /// Stop the sound
+    pub fn stop(&mut self) {
+        let regs = self.timer.as_timer0();
+        regs.tasks_stop.write(|w| unsafe { w.bits(1) });
+        regs.tasks_clear.write(|w| unsafe { w.bits(1) });
+        regs.events_compare[0].write(|w| unsafe { w.bits(0) });
+        self.beeper.stop();
+        self.playing = None;
+    }
+
+    /// Count the note and effect durations and move on to the next one
+    ///
+    /// Call this in the TIMER interrupt handler, it clears the TIMER events.
+    pub fn handle_timer_event(&mut self) {
+        let regs = self.timer.as_timer0();
+        if regs.events_compare[0].read().bits() == 0 {
+            return;
+        }
+        regs.events_compare[0].write(|w| unsafe { w.bits(0) });
+        let Some(playing) = &self.playing else {
+            return;
+        };
+        self.remaining_ms = self.remaining_ms.saturating_sub(1);
+        if self.remaining_ms == 0 {
+            if let Some(Playing::Effects { effects }) = &mut self.playing {
+                *effects = &effects[1..];
+            }
+            self.next();
+            return;
+        }
+        match playing {
+            Playing::Melody { .. } => {
+                if self.remaining_ms == self.release_ms {
+                    self.beeper.stop();
+                }
+            }
+            Playing::Effects { effects } => {
+                let effect = effects[0];
+                let elapsed_ms = u32::from(effect.duration_ms) - self.remaining_ms;
+                self.step_ms = self.step_ms.saturating_sub(1);
+                if self.step_ms == 0 {
+                    self.sweep(&effect, elapsed_ms);
+                }
+            }
+        }
+    }
+
+    /// Start the next note or effect
+    fn next(&mut self) {
+        match &mut self.playing {
+            Some(Playing::Melody {
+                melody,
+                notes,
+                looping,
+            }) => {
+                let mut note = notes.next();
+                if note.is_none() && *looping {
+                    *notes = melody.notes();
+                    note = notes.next();
+                }
+                let Some(note) = note else {
+                    self.stop();
+                    return;
+                };
+                self.tone(note.frequency_hz, self.volume);
+                self.remaining_ms = note.duration_ms.max(1);
+                self.release_ms = if note.duration_ms > 2 * RELEASE_MS {
+                    RELEASE_MS
+                } else {
+                    0
+                };
+            }
+            Some(Playing::Effects { effects }) => {
+                let Some(&effect) = effects.first() else {
+                    self.stop();
+                    return;
+                };
+                self.remaining_ms = u32::from(effect.duration_ms).max(1);
+                self.sweep(&effect, 0);
+            }
+            None => {}
+        }
+    }
+
+    /// Retune the beeper to `effect` at `elapsed_ms`
+    fn sweep(&mut self, effect: &SoundEffect, elapsed_ms: u32) {
+        let (mut frequency_hz, volume) = effect.at(elapsed_ms);
+        if effect.waveform == Waveform::Noise {
+            // xorshift32
+            self.noise ^= self.noise << 13;
+            self.noise ^= self.noise >> 17;
+            self.noise ^= self.noise << 5;
+            frequency_hz = frequency_hz * (512 + self.noise % 1024) / 1024;
+        }
+        let volume = u32::from(volume) * u32::from(self.volume) / 255;
+        self.tone(frequency_hz, volume as u8);
+        self.step_ms = STEP_MS;
+    }
+
+    fn tone(&mut self, frequency_hz: u32, volume: u8) {
+        // a tone being played is retuned in place, the H-bridge only stops
+        // for silence
+        self.beeper.set_volume(volume);
+        self.beeper.start_tone(frequency_hz);
+    }
+
+    /// Stop the sound and release the beeper and the timer
+    ///
+    /// The beeper keeps the volume of the last note or effect.
+    pub fn free(mut self) -> (Beeper<B>, T) {
+        self.stop();
+        (self.beeper, self.timer)
+    }
+}
@@ -0,0 +1,24 @@
+//! Sound output
+//!
+//! - [`melody`]: RTTTL ringtones and MakeCode note strings
+//! - [`expressions`]: frequency and volume sweeps like the sound expressions
+//!   of MakeCode
+#![cfg_attr(
+    feature = "v1",
+    doc = "- [`Beeper`]: square wave tones on the speaker of the Calliope mini, \
+           driven through the DRV8837 H-bridge, and [`MelodyPlayer`](melody::MelodyPlayer) \
+           to play melodies and expressions on it in the background"
+)]
+#![cfg_attr(
+    feature = "v2",
+    doc = "- [`pcm`]: 8-bit PCM clips on the speaker through a PWM peripheral"
+)]
+#[cfg(feature = "v1")]
+mod beeper;
+pub mod expressions;
+pub mod melody;
+#[cfg(feature = "v2")]
+pub mod pcm;
+
+#[cfg(feature = "v1")]
+pub use beeper::{Beeper, ToneTiming, MAX_FREQUENCY_HZ, MIN_FREQUENCY_HZ};
@@ -0,0 +1,341 @@
+//! 8-bit PCM playback on the speaker
+//!
+//! [`PcmPlayer`] streams unsigned 8-bit samples, as in 8-bit WAV files, to
+//! the speaker through a PWM peripheral. The PWM runs at 16 MHz and each
+//! sample sets the duty cycle of [`PcmTiming::refresh`] + 1 PWM periods,
+//! which gives the sample rate.
+//!
+//! EasyDMA only reads from RAM, so the samples of a [`Clip`] in flash are
+//! converted into two RAM buffers: while the PWM plays one of them, the
+//! interrupt handler refills the other. Call
+//! [`PcmPlayer::handle_pwm_event()`] in the interrupt handler of the PWM
+//! peripheral you are using. The buffers have to live forever, as the PWM
+//! reads them without the CPU.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use microbit_common as microbit;
+//! use microbit::{
+//!     sound::pcm::{Buffers, Clip, PcmPlayer},
+//!     Board,
+//! };
+//!
+//! static mut BUFFERS: Buffers = Buffers::new();
+//! static BLIP: [u8; 8] = [128, 192, 255, 192, 128, 64, 0, 64];
+//!
+//! let board = Board::take().unwrap();
+//! // the buffers are only handed out once
+//! let buffers = unsafe { &mut *core::ptr::addr_of_mut!(BUFFERS) };
+//! let mut player = PcmPlayer::new(board.PWM0, board.speaker_pin, buffers);
+//!
+//! player.set_volume(128);
+//! player.play(Clip::new(&BLIP, 8000));
+//! // call `player.handle_pwm_event()` in the PWM0 interrupt handler
+//! ```
+use core::sync::atomic::{compiler_fence, Ordering};
+
+use embedded_hal::digital::v2::OutputPin;
+
+use crate::hal::{
+    gpio::{p0::P0_00, Disconnected, Level, Output, PushPull},
+    pwm::Instance,
+};
+
+/// Samples per buffer
+pub const BUFFER_LEN: usize = 256;
+
+/// Lowest sample rate, lower ones are raised to it
+pub const MIN_SAMPLE_RATE_HZ: u32 = 1000;
+
+/// Highest sample rate, higher ones are lowered to it
+pub const MAX_SAMPLE_RATE_HZ: u32 = 62_500;
+
+/// PWM clock
+const CLOCK_HZ: u32 = 16_000_000;
+
+/// Highest PWM period, one tick per sample value
+const MAX_COUNTERTOP: u32 = 256;
+
+/// GPIO number of the speaker
+const SPEAKER_PSEL: u32 = 0;
+
+/// Sample value of silence
+const SILENCE: u8 = 128;
+
+/// RAM the PWM reads the samples from
+pub struct Buffers {
+    halves: [[u16; BUFFER_LEN]; 2],
+}
+
+impl Buffers {
+    /// Silent buffers, for use in a `static`
+    pub const fn new() -> Self {
+        Self {
+            halves: [[0; BUFFER_LEN]; 2],
+        }
+    }
+}
+
+impl Default for Buffers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Unsigned 8-bit samples at a sample rate
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Clip {
+    /// Samples, 128 is silence
+    pub samples: &'static [u8],
+    /// Samples per second
+    pub sample_rate_hz: u32,
+}
+
+impl Clip {
+    /// Play `samples` at `sample_rate_hz`
+    pub const fn new(samples: &'static [u8], sample_rate_hz: u32) -> Self {
+        Self {
+            samples,
+            sample_rate_hz,
+        }
+    }
+}
+
+/// PWM settings for a sample rate
+///
+/// The PWM period is as long as possible up to 256 ticks, for the finest
+/// resolution, while still repeating each sample a whole number of times.
+///
+/// ```
+/// # use microbit_common as microbit;
+/// use microbit::sound::pcm::PcmTiming;
+///
+/// let timing = PcmTiming::new(8000);
+/// assert_eq!((timing.countertop, timing.refresh), (250, 7));
+/// assert_eq!(timing.sample_rate_hz(), 8000);
+/// assert_eq!(PcmTiming::new(16_000).countertop, 250);
+/// // the closest rate that can be generated
+/// assert_eq!(PcmTiming::new(11_025).sample_rate_hz(), 11_019);
+/// assert_eq!(PcmTiming::new(44_100).sample_rate_hz(), 43_956);
+/// // out of range rates are clamped
+/// assert_eq!(PcmTiming::new(100_000), PcmTiming::new(62_500));
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PcmTiming {
+    /// PWM period in 16 MHz ticks
+    pub countertop: u16,
+    /// Additional PWM periods each sample is played
+    pub refresh: u32,
+}
+
+impl PcmTiming {
+    /// Timing of the sample rate closest to `sample_rate_hz`
+    pub fn new(sample_rate_hz: u32) -> Self {
+        let sample_rate_hz = sample_rate_hz.clamp(MIN_SAMPLE_RATE_HZ, MAX_SAMPLE_RATE_HZ);
+        let ticks = (CLOCK_HZ + sample_rate_hz / 2) / sample_rate_hz;
+        let periods = (ticks - 1) / MAX_COUNTERTOP + 1;
+        Self {
+            countertop: ((ticks + periods / 2) / periods) as u16,
+            refresh: periods - 1,
+        }
+    }
+
+    /// Sample rate that is played, rounded to Hz
+    pub fn sample_rate_hz(&self) -> u32 {
+        let ticks = u32::from(self.countertop) * (self.refresh + 1);
+        (CLOCK_HZ + ticks / 2) / ticks
+    }
+
+    /// Compare value of `sample` at `volume`
+    ///
+    /// The volume from 0 to 255 scales the distance from silence.
+    ///
+    /// ```
+    /// # use microbit_common as microbit;
+    /// use microbit::sound::pcm::PcmTiming;
+    ///
+    /// let timing = PcmTiming::new(8000);
+    /// assert_eq!(timing.value(255, 255), 249);
+    /// assert_eq!(timing.value(0, 255), 0);
+    /// assert_eq!(timing.value(128, 255), 125);
+    /// assert_eq!(timing.value(255, 128), 186);
+    /// assert_eq!(timing.value(0, 0), 125);
+    /// ```
+    pub fn value(&self, sample: u8, volume: u8) -> u16 {
+        let centered = (i32::from(sample) - i32::from(SILENCE)) * i32::from(volume) / 255;
+        let level = (centered + i32::from(SILENCE)) as u32;
+        (level * u32::from(self.countertop) / MAX_COUNTERTOP) as u16
+    }
+}
+
+struct Playing {
+    clip: Clip,
+    timing: PcmTiming,
+    looping: bool,
+    // next sample to be copied into a buffer
+    position: usize,
+    // buffer holding the end of the clip
+    ending: Option<usize>,
+}
+
+/// Plays [`Clip`]s on the speaker with a PWM peripheral
+pub struct PcmPlayer<T: Instance> {
+    pwm: T,
+    pin: P0_00<Output<PushPull>>,
+    buffers: &'static mut Buffers,
+    playing: Option<Playing>,
+    volume: u8,
+}
+
+impl<T: Instance> PcmPlayer<T> {
+    /// Take the PWM peripheral, the speaker pin and the buffers
+    pub fn new(pwm: T, pin: P0_00<Disconnected>, buffers: &'static mut Buffers) -> Self {
+        let pin = pin.into_push_pull_output(Level::Low);
+        pwm.psel.out[0].write(|w| unsafe { w.bits(SPEAKER_PSEL) });
+        pwm.mode.write(|w| w.updown().up());
+        // 16 MHz
+        pwm.prescaler.write(|w| unsafe { w.bits(0) });
+        pwm.decoder
+            .write(|w| w.load().common().mode().refresh_count());
+        // SEQ[0] and SEQ[1] take turns until the playback is stopped
+        pwm.loop_.write(|w| unsafe { w.bits(1) });
+        pwm.shorts.write(|w| w.loopsdone_seqstart0().enabled());
+        pwm.intenclr.write(|w| unsafe { w.bits(0xffff_ffff) });
+        pwm.intenset.write(|w| w.seqend0().set().seqend1().set());
+        let halves = &buffers.halves;
+        for (seq, half) in [&pwm.seq0, &pwm.seq1].into_iter().zip(halves) {
+            seq.ptr.write(|w| unsafe { w.bits(half.as_ptr() as u32) });
+            seq.cnt.write(|w| unsafe { w.bits(BUFFER_LEN as u32) });
+            seq.enddelay.write(|w| unsafe { w.bits(0) });
+        }
+        Self {
+            pwm,
+            pin,
+            buffers,
+            playing: None,
+            volume: u8::MAX,
+        }
+    }
+
+    /// Volume from 0 for silence to 255, the default
+    pub fn volume(&self) -> u8 {
+        self.volume
+    }
+
+    /// Change the volume, applied to the samples that are not buffered yet
+    pub fn set_volume(&mut self, volume: u8) {
+        self.volume = volume;
+    }
+
+    /// Play `clip` once, replacing the clip being played
+    pub fn play(&mut self, clip: Clip) {
+        self.start(clip, false);
+    }
+
+    /// Play `clip` until it is stopped, replacing the clip being played
+    pub fn play_looping(&mut self, clip: Clip) {
+        self.start(clip, true);
+    }
+
+    fn start(&mut self, clip: Clip, looping: bool) {
+        self.stop();
+        if clip.samples.is_empty() {
+            return;
+        }
+        let timing = PcmTiming::new(clip.sample_rate_hz);
+        self.playing = Some(Playing {
+            clip,
+            timing,
+            looping,
+            position: 0,
+            ending: None,
+        });
+        self.fill(0);
+        self.fill(1);
+        // the buffers are written before the PWM reads them
+        compiler_fence(Ordering::SeqCst);
+
+        let pwm = &self.pwm;
+        pwm.countertop
+            .write(|w| unsafe { w.bits(timing.countertop.into()) });
+        for seq in [&pwm.seq0, &pwm.seq1] {
+            seq.refresh.write(|w| unsafe { w.bits(timing.refresh) });
+        }
+        pwm.enable.write(|w| w.enable().enabled());
+        pwm.tasks_seqstart[0].write(|w| unsafe { w.bits(1) });
+    }
+
+    /// Whether a clip is being played
+    pub fn is_playing(&self) -> bool {
+        self.playing.is_some()
+    }
+
+    /// Stop the playback
+    pub fn stop(&mut self) {
+        let pwm = &self.pwm;
+        if pwm.enable.read().enable().is_enabled() {
+            pwm.tasks_stop.write(|w| unsafe { w.bits(1) });
+            while pwm.events_stopped.read().bits() == 0 {}
+            pwm.enable.write(|w| w.enable().disabled());
+        }
+        pwm.events_stopped.write(|w| unsafe { w.bits(0) });
+        for event in &pwm.events_seqend {
+            event.write(|w| unsafe { w.bits(0) });
+        }
+        self.pin.set_low().ok();
+        self.playing = None;
+    }
+
+    /// Refill the buffer that has just been played
+    ///
+    /// Call this in the interrupt handler of the PWM peripheral. It clears
+    /// the sequence end events and stops at the end of the clip.
+    pub fn handle_pwm_event(&mut self) {
+        for half in 0..2 {
+            let event = &self.pwm.events_seqend[half];
+            if event.read().bits() == 0 {
+                continue;
+            }
+            event.write(|w| unsafe { w.bits(0) });
+            match &self.playing {
+                Some(playing) if playing.ending == Some(half) => self.stop(),
+                Some(_) => self.fill(half),
+                None => {}
+            }
+        }
+    }
+
+    /// Convert the next samples into a buffer, padded with silence
+    fn fill(&mut self, half: usize) {
+        let Some(playing) = &mut self.playing else {
+            return;
+        };
+        let samples = playing.clip.samples;
+        for value in self.buffers.halves[half].iter_mut() {
+            if playing.position == samples.len() && playing.looping {
+                playing.position = 0;
+            }
+            let sample = match samples.get(playing.position) {
+                Some(&sample) => {
+                    playing.position += 1;
+                    sample
+                }
+                None => SILENCE,
+            };
+            *value = playing.timing.value(sample, self.volume);
+        }
+        compiler_fence(Ordering::SeqCst);
+        if playing.position == samples.len() && !playing.looping && playing.ending.is_none() {
+            playing.ending = Some(half);
+        }
+    }
+
+    /// Stop the playback and release the PWM peripheral and the speaker pin
+    pub fn free(mut self) -> (T, P0_00<Disconnected>) {
+        self.stop();
+        self.pwm.psel.out[0].reset();
+        self.pwm.intenclr.write(|w| unsafe { w.bits(0xffff_ffff) });
+        (self.pwm, self.pin.into_disconnected())
+    }
+}
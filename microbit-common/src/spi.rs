@@ -0,0 +1,332 @@
+//! SPI master on the edge connector
+//!
+//! [`SpiBus`] runs the SPI peripheral in master mode on the SCK, MOSI and
+//! MISO pins of the edge connector and hands out an [`SpiDevice`] for each
+//! chip select. The devices implement the embedded-hal SPI traits and pull
+//! their chip select low for the duration of each transfer, so SD cards,
+//! shift registers and SPI displays can share the bus.
+//!
+//! The bus is borrowed for the duration of each transfer. A transfer from an
+//! interrupt handler while another one is running panics, so only use the
+//! devices from one execution context.
+#![cfg_attr(
+    feature = "v1",
+    doc = r#"
+On the Calliope mini SCK, MISO and MOSI are C7, C8 and C9 (P0.07 to P0.09),
+which also drive columns 4 to 6 of the LED matrix. [`SpiPins`] are made from
+the [`DisplayPins`], so the display has to be stopped first:
+[`SpiBus::from_display`] stops the
+[non-blocking display](crate::display::nonblocking::Display) and takes its
+pins. The other matrix pins keep all LEDs off while the bus owns them and the
+display pins can be recovered from [`SpiBus::free`].
+
+SPI0 shares its registers with TWI0, use SPI1 to keep the I2C bus.
+
+# Example
+
+```no_run
+# use microbit_common as microbit;
+use embedded_hal::blocking::spi::{Transfer, Write};
+
+use microbit::{
+    display::nonblocking::Display,
+    hal::{gpio::Level, spi::{Frequency, MODE_0}},
+    spi::SpiBus,
+    Board,
+};
+
+let board = Board::take().unwrap();
+let display = Display::new(board.TIMER1, board.display_pins);
+// later, once the display is no longer needed
+let (bus, _timer1) = SpiBus::from_display(board.SPI1, display, Frequency::M1, MODE_0);
+
+let (c16, c17) = board.grove_a1.into_digital();
+let mut sd_card = bus.device(c16.into_push_pull_output(Level::High).degrade());
+let mut shift_register = bus.device(c17.into_push_pull_output(Level::High).degrade());
+let mut response = [0xff; 2];
+sd_card.transfer(&mut response).ok();
+shift_register.write(&[0b1010_0101]).ok();
+```
+"#
+)]
+#![cfg_attr(
+    feature = "v2",
+    doc = r#"
+SCK, MISO and MOSI are P13, P14 and P15. SPI0 shares its registers with
+TWIM0, use SPI1 to keep the I2C bus.
+
+# Example
+
+```no_run
+# use microbit_common as microbit;
+use embedded_hal::blocking::spi::{Transfer, Write};
+
+use microbit::{
+    hal::{gpio::Level, spi::{Frequency, MODE_0}},
+    spi::{SpiBus, SpiPins},
+    Board,
+};
+
+let board = Board::take().unwrap();
+let pins = SpiPins::new(board.pins.p0_17, board.pins.p0_13, board.pins.p0_01);
+let bus = SpiBus::new(board.SPI1, pins, Frequency::M1, MODE_0);
+
+let mut sd_card = bus.device(board.edge.e16.into_push_pull_output(Level::High).degrade());
+let mut shift_register = bus.device(board.edge.e08.into_push_pull_output(Level::High).degrade());
+let mut response = [0xff; 2];
+sd_card.transfer(&mut response).ok();
+shift_register.write(&[0b1010_0101]).ok();
+```
+"#
+)]
+use core::cell::RefCell;
+
+use embedded_hal::{
+    blocking::spi::{Transfer, Write},
+    digital::v2::OutputPin,
+};
+
+use crate::{
+    gpio::{MISO, MOSI, SCK},
+    hal::{
+        gpio::{Floating, Input, Level, Output, Pin, PushPull},
+        spi::{self, Error, Frequency, Instance, Mode, Spi},
+    },
+};
+
+#[cfg(feature = "v1")]
+use crate::{display::nonblocking::Display, hal::timer::Instance as TimerInstance};
+
+#[cfg(feature = "v1")]
+use crate::gpio::{DisplayPins, COL1, COL2, COL3, COL7, COL8, COL9, ROW1, ROW2, ROW3};
+
+#[cfg(feature = "v2")]
+use crate::hal::gpio::Disconnected;
+
+/// GPIO numbers of SCK, MOSI and MISO
+#[cfg(feature = "v1")]
+const PSEL: [u32; 3] = [7, 9, 8];
+
+/// GPIO numbers of SCK, MOSI and MISO
+#[cfg(feature = "v2")]
+const PSEL: [u32; 3] = [17, 13, 1];
+
+/// SCK, MOSI and MISO
+pub struct SpiPins {
+    sck: SCK<Output<PushPull>>,
+    mosi: MOSI<Output<PushPull>>,
+    miso: MISO<Input<Floating>>,
+    #[cfg(feature = "v1")]
+    matrix: Matrix,
+}
+
+/// The display pins which are not used by the bus
+#[cfg(feature = "v1")]
+struct Matrix {
+    col1: COL1,
+    col2: COL2,
+    col3: COL3,
+    col7: COL7,
+    col8: COL8,
+    col9: COL9,
+    row1: ROW1,
+    row2: ROW2,
+    row3: ROW3,
+}
+
+#[cfg(feature = "v1")]
+impl From<DisplayPins> for SpiPins {
+    fn from(pins: DisplayPins) -> Self {
+        // low rows keep every LED off whatever the columns do
+        let DisplayPins {
+            col1,
+            col2,
+            col3,
+            col4,
+            col5,
+            col6,
+            col7,
+            col8,
+            col9,
+            mut row1,
+            mut row2,
+            mut row3,
+        } = pins;
+        row1.set_low().ok();
+        row2.set_low().ok();
+        row3.set_low().ok();
+        Self {
+            sck: col4.into_push_pull_output(Level::Low),
+            mosi: col6.into_push_pull_output(Level::Low),
+            miso: col5.into_floating_input(),
+            matrix: Matrix {
+                col1,
+                col2,
+                col3,
+                col7,
+                col8,
+                col9,
+                row1,
+                row2,
+                row3,
+            },
+        }
+    }
+}
+
+#[cfg(feature = "v1")]
+impl From<SpiPins> for DisplayPins {
+    fn from(pins: SpiPins) -> Self {
+        let Matrix {
+            col1,
+            col2,
+            col3,
+            col7,
+            col8,
+            col9,
+            row1,
+            row2,
+            row3,
+        } = pins.matrix;
+        DisplayPins {
+            col1,
+            col2,
+            col3,
+            col4: pins.sck.into_push_pull_output(Level::High),
+            col5: pins.miso.into_push_pull_output(Level::High),
+            col6: pins.mosi.into_push_pull_output(Level::High),
+            col7,
+            col8,
+            col9,
+            row1,
+            row2,
+            row3,
+        }
+    }
+}
+
+impl SpiPins {
+    /// Configure the pins for the bus
+    #[cfg(feature = "v2")]
+    pub fn new(sck: SCK<Disconnected>, mosi: MOSI<Disconnected>, miso: MISO<Disconnected>) -> Self {
+        Self {
+            sck: sck.into_push_pull_output(Level::Low),
+            mosi: mosi.into_push_pull_output(Level::Low),
+            miso: miso.into_floating_input(),
+        }
+    }
+
+    /// Disconnect the pins
+    #[cfg(feature = "v2")]
+    pub fn free(self) -> (SCK<Disconnected>, MOSI<Disconnected>, MISO<Disconnected>) {
+        (
+            self.sck.into_disconnected(),
+            self.mosi.into_disconnected(),
+            self.miso.into_disconnected(),
+        )
+    }
+
+    fn to_hal(&self) -> spi::Pins {
+        let [sck, mosi, miso] = PSEL;
+        // The hal only copies the pin numbers into the PSEL registers and
+        // drops its pins, the typed pins stay in `SpiPins`.
+        unsafe {
+            spi::Pins {
+                sck: Pin::from_psel_bits(sck),
+                mosi: Some(Pin::from_psel_bits(mosi)),
+                miso: Some(Pin::from_psel_bits(miso)),
+            }
+        }
+    }
+}
+
+/// SPI master shared by several devices
+pub struct SpiBus<T: Instance> {
+    spi: RefCell<Spi<T>>,
+    pins: SpiPins,
+}
+
+impl<T: Instance> SpiBus<T> {
+    /// Configure the SPI peripheral and enable it
+    pub fn new(spi: T, pins: SpiPins, frequency: Frequency, mode: Mode) -> Self {
+        let spi = Spi::new(spi, pins.to_hal(), frequency, mode);
+        Self {
+            spi: RefCell::new(spi),
+            pins,
+        }
+    }
+
+    /// Create a handle for the device selected by `cs`
+    ///
+    /// The chip select is driven high until the device is used.
+    pub fn device(&self, mut cs: Pin<Output<PushPull>>) -> SpiDevice<'_, T> {
+        cs.set_high().ok();
+        SpiDevice { bus: &self.spi, cs }
+    }
+
+    /// Disable the SPI peripheral and release it with the pins once all
+    /// devices are gone
+    pub fn free(self) -> (T, SpiPins) {
+        let spi = self.spi.into_inner().free();
+        spi.enable.write(|w| w.enable().disabled());
+        (spi, self.pins)
+    }
+}
+
+#[cfg(feature = "v1")]
+impl<T: Instance> SpiBus<T> {
+    /// Stop the display and configure the SPI peripheral on its pins
+    ///
+    /// The display timer is stopped with its interrupts disabled and
+    /// returned, so it can be reused once the display is no longer needed.
+    pub fn from_display<U: TimerInstance>(
+        spi: T,
+        display: Display<U>,
+        frequency: Frequency,
+        mode: Mode,
+    ) -> (Self, U) {
+        let (timer, pins) = display.free();
+        let regs = timer.as_timer0();
+        regs.tasks_stop.write(|w| unsafe { w.bits(1) });
+        regs.intenclr.write(|w| unsafe { w.bits(0xffff_ffff) });
+        regs.events_compare[0].write(|w| unsafe { w.bits(0) });
+        regs.events_compare[1].write(|w| unsafe { w.bits(0) });
+        (Self::new(spi, pins.into(), frequency, mode), timer)
+    }
+}
+
+/// Handle to a device on an [`SpiBus`]
+pub struct SpiDevice<'a, T: Instance> {
+    bus: &'a RefCell<Spi<T>>,
+    cs: Pin<Output<PushPull>>,
+}
+
+impl<'a, T: Instance> SpiDevice<'a, T> {
+    /// Release the chip select pin
+    pub fn free(self) -> Pin<Output<PushPull>> {
+        self.cs
+    }
+
+    fn selected<R>(&mut self, transfer: impl FnOnce(&mut Spi<T>) -> R) -> R {
+        self.cs.set_low().ok();
+        let result = transfer(&mut self.bus.borrow_mut());
+        self.cs.set_high().ok();
+        result
+    }
+}
+
+impl<'a, T: Instance> Transfer<u8> for SpiDevice<'a, T> {
+    type Error = Error;
+
+    fn transfer<'w>(&mut self, words: &'w mut [u8]) -> Result<&'w [u8], Self::Error> {
+        self.selected(|spi| spi.transfer(words))
+    }
+}
+
+impl<'a, T: Instance> Write<u8> for SpiDevice<'a, T> {
+    type Error = Error;
+
+    fn write(&mut self, words: &[u8]) -> Result<(), Self::Error> {
+        self.selected(|spi| spi.write(words))
+    }
+}
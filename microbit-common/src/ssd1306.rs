@@ -0,0 +1,392 @@
+//! SSD1306 OLED text console
+//!
+//! [`Console`] shows text on a 128x64 SSD1306 OLED, the kind sold as Grove
+//! OLED display 0.96", in [`LINES`] lines of [`COLUMNS`] characters. New
+//! lines scroll the text up like a terminal.
+//!
+//! Text is written into a small character buffer, for example with
+//! [`write!`], and sent to the display with [`Console::flush`]. Only lines
+//! which changed are rendered, one 8 pixel high page at a time, so no frame
+//! buffer is needed. Scrolling moves the start line of the display instead of
+//! redrawing it.
+//!
+//! The display is passed to every call that talks to it. This leaves the bus
+//! free for other devices, like the motion sensor which shares the I2C bus
+//! with the Grove port; see [`SharedBus`](crate::i2c::SharedBus) to share
+//! the bus with drivers that take ownership of it.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! # use microbit_common as microbit;
+//! use core::fmt::Write as _;
+//!
+//! use embedded_hal::blocking::i2c::Write;
+//! use microbit::ssd1306::{Console, DEFAULT_ADDRESS};
+//!
+//! fn hello<I2C: Write>(i2c: &mut I2C) -> Result<(), I2C::Error> {
+//!     let mut console = Console::new(DEFAULT_ADDRESS);
+//!     console.init(i2c)?;
+//!     for count in 0..100 {
+//!         writeln!(console, "count: {}", count).ok();
+//!         console.flush(i2c)?;
+//!     }
+//!     Ok(())
+//! }
+//! ```
+use core::fmt;
+
+use embedded_hal::blocking::i2c::Write;
+
+/// I2C address of most modules, some can be switched to `0x3d`
+pub const DEFAULT_ADDRESS: u8 = 0x3c;
+
+/// Width of the display in pixels
+pub const WIDTH: usize = 128;
+
+/// Characters per line, 5x7 glyphs in 6 pixel wide cells
+pub const COLUMNS: usize = WIDTH / CELL_WIDTH;
+
+/// Lines of text, one per 8 pixel high page
+pub const LINES: usize = 8;
+
+const CELL_WIDTH: usize = 6;
+
+/// Control byte: the rest of the transfer are commands
+const COMMANDS: u8 = 0x00;
+
+/// Control byte: the rest of the transfer is display data
+const DATA: u8 = 0x40;
+
+const SET_START_LINE: u8 = 0x40;
+const SET_PAGE: u8 = 0xb0;
+const DISPLAY_OFF: u8 = 0xae;
+const DISPLAY_ON: u8 = 0xaf;
+
+const INIT: [u8; 26] = [
+    COMMANDS,
+    DISPLAY_OFF,
+    // clock divider and oscillator frequency
+    0xd5,
+    0x80,
+    // multiplex ratio: 64 lines
+    0xa8,
+    0x3f,
+    // display offset
+    0xd3,
+    0x00,
+    SET_START_LINE,
+    // charge pump on
+    0x8d,
+    0x14,
+    // page addressing mode
+    0x20,
+    0x02,
+    // segment remap and COM scan direction: not mirrored
+    0xa1,
+    0xc8,
+    // COM pins configuration
+    0xda,
+    0x12,
+    // contrast
+    0x81,
+    0xcf,
+    // precharge period
+    0xd9,
+    0xf1,
+    // VCOMH deselect level
+    0xdb,
+    0x40,
+    // show the RAM contents, not inverted
+    0xa4,
+    0xa6,
+    DISPLAY_ON,
+];
+
+/// Columns of the printable ASCII characters from `' '` to `'~'`, least
+/// significant bit at the top
+const FONT: [[u8; 5]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00],
+    [0x00, 0x00, 0x5f, 0x00, 0x00],
+    [0x00, 0x07, 0x00, 0x07, 0x00],
+    [0x14, 0x7f, 0x14, 0x7f, 0x14],
+    [0x24, 0x2a, 0x7f, 0x2a, 0x12],
+    [0x23, 0x13, 0x08, 0x64, 0x62],
+    [0x36, 0x49, 0x55, 0x22, 0x50],
+    [0x00, 0x05, 0x03, 0x00, 0x00],
+    [0x00, 0x1c, 0x22, 0x41, 0x00],
+    [0x00, 0x41, 0x22, 0x1c, 0x00],
+    [0x14, 0x08, 0x3e, 0x08, 0x14],
+    [0x08, 0x08, 0x3e, 0x08, 0x08],
+    [0x00, 0x50, 0x30, 0x00, 0x00],
+    [0x08, 0x08, 0x08, 0x08, 0x08],
+    [0x00, 0x60, 0x60, 0x00, 0x00],
+    [0x20, 0x10, 0x08, 0x04, 0x02],
+    [0x3e, 0x51, 0x49, 0x45, 0x3e],
+    [0x00, 0x42, 0x7f, 0x40, 0x00],
+    [0x42, 0x61, 0x51, 0x49, 0x46],
+    [0x21, 0x41, 0x45, 0x4b, 0x31],
+    [0x18, 0x14, 0x12, 0x7f, 0x10],
+    [0x27, 0x45, 0x45, 0x45, 0x39],
+    [0x3c, 0x4a, 0x49, 0x49, 0x30],
+    [0x01, 0x71, 0x09, 0x05, 0x03],
+    [0x36, 0x49, 0x49, 0x49, 0x36],
+    [0x06, 0x49, 0x49, 0x29, 0x1e],
+    [0x00, 0x36, 0x36, 0x00, 0x00],
+    [0x00, 0x56, 0x36, 0x00, 0x00],
+    [0x08, 0x14, 0x22, 0x41, 0x00],
+    [0x14, 0x14, 0x14, 0x14, 0x14],
+    [0x00, 0x41, 0x22, 0x14, 0x08],
+    [0x02, 0x01, 0x51, 0x09, 0x06],
+    [0x32, 0x49, 0x79, 0x41, 0x3e],
+    [0x7e, 0x11, 0x11, 0x11, 0x7e],
+    [0x7f, 0x49, 0x49, 0x49, 0x36],
+    [0x3e, 0x41, 0x41, 0x41, 0x22],
+    [0x7f, 0x41, 0x41, 0x22, 0x1c],
+    [0x7f, 0x49, 0x49, 0x49, 0x41],
+    [0x7f, 0x09, 0x09, 0x09, 0x01],
+    [0x3e, 0x41, 0x49, 0x49, 0x7a],
+    [0x7f, 0x08, 0x08, 0x08, 0x7f],
+    [0x00, 0x41, 0x7f, 0x41, 0x00],
+    [0x20, 0x40, 0x41, 0x3f, 0x01],
+    [0x7f, 0x08, 0x14, 0x22, 0x41],
+    [0x7f, 0x40, 0x40, 0x40, 0x40],
+    [0x7f, 0x02, 0x0c, 0x02, 0x7f],
+    [0x7f, 0x04, 0x08, 0x10, 0x7f],
+    [0x3e, 0x41, 0x41, 0x41, 0x3e],
+    [0x7f, 0x09, 0x09, 0x09, 0x06],
+    [0x3e, 0x41, 0x51, 0x21, 0x5e],
+    [0x7f, 0x09, 0x19, 0x29, 0x46],
+    [0x46, 0x49, 0x49, 0x49, 0x31],
+    [0x01, 0x01, 0x7f, 0x01, 0x01],
+    [0x3f, 0x40, 0x40, 0x40, 0x3f],
+    [0x1f, 0x20, 0x40, 0x20, 0x1f],
+    [0x3f, 0x40, 0x38, 0x40, 0x3f],
+    [0x63, 0x14, 0x08, 0x14, 0x63],
+    [0x07, 0x08, 0x70, 0x08, 0x07],
+    [0x61, 0x51, 0x49, 0x45, 0x43],
+    [0x00, 0x7f, 0x41, 0x41, 0x00],
+    [0x02, 0x04, 0x08, 0x10, 0x20],
+    [0x00, 0x41, 0x41, 0x7f, 0x00],
+    [0x04, 0x02, 0x01, 0x02, 0x04],
+    [0x40, 0x40, 0x40, 0x40, 0x40],
+    [0x00, 0x01, 0x02, 0x04, 0x00],
+    [0x20, 0x54, 0x54, 0x54, 0x78],
+    [0x7f, 0x48, 0x44, 0x44, 0x38],
+    [0x38, 0x44, 0x44, 0x44, 0x20],
+    [0x38, 0x44, 0x44, 0x48, 0x7f],
+    [0x38, 0x54, 0x54, 0x54, 0x18],
+    [0x08, 0x7e, 0x09, 0x01, 0x02],
+    [0x0c, 0x52, 0x52, 0x52, 0x3e],
+    [0x7f, 0x08, 0x04, 0x04, 0x78],
+    [0x00, 0x44, 0x7d, 0x40, 0x00],
+    [0x20, 0x40, 0x44, 0x3d, 0x00],
+    [0x7f, 0x10, 0x28, 0x44, 0x00],
+    [0x00, 0x41, 0x7f, 0x40, 0x00],
+    [0x7c, 0x04, 0x18, 0x04, 0x78],
+    [0x7c, 0x08, 0x04, 0x04, 0x78],
+    [0x38, 0x44, 0x44, 0x44, 0x38],
+    [0x7c, 0x14, 0x14, 0x14, 0x08],
+    [0x08, 0x14, 0x14, 0x18, 0x7c],
+    [0x7c, 0x08, 0x04, 0x04, 0x08],
+    [0x48, 0x54, 0x54, 0x54, 0x20],
+    [0x04, 0x3f, 0x44, 0x40, 0x20],
+    [0x3c, 0x40, 0x40, 0x20, 0x7c],
+    [0x1c, 0x20, 0x40, 0x20, 0x1c],
+    [0x3c, 0x40, 0x30, 0x40, 0x3c],
+    [0x44, 0x28, 0x10, 0x28, 0x44],
+    [0x0c, 0x50, 0x50, 0x50, 0x3c],
+    [0x44, 0x64, 0x54, 0x4c, 0x44],
+    [0x00, 0x08, 0x36, 0x41, 0x00],
+    [0x00, 0x00, 0x7f, 0x00, 0x00],
+    [0x00, 0x41, 0x36, 0x08, 0x00],
+    [0x10, 0x08, 0x08, 0x10, 0x08],
+];
+
+/// Columns of a character, `'?'` for characters outside printable ASCII
+///
+/// ```
+/// # use microbit_common as microbit;
+/// use microbit::ssd1306::glyph;
+///
+/// assert_eq!(glyph('1'), [0x00, 0x42, 0x7f, 0x40, 0x00]);
+/// assert_eq!(glyph('é'), glyph('?'));
+/// ```
+pub fn glyph(c: char) -> [u8; 5] {
+    let index = match c {
+        ' '..='~' => c as usize - ' ' as usize,
+        _ => '?' as usize - ' ' as usize,
+    };
+    FONT[index]
+}
+
+/// Scrolling text console on an SSD1306
+///
+/// ```
+/// # use microbit_common as microbit;
+/// use core::fmt::Write;
+///
+/// use microbit::ssd1306::{Console, DEFAULT_ADDRESS, LINES};
+///
+/// let mut console = Console::new(DEFAULT_ADDRESS);
+/// write!(console, "hello\nworld").unwrap();
+/// assert_eq!(console.line(0), "hello");
+/// assert_eq!(console.line(1), "world");
+///
+/// // the first line scrolls out at the top of the display
+/// for _ in 1..LINES {
+///     writeln!(console).unwrap();
+/// }
+/// assert_eq!(console.line(0), "world");
+/// ```
+pub struct Console {
+    address: u8,
+    /// Lines in display RAM order, the screen starts at `top`
+    text: [[u8; COLUMNS]; LINES],
+    lengths: [u8; LINES],
+    top: usize,
+    row: usize,
+    column: usize,
+    /// Bit n set if RAM page n has to be rendered
+    dirty: u8,
+    scrolled: bool,
+}
+
+impl Console {
+    /// Empty console for the display at `address`
+    pub fn new(address: u8) -> Self {
+        Self {
+            address,
+            text: [[b' '; COLUMNS]; LINES],
+            lengths: [0; LINES],
+            top: 0,
+            row: 0,
+            column: 0,
+            dirty: 0xff,
+            scrolled: true,
+        }
+    }
+
+    /// Configure and switch on the display, then show the console
+    pub fn init<I2C: Write>(&mut self, i2c: &mut I2C) -> Result<(), I2C::Error> {
+        i2c.write(self.address, &INIT)?;
+        self.dirty = 0xff;
+        self.scrolled = true;
+        self.flush(i2c)
+    }
+
+    /// Switch the display on or off, the console is kept
+    pub fn set_on<I2C: Write>(&mut self, i2c: &mut I2C, on: bool) -> Result<(), I2C::Error> {
+        let command = if on { DISPLAY_ON } else { DISPLAY_OFF };
+        i2c.write(self.address, &[COMMANDS, command])
+    }
+
+    /// Set the contrast, 0 to 255
+    pub fn set_contrast<I2C: Write>(
+        &mut self,
+        i2c: &mut I2C,
+        contrast: u8,
+    ) -> Result<(), I2C::Error> {
+        i2c.write(self.address, &[COMMANDS, 0x81, contrast])
+    }
+
+    /// Text of a line on the screen, from the top
+    pub fn line(&self, row: usize) -> &str {
+        let page = self.page(row);
+        let text = &self.text[page][..usize::from(self.lengths[page])];
+        // only printable ASCII is stored
+        core::str::from_utf8(text).unwrap_or("")
+    }
+
+    /// Blank the console and move the cursor to the top left
+    pub fn clear(&mut self) {
+        *self = Self::new(self.address);
+    }
+
+    /// Move the cursor to a position on the screen
+    pub fn set_cursor(&mut self, row: usize, column: usize) {
+        self.row = row.min(LINES - 1);
+        self.column = column.min(COLUMNS - 1);
+    }
+
+    /// Add a character at the cursor
+    ///
+    /// `'\n'` starts a new line and `'\r'` returns to the start of the line.
+    /// Long lines wrap.
+    pub fn write_char(&mut self, c: char) {
+        match c {
+            '\n' => self.new_line(),
+            '\r' => self.column = 0,
+            c => {
+                if self.column == COLUMNS {
+                    self.new_line();
+                }
+                let page = self.page(self.row);
+                let byte = if c.is_ascii() && !c.is_ascii_control() {
+                    c as u8
+                } else {
+                    b'?'
+                };
+                self.text[page][self.column] = byte;
+                self.column += 1;
+                let length = &mut self.lengths[page];
+                *length = (*length).max(self.column as u8);
+                self.dirty |= 1 << page;
+            }
+        }
+    }
+
+    /// Send the changes to the display
+    pub fn flush<I2C: Write>(&mut self, i2c: &mut I2C) -> Result<(), I2C::Error> {
+        for page in 0..LINES {
+            if self.dirty & (1 << page) != 0 {
+                self.render(i2c, page)?;
+                self.dirty &= !(1 << page);
+            }
+        }
+        if self.scrolled {
+            let start_line = SET_START_LINE | (self.top * 8) as u8;
+            i2c.write(self.address, &[COMMANDS, start_line])?;
+            self.scrolled = false;
+        }
+        Ok(())
+    }
+
+    fn page(&self, row: usize) -> usize {
+        (self.top + row) % LINES
+    }
+
+    fn new_line(&mut self) {
+        self.column = 0;
+        if self.row < LINES - 1 {
+            self.row += 1;
+            return;
+        }
+        // the top line becomes the new bottom line
+        let page = self.top;
+        self.top = (self.top + 1) % LINES;
+        self.text[page] = [b' '; COLUMNS];
+        self.lengths[page] = 0;
+        self.dirty |= 1 << page;
+        self.scrolled = true;
+    }
+
+    fn render<I2C: Write>(&self, i2c: &mut I2C, page: usize) -> Result<(), I2C::Error> {
+        i2c.write(self.address, &[COMMANDS, SET_PAGE | page as u8, 0x00, 0x10])?;
+        let mut data = [0; 1 + WIDTH];
+        data[0] = DATA;
+        for (cell, byte) in data[1..].chunks_mut(CELL_WIDTH).zip(self.text[page]) {
+            cell[..5].copy_from_slice(&glyph(char::from(byte)));
+        }
+        i2c.write(self.address, &data)
+    }
+}
+
+impl fmt::Write for Console {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            self.write_char(c);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,428 @@
+//! Stepper motors
+//!
+//! [`Stepper`] energises the four phases of a stepper motor in wave,
+//! full-step or half-step sequence. The phases are numbered in the order
+//! they are energised during wave stepping:
+//!
+//! - unipolar motors like the 28BYJ-48 on a ULN2003 board: IN1 to IN4
+//! - bipolar motors on two H-bridges: A+, B+, A-, B-
+//!
+#![cfg_attr(
+    feature = "v1",
+    doc = "A bipolar motor can use the onboard DRV8837 H-bridge for coil A and an external H-bridge on two edge pins for coil B, see [`Stepper::drv8837`]."
+)]
+//!
+//! Steps are paced by a TIMER: call [`Stepper::handle_stepper_event()`] in
+//! the interrupt handler of the timer you are using. The speed ramps up and
+//! down with the configured acceleration and the position is tracked in
+//! steps.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! # use microbit_common as microbit;
+//! use microbit::{
+//!     hal::gpio::Level,
+//!     stepper::{StepMode, Stepper},
+//!     Board,
+//! };
+//!
+//! let board = Board::take().unwrap();
+//! let edge = board.edge;
+//! # #[cfg(feature = "v1")]
+//! let in4 = edge.e03;
+//! # #[cfg(feature = "v2")]
+//! # let in4 = edge.e16;
+//! let mut stepper = Stepper::new(
+//!     board.TIMER1,
+//!     [
+//!         edge.e00.into_push_pull_output(Level::Low).degrade(),
+//!         edge.e01.into_push_pull_output(Level::Low).degrade(),
+//!         edge.e02.into_push_pull_output(Level::Low).degrade(),
+//!         in4.into_push_pull_output(Level::Low).degrade(),
+//!     ],
+//!     StepMode::Half,
+//! );
+//! stepper.set_max_speed(800);
+//! stepper.set_acceleration(400);
+//! // one turn of a 28BYJ-48 in half steps
+//! stepper.move_by(4096);
+//!
+//! // in the TIMER1 interrupt
+//! {
+//!     stepper.handle_stepper_event();
+//! }
+//! ```
+use embedded_hal::digital::v2::OutputPin;
+
+#[cfg(feature = "v1")]
+use crate::gpio::BeeperMotorPins;
+use crate::hal::{
+    gpio::{Output, Pin, PushPull},
+    timer::Instance,
+};
+
+/// Slowest speed in steps/s, limited by the 16 bit step timer
+pub const MIN_SPEED: u32 = 16;
+
+/// Fastest speed in steps/s, one step per µs of the step timer
+///
+/// The interrupt handler has to keep up with the steps, so the speeds that
+/// can actually be reached are much lower.
+pub const MAX_SPEED: u32 = 1_000_000;
+
+/// Stepping sequence
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StepMode {
+    /// One phase energised at a time, lowest torque and current
+    Wave,
+    /// Two phases energised at a time, full torque
+    Full,
+    /// Alternating one and two phases, twice the resolution
+    Half,
+}
+
+impl StepMode {
+    /// Phase patterns in stepping order, bit n is phase n
+    ///
+    /// ```
+    /// # use microbit_common as microbit;
+    /// use microbit::stepper::StepMode;
+    ///
+    /// assert_eq!(StepMode::Full.sequence()[0], 0b0011);
+    /// assert_eq!(StepMode::Half.sequence().len(), 8);
+    /// ```
+    pub fn sequence(self) -> &'static [u8] {
+        match self {
+            StepMode::Wave => &[0b0001, 0b0010, 0b0100, 0b1000],
+            StepMode::Full => &[0b0011, 0b0110, 0b1100, 0b1001],
+            StepMode::Half => &[
+                0b0001, 0b0011, 0b0010, 0b0110, 0b0100, 0b1100, 0b1000, 0b1001,
+            ],
+        }
+    }
+}
+
+type Phase = Pin<Output<PushPull>>;
+
+enum Wiring {
+    Edge([Phase; 4]),
+    #[cfg(feature = "v1")]
+    Drv8837 {
+        motor: BeeperMotorPins,
+        coil_b: [Phase; 2],
+    },
+}
+
+/// Pins released by [`Stepper::free`]
+pub enum StepperPins {
+    /// Phase pins of [`Stepper::new`]
+    Edge([Phase; 4]),
+    /// H-bridge and coil B pins of [`Stepper::drv8837`]
+    #[cfg(feature = "v1")]
+    Drv8837(BeeperMotorPins, [Phase; 2]),
+}
+
+/// Stepper motor driver
+pub struct Stepper<T: Instance> {
+    timer: T,
+    wiring: Wiring,
+    mode: StepMode,
+    sequence_index: usize,
+    position: i32,
+    target: i32,
+    direction: i32,
+    speed: u32,
+    max_speed: u32,
+    acceleration: u32,
+}
+
+fn set(pin: &mut impl OutputPin, high: bool) {
+    if high {
+        pin.set_high().ok();
+    } else {
+        pin.set_low().ok();
+    }
+}
+
+fn isqrt(value: u64) -> u32 {
+    let mut root = 0u64;
+    let mut bit = 1u64 << 62;
+    let mut rest = value;
+    while bit > rest {
+        bit >>= 2;
+    }
+    while bit != 0 {
+        if rest >= root + bit {
+            rest -= root + bit;
+            root = (root >> 1) + bit;
+        } else {
+            root >>= 1;
+        }
+        bit >>= 2;
+    }
+    root as u32
+}
+
+impl<T: Instance> Stepper<T> {
+    /// Drive a motor from four pins, e.g. a 28BYJ-48 on a ULN2003 board
+    pub fn new(timer: T, phases: [Phase; 4], mode: StepMode) -> Self {
+        Self::with_wiring(timer, Wiring::Edge(phases), mode)
+    }
+
+    /// Drive coil A of a bipolar motor from the onboard DRV8837
+    ///
+    /// `coil_b` are the inputs of an external H-bridge driving coil B. The
+    /// DRV8837 is put to sleep while the motor is released.
+    #[cfg(feature = "v1")]
+    pub fn drv8837(timer: T, motor: BeeperMotorPins, coil_b: [Phase; 2], mode: StepMode) -> Self {
+        Self::with_wiring(timer, Wiring::Drv8837 { motor, coil_b }, mode)
+    }
+
+    fn with_wiring(timer: T, wiring: Wiring, mode: StepMode) -> Self {
+        let regs = timer.as_timer0();
+        regs.tasks_stop.write(|w| unsafe { w.bits(1) });
+        regs.tasks_clear.write(|w| unsafe { w.bits(1) });
+        regs.mode.write(|w| w.mode().timer());
+        regs.bitmode.write(|w| w.bitmode()._16bit());
+        // 16 MHz / 2^4 = 1 MHz
+        regs.prescaler.write(|w| unsafe { w.bits(4) });
+        regs.shorts.write(|w| w.compare0_clear().enabled());
+        regs.intenclr.write(|w| unsafe { w.bits(0xffff_ffff) });
+        regs.intenset.write(|w| w.compare0().set());
+
+        let mut stepper = Self {
+            timer,
+            wiring,
+            mode,
+            sequence_index: 0,
+            position: 0,
+            target: 0,
+            direction: 0,
+            speed: 0,
+            max_speed: 200,
+            acceleration: 0,
+        };
+        stepper.release();
+        stepper
+    }
+
+    /// Change the stepping sequence
+    ///
+    /// Positions are counted in steps of the current mode, switching between
+    /// half and full steps does not convert them.
+    pub fn set_mode(&mut self, mode: StepMode) {
+        let len = mode.sequence().len();
+        // keep the current phase pattern as close as possible
+        self.sequence_index = self.sequence_index * len / self.mode.sequence().len();
+        self.mode = mode;
+    }
+
+    /// Set the top speed in steps/s, from [`MIN_SPEED`] to [`MAX_SPEED`]
+    pub fn set_max_speed(&mut self, steps_per_second: u32) {
+        self.max_speed = steps_per_second.clamp(MIN_SPEED, MAX_SPEED);
+    }
+
+    /// Set the acceleration in steps/s², 0 starts and stops at top speed
+    pub fn set_acceleration(&mut self, steps_per_second2: u32) {
+        self.acceleration = steps_per_second2;
+    }
+
+    /// Current position in steps
+    pub fn position(&self) -> i32 {
+        self.position
+    }
+
+    /// Redefine the current position, e.g. after homing
+    pub fn set_position(&mut self, position: i32) {
+        self.target = self.target - self.position + position;
+        self.position = position;
+    }
+
+    /// Position the motor is moving to
+    pub fn target(&self) -> i32 {
+        self.target
+    }
+
+    /// The motor is moving
+    pub fn is_running(&self) -> bool {
+        self.speed != 0
+    }
+
+    /// Move to an absolute position
+    ///
+    /// A motor moving in the other direction slows down before it turns.
+    pub fn move_to(&mut self, target: i32) {
+        self.target = target;
+        if self.speed == 0 && self.target != self.position {
+            self.direction = (self.target - self.position).signum();
+            self.speed = self.start_speed();
+            self.energise();
+            let regs = self.timer.as_timer0();
+            regs.cc[0].write(|w| unsafe { w.bits(1_000_000 / self.speed) });
+            regs.tasks_clear.write(|w| unsafe { w.bits(1) });
+            regs.tasks_start.write(|w| unsafe { w.bits(1) });
+        }
+    }
+
+    /// Move relative to the target of the current move
+    pub fn move_by(&mut self, steps: i32) {
+        self.move_to(self.target + steps);
+    }
+
+    /// Slow down and stop as soon as the acceleration allows
+    pub fn stop(&mut self) {
+        if self.speed != 0 {
+            let steps = self.stopping_steps() as i32;
+            self.target = self.position + self.direction * steps.max(1);
+        }
+    }
+
+    /// De-energise the coils, the motor no longer holds its position
+    ///
+    /// A running motor stops immediately.
+    pub fn release(&mut self) {
+        self.halt();
+        match &mut self.wiring {
+            Wiring::Edge(phases) => {
+                for phase in phases {
+                    phase.set_low().ok();
+                }
+            }
+            #[cfg(feature = "v1")]
+            Wiring::Drv8837 { motor, coil_b } => {
+                motor.motor_nsleep.set_low().ok();
+                motor.motor_in1.set_low().ok();
+                motor.motor_in2.set_low().ok();
+                for pin in coil_b {
+                    pin.set_low().ok();
+                }
+            }
+        }
+    }
+
+    /// Advance the motor by a step and schedule the next one
+    ///
+    /// Call this in the interrupt handler of the timer. It clears the
+    /// timer's event registers.
+    pub fn handle_stepper_event(&mut self) {
+        let regs = self.timer.as_timer0();
+        if regs.events_compare[0].read().bits() == 0 {
+            return;
+        }
+        regs.events_compare[0].write(|w| unsafe { w.bits(0) });
+        if self.speed == 0 {
+            return;
+        }
+
+        let wanted = (self.target - self.position).signum();
+        if wanted != self.direction && self.speed <= self.start_speed() {
+            // slow enough to turn around or to stop
+            self.direction = wanted;
+        }
+        if self.direction == 0 {
+            self.halt();
+            return;
+        }
+
+        self.step();
+
+        let remaining = (self.target - self.position) * self.direction;
+        if remaining == 0 && self.speed <= self.start_speed() {
+            self.halt();
+            return;
+        }
+        self.speed = self.next_speed(remaining);
+        let interval = 1_000_000 / self.speed;
+        self.timer.as_timer0().cc[0].write(|w| unsafe { w.bits(interval) });
+    }
+
+    /// Stop the timer and release it with the pins
+    ///
+    /// The coils are de-energised.
+    pub fn free(mut self) -> (T, StepperPins) {
+        self.release();
+        let pins = match self.wiring {
+            Wiring::Edge(phases) => StepperPins::Edge(phases),
+            #[cfg(feature = "v1")]
+            Wiring::Drv8837 { motor, coil_b } => StepperPins::Drv8837(motor, coil_b),
+        };
+        (self.timer, pins)
+    }
+
+    fn halt(&mut self) {
+        self.timer
+            .as_timer0()
+            .tasks_stop
+            .write(|w| unsafe { w.bits(1) });
+        self.speed = 0;
+        self.direction = 0;
+        self.target = self.position;
+    }
+
+    fn start_speed(&self) -> u32 {
+        if self.acceleration == 0 {
+            self.max_speed
+        } else {
+            isqrt(2 * u64::from(self.acceleration)).clamp(MIN_SPEED, self.max_speed)
+        }
+    }
+
+    fn stopping_steps(&self) -> u32 {
+        if self.acceleration == 0 {
+            0
+        } else {
+            let speed = u64::from(self.speed);
+            (speed * speed / (2 * u64::from(self.acceleration))) as u32
+        }
+    }
+
+    /// Speed for the next step with `remaining` steps to go
+    ///
+    /// `remaining` is negative while the motor overshoots to slow down
+    /// before turning around.
+    fn next_speed(&self, remaining: i32) -> u32 {
+        if self.acceleration == 0 {
+            return self.max_speed;
+        }
+        let speed2 = u64::from(self.speed) * u64::from(self.speed);
+        let delta = 2 * u64::from(self.acceleration);
+        let must_brake = remaining <= 0 || remaining as u32 <= self.stopping_steps();
+        if must_brake || self.speed > self.max_speed {
+            isqrt(speed2.saturating_sub(delta)).max(self.start_speed())
+        } else {
+            isqrt(speed2 + delta).min(self.max_speed)
+        }
+    }
+
+    fn step(&mut self) {
+        let len = self.mode.sequence().len();
+        self.sequence_index = if self.direction > 0 {
+            (self.sequence_index + 1) % len
+        } else {
+            (self.sequence_index + len - 1) % len
+        };
+        self.position += self.direction;
+        self.energise();
+    }
+
+    fn energise(&mut self) {
+        let pattern = self.mode.sequence()[self.sequence_index];
+        let phase = |n: u8| pattern & (1 << n) != 0;
+        match &mut self.wiring {
+            Wiring::Edge(phases) => {
+                for (n, pin) in phases.iter_mut().enumerate() {
+                    set(pin, phase(n as u8));
+                }
+            }
+            #[cfg(feature = "v1")]
+            Wiring::Drv8837 { motor, coil_b } => {
+                set(&mut motor.motor_in1, phase(0));
+                set(&mut coil_b[0], phase(1));
+                set(&mut motor.motor_in2, phase(2));
+                set(&mut coil_b[1], phase(3));
+                motor.motor_nsleep.set_high().ok();
+            }
+        }
+    }
+}
@@ -0,0 +1,471 @@
+//! TM1637 4-digit 7-segment displays
+//!
+//! Grove 4-digit displays and most clock displays use a TM1637 controller
+//! with a two wire interface similar to I2C: a clock line and a data line.
+//! [`Tm1637`] bit-bangs the protocol on any two pins implementing the
+//! embedded-hal [`OutputPin`] trait, for example two edge pins or the Grove
+//! A1 port, which are `C16` and `C17` on the Calliope mini.
+//!
+//! The TM1637 pulls the data line low to acknowledge each byte. Use an open
+//! drain output for the data pin so it can do so, the acknowledge is not
+//! checked.
+//!
+//! Segments are given as a byte per digit with bit 0 to 6 for segments A to
+//! G and bit 7 for the decimal point or the colon:
+//!
+//! ```text
+//!  -A-
+//! F   B
+//!  -G-
+//! E   C
+//!  -D-
+//! ```
+//!
+//! ## Example
+//!
+//! ```no_run
+//! # use microbit_common as microbit;
+//! use microbit::{
+//!     hal::{
+//!         gpio::{Level, OpenDrainConfig},
+//!         Timer,
+//!     },
+//!     tm1637::Tm1637,
+//!     Board,
+//! };
+//!
+//! let board = Board::take().unwrap();
+//! let clk = board.edge.e01.into_push_pull_output(Level::High);
+//! let dio = board
+//!     .edge
+//!     .e02
+//!     .into_open_drain_output(OpenDrainConfig::Standard0Disconnect1, Level::High);
+//! let mut display = Tm1637::new(clk, dio, Timer::new(board.TIMER0));
+//!
+//! display.set_brightness(4);
+//! display.show_number(1234);
+//! display.set_colon(true);
+//! display.scroll_text("HELLO", 300);
+//! ```
+use embedded_hal::{blocking::delay::DelayUs, digital::v2::OutputPin};
+
+/// Number of digits
+pub const NUM_DIGITS: usize = 4;
+
+/// Highest brightness
+pub const MAX_BRIGHTNESS: u8 = 7;
+
+/// Segment bit of the decimal point, the colon on the second digit
+pub const SEG_DP: u8 = 0x80;
+
+/// Segment bit of the minus sign
+pub const SEG_MINUS: u8 = 0x40;
+
+const DATA_AUTO_INCREMENT: u8 = 0x40;
+const ADDRESS: u8 = 0xc0;
+const DISPLAY_CONTROL: u8 = 0x80;
+const DISPLAY_ON: u8 = 0x08;
+
+/// Half a clock period in µs, the TM1637 handles up to 250 kHz
+const HALF_PERIOD_US: u32 = 5;
+
+/// Digit carrying the colon on clock displays
+const COLON_DIGIT: usize = 1;
+
+/// Segments of the hexadecimal digits
+pub const DIGITS: [u8; 16] = [
+    0x3f, 0x06, 0x5b, 0x4f, 0x66, 0x6d, 0x7d, 0x07, 0x7f, 0x6f, 0x77, 0x7c, 0x39, 0x5e, 0x79, 0x71,
+];
+
+/// Segments of a character
+///
+/// Letters are shown as the closest 7-segment glyph, upper or lower case
+/// depending on which is easier to read. Characters without a glyph are
+/// blank.
+///
+/// ```
+/// # use microbit_common as microbit;
+/// use microbit::tm1637::char_to_segments;
+///
+/// assert_eq!(char_to_segments('8'), 0x7f);
+/// assert_eq!(char_to_segments('h'), char_to_segments('H'));
+/// assert_eq!(char_to_segments('-'), 0x40);
+/// assert_eq!(char_to_segments('~'), 0);
+/// ```
+pub fn char_to_segments(c: char) -> u8 {
+    match c.to_ascii_uppercase() {
+        c @ '0'..='9' => DIGITS[c as usize - '0' as usize],
+        'A' => 0x77,
+        'B' => 0x7c,
+        'C' => 0x39,
+        'D' => 0x5e,
+        'E' => 0x79,
+        'F' => 0x71,
+        'G' => 0x3d,
+        'H' => 0x76,
+        'I' => 0x30,
+        'J' => 0x1e,
+        'K' => 0x75,
+        'L' => 0x38,
+        'M' => 0x15,
+        'N' => 0x54,
+        'O' => 0x5c,
+        'P' => 0x73,
+        'Q' => 0x67,
+        'R' => 0x50,
+        'S' => 0x6d,
+        'T' => 0x78,
+        'U' => 0x3e,
+        'V' => 0x1c,
+        'W' => 0x2a,
+        'X' => 0x76,
+        'Y' => 0x6e,
+        'Z' => 0x5b,
+        '-' => SEG_MINUS,
+        '_' => 0x08,
+        '=' => 0x48,
+        '°' => 0x63,
+        '\'' => 0x02,
+        '"' => 0x22,
+        _ => 0,
+    }
+}
+
+/// Segments of a right aligned number from -999 to 9999
+///
+/// Numbers which do not fit are shown as `----`.
+///
+/// ```
+/// # use microbit_common as microbit;
+/// use microbit::tm1637::{number_to_segments, DIGITS, SEG_MINUS};
+///
+/// assert_eq!(number_to_segments(42), [0, 0, DIGITS[4], DIGITS[2]]);
+/// assert_eq!(number_to_segments(-7), [0, 0, SEG_MINUS, DIGITS[7]]);
+/// assert_eq!(number_to_segments(12345), [SEG_MINUS; 4]);
+/// ```
+pub fn number_to_segments(number: i32) -> [u8; NUM_DIGITS] {
+    if !(-999..=9999).contains(&number) {
+        return [SEG_MINUS; NUM_DIGITS];
+    }
+    let mut segments = [0; NUM_DIGITS];
+    let mut rest = number.unsigned_abs();
+    let mut position = NUM_DIGITS;
+    loop {
+        position -= 1;
+        segments[position] = DIGITS[(rest % 10) as usize];
+        rest /= 10;
+        if rest == 0 {
+            break;
+        }
+    }
+    if number < 0 {
+        segments[position - 1] = SEG_MINUS;
+    }
+    segments
+}
+
+/// Frames of text scrolling from right to left
+///
+/// The text enters on the right and leaves on the left, so each character
+/// is shown in every position.
+///
+/// ```
+/// # use microbit_common as microbit;
+/// use microbit::tm1637::{char_to_segments, Scroll};
+///
+/// let frames: Vec<_> = Scroll::new("HI").collect();
+/// let (h, i) = (char_to_segments('H'), char_to_segments('I'));
+/// assert_eq!(frames.len(), 6);
+/// assert_eq!(frames[0], [0, 0, 0, h]);
+/// assert_eq!(frames[1], [0, 0, h, i]);
+/// assert_eq!(frames[4], [i, 0, 0, 0]);
+/// assert_eq!(frames[5], [0, 0, 0, 0]);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Scroll<'a> {
+    text: &'a str,
+    frame: usize,
+    len: usize,
+}
+
+impl<'a> Scroll<'a> {
+    /// Scroll `text`
+    pub fn new(text: &'a str) -> Self {
+        Self {
+            text,
+            frame: 0,
+            len: text.chars().count(),
+        }
+    }
+}
+
+impl<'a> Iterator for Scroll<'a> {
+    type Item = [u8; NUM_DIGITS];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.frame >= self.len + NUM_DIGITS {
+            return None;
+        }
+        let mut segments = [0; NUM_DIGITS];
+        // index of the text character in the leftmost position
+        let first = self.frame as isize + 1 - NUM_DIGITS as isize;
+        for (position, segment) in segments.iter_mut().enumerate() {
+            let index = first + position as isize;
+            if index >= 0 {
+                if let Some(c) = self.text.chars().nth(index as usize) {
+                    *segment = char_to_segments(c);
+                }
+            }
+        }
+        self.frame += 1;
+        Some(segments)
+    }
+}
+
+/// TM1637 display on two pins
+///
+/// The display can be tested without hardware by recording what is sent
+/// over mock pins:
+///
+/// ```
+/// # use microbit_common as microbit;
+/// use core::convert::Infallible;
+/// use std::{cell::RefCell, rc::Rc};
+///
+/// use embedded_hal::{blocking::delay::DelayUs, digital::v2::OutputPin};
+/// use microbit::tm1637::{Tm1637, DIGITS};
+///
+/// #[derive(Default)]
+/// struct Bus {
+///     clk: bool,
+///     dio: bool,
+///     bits: Vec<bool>,
+///     frames: Vec<Vec<u8>>,
+/// }
+///
+/// impl Bus {
+///     fn set(&mut self, clk: bool, dio: bool) {
+///         if clk && self.clk && dio != self.dio {
+///             if dio {
+///                 // stop: 8 data bits and an acknowledge per byte
+///                 let bytes = self.bits.chunks_exact(9).map(|bits| {
+///                     (0..8).fold(0, |byte, i| byte | u8::from(bits[i]) << i)
+///                 });
+///                 self.frames.push(bytes.collect());
+///             } else {
+///                 // start
+///                 self.bits.clear();
+///             }
+///         } else if clk && !self.clk {
+///             self.bits.push(dio);
+///         }
+///         (self.clk, self.dio) = (clk, dio);
+///     }
+/// }
+///
+/// struct Clk(Rc<RefCell<Bus>>);
+/// struct Dio(Rc<RefCell<Bus>>);
+///
+/// impl OutputPin for Clk {
+///     type Error = Infallible;
+///     fn set_high(&mut self) -> Result<(), Infallible> {
+///         let mut bus = self.0.borrow_mut();
+///         let dio = bus.dio;
+///         bus.set(true, dio);
+///         Ok(())
+///     }
+///     fn set_low(&mut self) -> Result<(), Infallible> {
+///         let mut bus = self.0.borrow_mut();
+///         let dio = bus.dio;
+///         bus.set(false, dio);
+///         Ok(())
+///     }
+/// }
+///
+/// impl OutputPin for Dio {
+///     type Error = Infallible;
+///     fn set_high(&mut self) -> Result<(), Infallible> {
+///         let mut bus = self.0.borrow_mut();
+///         let clk = bus.clk;
+///         bus.set(clk, true);
+///         Ok(())
+///     }
+///     fn set_low(&mut self) -> Result<(), Infallible> {
+///         let mut bus = self.0.borrow_mut();
+///         let clk = bus.clk;
+///         bus.set(clk, false);
+///         Ok(())
+///     }
+/// }
+///
+/// struct NoDelay;
+///
+/// impl DelayUs<u32> for NoDelay {
+///     fn delay_us(&mut self, _us: u32) {}
+/// }
+///
+/// let bus = Rc::new(RefCell::new(Bus::default()));
+/// let mut display = Tm1637::new(Clk(bus.clone()), Dio(bus.clone()), NoDelay);
+/// bus.borrow_mut().frames.clear();
+///
+/// display.show_number(42);
+/// assert_eq!(
+///     bus.borrow().frames,
+///     [vec![0x40], vec![0xc0, 0, 0, DIGITS[4], DIGITS[2]], vec![0x8f]]
+/// );
+/// ```
+pub struct Tm1637<CLK, DIO, D> {
+    clk: CLK,
+    dio: DIO,
+    delay: D,
+    segments: [u8; NUM_DIGITS],
+    colon: bool,
+    brightness: u8,
+    on: bool,
+}
+
+impl<CLK: OutputPin, DIO: OutputPin, D: DelayUs<u32>> Tm1637<CLK, DIO, D> {
+    /// Take the pins and a delay, and clear the display
+    ///
+    /// The brightness starts at the maximum.
+    pub fn new(clk: CLK, dio: DIO, delay: D) -> Self {
+        let mut display = Self {
+            clk,
+            dio,
+            delay,
+            segments: [0; NUM_DIGITS],
+            colon: false,
+            brightness: MAX_BRIGHTNESS,
+            on: true,
+        };
+        display.clk.set_high().ok();
+        display.dio.set_high().ok();
+        display.update();
+        display
+    }
+
+    /// Set the brightness from 0 to [`MAX_BRIGHTNESS`]
+    pub fn set_brightness(&mut self, brightness: u8) {
+        self.brightness = brightness.min(MAX_BRIGHTNESS);
+        self.update();
+    }
+
+    /// Switch the display on or off, the digits are kept
+    pub fn set_on(&mut self, on: bool) {
+        self.on = on;
+        self.update();
+    }
+
+    /// Show or hide the colon of clock displays
+    pub fn set_colon(&mut self, colon: bool) {
+        self.colon = colon;
+        self.update();
+    }
+
+    /// Segments currently shown, without the colon
+    pub fn segments(&self) -> [u8; NUM_DIGITS] {
+        self.segments
+    }
+
+    /// Set the segments of the digits from `position` on
+    ///
+    /// Digits beyond the last one are ignored.
+    pub fn write_segments(&mut self, position: usize, segments: &[u8]) {
+        for (digit, value) in self.segments.iter_mut().skip(position).zip(segments) {
+            *digit = *value;
+        }
+        self.update();
+    }
+
+    /// Show a hexadecimal digit at `position`
+    pub fn set_digit(&mut self, position: usize, value: u8) {
+        self.write_segments(position, &[DIGITS[usize::from(value & 0xf)]]);
+    }
+
+    /// Show a number from -999 to 9999, right aligned
+    pub fn show_number(&mut self, number: i32) {
+        self.write_segments(0, &number_to_segments(number));
+    }
+
+    /// Show the first four characters of `text`
+    pub fn show_text(&mut self, text: &str) {
+        let mut segments = [0; NUM_DIGITS];
+        for (segment, c) in segments.iter_mut().zip(text.chars()) {
+            *segment = char_to_segments(c);
+        }
+        self.write_segments(0, &segments);
+    }
+
+    /// Scroll `text` through the display, one position every `step_ms`
+    ///
+    /// Blocks until the text has left the display. Use [`Scroll`] to scroll
+    /// from a periodic task instead.
+    pub fn scroll_text(&mut self, text: &str, step_ms: u32) {
+        for frame in Scroll::new(text) {
+            self.write_segments(0, &frame);
+            self.delay.delay_us(step_ms * 1000);
+        }
+    }
+
+    /// Blank all digits and the colon
+    pub fn clear(&mut self) {
+        self.segments = [0; NUM_DIGITS];
+        self.colon = false;
+        self.update();
+    }
+
+    /// Release the pins and the delay
+    pub fn free(self) -> (CLK, DIO, D) {
+        (self.clk, self.dio, self.delay)
+    }
+
+    fn update(&mut self) {
+        let mut data = [0; NUM_DIGITS + 1];
+        data[0] = ADDRESS;
+        data[1..].copy_from_slice(&self.segments);
+        if self.colon {
+            data[1 + COLON_DIGIT] |= SEG_DP;
+        }
+        let control = if self.on {
+            DISPLAY_CONTROL | DISPLAY_ON | self.brightness
+        } else {
+            DISPLAY_CONTROL
+        };
+        self.send(&[DATA_AUTO_INCREMENT]);
+        self.send(&data);
+        self.send(&[control]);
+    }
+
+    fn send(&mut self, bytes: &[u8]) {
+        // start: data falls while the clock is high
+        self.dio.set_low().ok();
+        self.delay.delay_us(HALF_PERIOD_US);
+        self.clk.set_low().ok();
+        for byte in bytes {
+            for bit in 0..8 {
+                if byte & (1 << bit) != 0 {
+                    self.dio.set_high().ok();
+                } else {
+                    self.dio.set_low().ok();
+                }
+                self.delay.delay_us(HALF_PERIOD_US);
+                self.clk.set_high().ok();
+                self.delay.delay_us(HALF_PERIOD_US);
+                self.clk.set_low().ok();
+            }
+            // acknowledge: release the data line for the ninth clock
+            self.dio.set_high().ok();
+            self.delay.delay_us(HALF_PERIOD_US);
+            self.clk.set_high().ok();
+            self.delay.delay_us(HALF_PERIOD_US);
+            self.clk.set_low().ok();
+        }
+        // stop: data rises while the clock is high
+        self.dio.set_low().ok();
+        self.delay.delay_us(HALF_PERIOD_US);
+        self.clk.set_high().ok();
+        self.delay.delay_us(HALF_PERIOD_US);
+        self.dio.set_high().ok();
+        self.delay.delay_us(HALF_PERIOD_US);
+    }
+}
@@ -1,11 +1,11 @@
 use super::gpio::{
-    DisplayPins, BTN_A, BTN_B, EDGE00, EDGE01, EDGE02, EDGE03, EDGE16, EDGE17, SCL, SDA, UART_RX,
+    DisplayPins, A1_RX, A1_TX, BTN_A, BTN_B, EDGE00, EDGE01, EDGE02, EDGE03, SCL, SDA, UART_RX,
     UART_TX,
 };
 use crate::{
     gpio::{BeeperMotorPins, MicrophonePins, RGB_LED},
     hal::{
-        gpio::{p0, Disconnected, Level},
+        gpio::{p0, Disconnected, Floating, Input, Level, Output, PushPull},
         twi, uart,
     },
     pac,
@@ -35,8 +35,11 @@ pub struct Board {
     /// rgb led pin
     pub rgb_led_pin: RGB_LED,
 
-    /// I2C shared internal and external bus pins
-    pub i2c: I2CPins,
+    /// Grove A0 connector, the I2C bus shared with the motion sensor
+    pub grove_a0: GroveA0,
+
+    /// Grove A1 connector on C16 and C17
+    pub grove_a1: GroveA1,
 
     /// UART to debugger pins
     pub uart: UartPins,
@@ -86,6 +89,13 @@ pub struct Board {
     /// nRF51 peripheral: GPIOTE
     pub GPIOTE: pac::GPIOTE,
 
+    /// nRF51 peripheral: LPCOMP <br>
+    /// Can be used with [`SoundTrigger::new()`](crate::microphone::SoundTrigger::new())
+    pub LPCOMP: pac::LPCOMP,
+
+    /// nRF51 peripheral: POWER
+    pub POWER: pac::POWER,
+
     /// nRF51 preipheral: PPI
     pub PPI: pac::PPI,
 
@@ -98,6 +108,10 @@ pub struct Board {
     /// nRF51 peripheral: RTC0
     pub RTC0: pac::RTC0,
 
+    /// nRF51 peripheral: SPI1 <br>
+    /// Can be used with [`SpiBus::new()`](`crate::spi::SpiBus::new()`)
+    pub SPI1: pac::SPI1,
+
     /// nRF51 peripheral: TEMP <br>
     /// Can be used with [`Temp::new()`](`crate::hal::temp::Temp::new()`)
     pub TEMP: pac::TEMP,
@@ -150,8 +164,6 @@ impl Board {
                 e01: p0parts.p0_01,
                 e02: p0parts.p0_02,
                 e03: p0parts.p0_22,
-                e16: p0parts.p0_26,
-                e17: p0parts.p0_27,
             },
             display_pins: DisplayPins {
                 row1: p0parts.p0_13.into_push_pull_output(Level::Low),
@@ -180,10 +192,14 @@ impl Board {
                 mic_in: p0parts.p0_03.into_floating_input(),
             },
             rgb_led_pin: p0parts.p0_18.into_push_pull_output(Level::Low),
-            i2c: I2CPins {
+            grove_a0: GroveA0 {
                 scl: p0parts.p0_19.into_floating_input(),
                 sda: p0parts.p0_20.into_floating_input(),
             },
+            grove_a1: GroveA1 {
+                c16: p0parts.p0_26,
+                c17: p0parts.p0_27,
+            },
             uart: UartPins {
                 tx: p0parts.p0_24.into_push_pull_output(Level::Low),
                 rx: p0parts.p0_25.into_floating_input(),
@@ -207,10 +223,13 @@ impl Board {
             CLOCK: p.CLOCK,
             FICR: p.FICR,
             GPIOTE: p.GPIOTE,
+            LPCOMP: p.LPCOMP,
+            POWER: p.POWER,
             PPI: p.PPI,
             RADIO: p.RADIO,
             RNG: p.RNG,
             RTC0: p.RTC0,
+            SPI1: p.SPI1,
             TEMP: p.TEMP,
             TIMER0: p.TIMER0,
             TIMER1: p.TIMER1,
@@ -264,8 +283,6 @@ pub struct Edge {
     pub e01: EDGE01<Disconnected>, // <- big pad 1
     pub e02: EDGE02<Disconnected>, // <- big pad 2
     pub e03: EDGE03<Disconnected>, // <- big pad 3
-    pub e16: EDGE16<Disconnected>,
-    pub e17: EDGE17<Disconnected>,
 }
 
 /// Board buttons
@@ -278,8 +295,8 @@ pub struct Buttons {
 
 /// I2C shared internal and external bus pins
 pub struct I2CPins {
-    scl: SCL,
-    sda: SDA,
+    pub(crate) scl: SCL,
+    pub(crate) sda: SDA,
 }
 
 impl From<I2CPins> for twi::Pins {
@@ -291,6 +308,74 @@ impl From<I2CPins> for twi::Pins {
     }
 }
 
+/// Grove A0 connector
+///
+/// A0 carries the I2C bus of the motion sensor, so it can only be used for
+/// I2C devices.
+pub struct GroveA0 {
+    scl: SCL,
+    sda: SDA,
+}
+
+impl GroveA0 {
+    /// The I2C bus pins, shared with the motion sensor
+    pub fn into_i2c(self) -> I2CPins {
+        I2CPins {
+            scl: self.scl,
+            sda: self.sda,
+        }
+    }
+}
+
+/// Grove A1 connector
+///
+/// A1 connects to C16 and C17, which are the analog inputs AIN0 and AIN1 and
+/// can be used as digital pins or for the UART.
+pub struct GroveA1 {
+    c16: A1_RX<Disconnected>,
+    c17: A1_TX<Disconnected>,
+}
+
+impl GroveA1 {
+    /// C16 and C17 as inputs for the ADC, channels AIN0 and AIN1
+    pub fn into_analog(self) -> (A1_RX<Input<Floating>>, A1_TX<Input<Floating>>) {
+        (
+            self.c16.into_floating_input(),
+            self.c17.into_floating_input(),
+        )
+    }
+
+    /// C16 and C17 to be configured as digital inputs or outputs
+    pub fn into_digital(self) -> (A1_RX<Disconnected>, A1_TX<Disconnected>) {
+        (self.c16, self.c17)
+    }
+
+    /// RX on C16 and TX on C17
+    pub fn into_uart(self) -> GroveUartPins {
+        GroveUartPins {
+            tx: self.c17.into_push_pull_output(Level::High),
+            rx: self.c16.into_floating_input(),
+        }
+    }
+}
+
+/// UART pins on the Grove A1 connector
+pub struct GroveUartPins {
+    tx: A1_TX<Output<PushPull>>,
+    rx: A1_RX<Input<Floating>>,
+}
+
+impl From<GroveUartPins> for uart::Pins {
+    fn from(pins: GroveUartPins) -> Self {
+        Self {
+            rxd: pins.rx.degrade(),
+            txd: pins.tx.degrade(),
+            cts: None,
+            rts: None,
+        }
+    }
+}
+
 /// UART to debugger pins
 pub struct UartPins {
     tx: UART_TX,
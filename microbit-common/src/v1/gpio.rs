@@ -54,12 +54,29 @@ pub struct DisplayPins {
 }
 
 /// GPIO pins connected to the beeper / motor driver
+///
+/// They turn into a [`Beeper`](crate::sound::Beeper),
+/// a [`Motor`](crate::motor::Motor) or [`Motors`](crate::motor::Motors).
 pub struct BeeperMotorPins {
     pub motor_nsleep: MOTOR_NSLEEP,
     pub motor_in1: MOTOR_IN1,
     pub motor_in2: MOTOR_IN2,
 }
 
+impl BeeperMotorPins {
+    /// Aliases of `motor_in1` and `motor_in2` for the configuration of
+    /// GPIOTE channels
+    ///
+    /// GPIOTE only accepts degraded pins. The channels only read the pin
+    /// numbers, so the typed pins stay here and drive the inputs again once
+    /// the channels are disabled.
+    pub(crate) fn gpiote_in_pins(&self) -> [Pin<Output<PushPull>>; 2] {
+        // the aliases are dropped after the configuration and never drive
+        // the pins
+        unsafe { [Pin::from_psel_bits(29), Pin::from_psel_bits(30)] }
+    }
+}
+
 /// GPIO pins connected to the microphone
 pub struct MicrophonePins {
     pub mic_in: MIC,
@@ -189,3 +206,33 @@ pub type EDGE18 = SDA;
 pub type EDGE19 = SCL;
 // -> +V
 // -> GND
+
+/* Calliope mini edge connector names */
+pub type P0<MODE> = EDGE00<MODE>;
+pub type P1<MODE> = EDGE01<MODE>;
+pub type P2<MODE> = EDGE02<MODE>;
+pub type P3<MODE> = EDGE03<MODE>;
+pub type C4 = EDGE04;
+pub type C5 = EDGE05;
+pub type C6 = EDGE06;
+pub type C7 = EDGE07;
+pub type C8 = EDGE08;
+pub type C9 = EDGE09;
+pub type C10 = EDGE10;
+pub type C11 = EDGE11;
+pub type C12 = EDGE12;
+pub type C13 = EDGE13;
+pub type C14 = EDGE14;
+pub type C15 = EDGE15;
+pub type C16<MODE> = EDGE16<MODE>;
+pub type C17<MODE> = EDGE17<MODE>;
+pub type C18 = EDGE18;
+pub type C19 = EDGE19;
+
+/* Grove connectors */
+// A0: I2C, shared with the internal bus
+pub type A0_SCL = C19;
+pub type A0_SDA = C18;
+// A1: analog / digital / UART
+pub type A1_RX<MODE> = C16<MODE>;
+pub type A1_TX<MODE> = C17<MODE>;
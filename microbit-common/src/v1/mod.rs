@@ -1,3 +1,4 @@
 pub mod adc;
 pub mod board;
 pub mod gpio;
+pub mod pins;
@@ -0,0 +1,465 @@
+//! Runtime access to the edge connector pins by their Calliope mini label
+//!
+//! The typed aliases in [`gpio`](crate::gpio) are the right tool when the
+//! pin is known at compile time. Block-style programs, however, select the
+//! pin at runtime, for example `digital_read(Pin::P1)`. This module provides
+//! a [`Pin`] enum with the labels printed on the board and an
+//! [`EdgeConnector`] which owns the pins and switches their mode on demand.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use microbit_common as microbit;
+//! use microbit::{
+//!     pins::{EdgeConnector, Pin},
+//!     Board,
+//! };
+//!
+//! let board = Board::take().unwrap();
+//! let mut pins = EdgeConnector::new(board.edge)
+//!     .with_grove_a1(board.grove_a1)
+//!     .with_adc(board.ADC);
+//!
+//! if pins.digital_read(Pin::P1).unwrap() {
+//!     pins.digital_write(Pin::P2, true).unwrap();
+//! }
+//! let _level = pins.analog_read(Pin::C16).unwrap();
+//! ```
+use core::mem;
+
+use embedded_hal::digital::v2::{InputPin, OutputPin};
+
+use super::{
+    board::{Edge, GroveA1, I2CPins},
+    gpio::DisplayPins,
+};
+use crate::{
+    hal::gpio::{self, Disconnected, Floating, Input, Level, Output, PullDown, PullUp, PushPull},
+    pac,
+};
+
+/// Number of pins addressable by [`Pin`]
+pub const NUM_PINS: usize = 20;
+
+/// Edge connector pins named by their Calliope mini label
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[allow(missing_docs)]
+pub enum Pin {
+    P0,
+    P1,
+    P2,
+    P3,
+    C4,
+    C5,
+    C6,
+    C7,
+    C8,
+    C9,
+    C10,
+    C11,
+    C12,
+    C13,
+    C14,
+    C15,
+    C16,
+    C17,
+    C18,
+    C19,
+}
+
+/// What a [`Pin`] can be used for besides digital input and output
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Capabilities {
+    /// ADC input channel, if the pin is an analog input
+    pub analog: Option<u8>,
+    /// The pin is one of the four touch pads
+    pub touch: bool,
+    /// The pin can drive a software PWM signal
+    pub pwm: bool,
+}
+
+impl Pin {
+    /// All pins in index order
+    pub const ALL: [Pin; NUM_PINS] = [
+        Pin::P0,
+        Pin::P1,
+        Pin::P2,
+        Pin::P3,
+        Pin::C4,
+        Pin::C5,
+        Pin::C6,
+        Pin::C7,
+        Pin::C8,
+        Pin::C9,
+        Pin::C10,
+        Pin::C11,
+        Pin::C12,
+        Pin::C13,
+        Pin::C14,
+        Pin::C15,
+        Pin::C16,
+        Pin::C17,
+        Pin::C18,
+        Pin::C19,
+    ];
+
+    /// Look up a pin by the number in its label, e.g. `4` for `C4`
+    ///
+    /// ```
+    /// # use microbit_common as microbit;
+    /// use microbit::pins::Pin;
+    ///
+    /// assert_eq!(Pin::from_index(1), Some(Pin::P1));
+    /// assert_eq!(Pin::from_index(17), Some(Pin::C17));
+    /// assert_eq!(Pin::from_index(20), None);
+    /// ```
+    pub fn from_index(index: usize) -> Option<Pin> {
+        Pin::ALL.get(index).copied()
+    }
+
+    /// The number in the pin label
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    /// The label printed on the board
+    pub fn label(self) -> &'static str {
+        const LABELS: [&str; NUM_PINS] = [
+            "P0", "P1", "P2", "P3", "C4", "C5", "C6", "C7", "C8", "C9", "C10", "C11", "C12", "C13",
+            "C14", "C15", "C16", "C17", "C18", "C19",
+        ];
+        LABELS[self.index()]
+    }
+
+    /// The P0.xx GPIO number of the pin
+    pub fn gpio(self) -> u8 {
+        match self {
+            Pin::P0 => 0,
+            Pin::P1 => 1,
+            Pin::P2 => 2,
+            Pin::P3 => 22,
+            Pin::C16 => 26,
+            Pin::C17 => 27,
+            Pin::C18 => 20,
+            Pin::C19 => 19,
+            // C4 - C15 are the LED matrix columns and rows on P0.04 - P0.15
+            other => other.index() as u8,
+        }
+    }
+
+    /// What the pin can be used for
+    ///
+    /// ```
+    /// # use microbit_common as microbit;
+    /// use microbit::pins::Pin;
+    ///
+    /// assert_eq!(Pin::C16.capabilities().analog, Some(0));
+    /// assert!(Pin::P3.capabilities().touch);
+    /// assert!(!Pin::C19.capabilities().pwm);
+    /// ```
+    pub fn capabilities(self) -> Capabilities {
+        Capabilities {
+            analog: match self.gpio() {
+                26 => Some(0),
+                27 => Some(1),
+                n @ 1..=6 => Some(n + 1),
+                _ => None,
+            },
+            touch: matches!(self, Pin::P0 | Pin::P1 | Pin::P2 | Pin::P3),
+            // C18 and C19 carry the I2C bus shared with the motion sensor
+            pwm: !matches!(self, Pin::C18 | Pin::C19),
+        }
+    }
+
+    /// The pin is connected to an ADC input channel
+    pub fn is_analog(self) -> bool {
+        self.capabilities().analog.is_some()
+    }
+
+    /// The pin is one of the touch pads
+    pub fn is_touch(self) -> bool {
+        self.capabilities().touch
+    }
+
+    /// The pin can drive a software PWM signal
+    pub fn is_pwm(self) -> bool {
+        self.capabilities().pwm
+    }
+}
+
+/// Errors of the runtime pin access
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The pin has not been handed to the [`EdgeConnector`] or was taken out of it
+    Unavailable(Pin),
+    /// The pin is not connected to an ADC input channel
+    NotAnalog(Pin),
+    /// The pin is not a touch pad
+    NotTouch(Pin),
+    /// The pin can not drive a PWM signal
+    NotPwm(Pin),
+    /// No ADC has been handed to the [`EdgeConnector`]
+    NoAdc,
+}
+
+/// Pull resistor applied when a pin is read
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Pull {
+    /// No pull resistor
+    None,
+    /// Pull up to VDD
+    Up,
+    /// Pull down to GND
+    Down,
+}
+
+enum Slot {
+    Missing,
+    Disconnected(gpio::Pin<Disconnected>),
+    Floating(gpio::Pin<Input<Floating>>),
+    PullUp(gpio::Pin<Input<PullUp>>),
+    PullDown(gpio::Pin<Input<PullDown>>),
+    Output(gpio::Pin<Output<PushPull>>),
+}
+
+impl Slot {
+    fn into_disconnected(self) -> Option<gpio::Pin<Disconnected>> {
+        match self {
+            Slot::Missing => None,
+            Slot::Disconnected(pin) => Some(pin),
+            Slot::Floating(pin) => Some(pin.into_disconnected()),
+            Slot::PullUp(pin) => Some(pin.into_disconnected()),
+            Slot::PullDown(pin) => Some(pin.into_disconnected()),
+            Slot::Output(pin) => Some(pin.into_disconnected()),
+        }
+    }
+
+    fn into_input(self, pull: Pull) -> Slot {
+        match (self, pull) {
+            (slot @ Slot::Floating(_), Pull::None)
+            | (slot @ Slot::PullUp(_), Pull::Up)
+            | (slot @ Slot::PullDown(_), Pull::Down) => slot,
+            (slot, pull) => slot
+                .into_disconnected()
+                .map_or(Slot::Missing, |p| match pull {
+                    Pull::None => Slot::Floating(p.into_floating_input()),
+                    Pull::Up => Slot::PullUp(p.into_pullup_input()),
+                    Pull::Down => Slot::PullDown(p.into_pulldown_input()),
+                }),
+        }
+    }
+
+    fn is_high(&self) -> bool {
+        match self {
+            Slot::Floating(p) => p.is_high(),
+            Slot::PullUp(p) => p.is_high(),
+            Slot::PullDown(p) => p.is_high(),
+            _ => Ok(false),
+        }
+        .unwrap_or(false)
+    }
+}
+
+/// Edge connector pins addressed by [`Pin`] at runtime
+///
+/// The connector starts with the pins from [`Board::edge`](crate::Board::edge)
+/// (P0 - P3). The Grove A1 pins C16 and C17, the LED matrix pins C4 - C15
+/// and the I2C pins C18 and C19 can be added when they are not used
+/// otherwise.
+pub struct EdgeConnector {
+    slots: [Slot; NUM_PINS],
+    pulls: [Pull; NUM_PINS],
+    adc: Option<pac::ADC>,
+}
+
+impl EdgeConnector {
+    /// Create the connector from the unused edge connector pins
+    pub fn new(edge: Edge) -> Self {
+        const MISSING: Slot = Slot::Missing;
+        let mut connector = Self {
+            slots: [MISSING; NUM_PINS],
+            pulls: [Pull::None; NUM_PINS],
+            adc: None,
+        };
+        connector.insert(Pin::P0, edge.e00.degrade());
+        connector.insert(Pin::P1, edge.e01.degrade());
+        connector.insert(Pin::P2, edge.e02.degrade());
+        connector.insert(Pin::P3, edge.e03.degrade());
+        connector
+    }
+
+    /// Add the Grove A1 pins C16 and C17
+    pub fn with_grove_a1(mut self, grove: GroveA1) -> Self {
+        let (c16, c17) = grove.into_digital();
+        self.insert(Pin::C16, c16.degrade());
+        self.insert(Pin::C17, c17.degrade());
+        self
+    }
+
+    /// Add the LED matrix pins C4 - C15
+    ///
+    /// The display must not be used while the connector owns these pins.
+    pub fn with_display_pins(mut self, pins: DisplayPins) -> Self {
+        let (cols, rows) = pins.degrade();
+        for (pin, col) in Pin::ALL[4..13].iter().zip(cols) {
+            self.insert(*pin, col.into_disconnected());
+        }
+        for (pin, row) in Pin::ALL[13..16].iter().zip(rows) {
+            self.insert(*pin, row.into_disconnected());
+        }
+        self
+    }
+
+    /// Add the I2C pins C18 and C19
+    ///
+    /// The motion sensor and anything on the Grove A0 connector can not be
+    /// used while the connector owns these pins.
+    pub fn with_i2c_pins(mut self, pins: I2CPins) -> Self {
+        self.insert(Pin::C18, pins.sda.into_disconnected().degrade());
+        self.insert(Pin::C19, pins.scl.into_disconnected().degrade());
+        self
+    }
+
+    /// Hand over the ADC for [`analog_read`](EdgeConnector::analog_read)
+    pub fn with_adc(mut self, adc: pac::ADC) -> Self {
+        adc.config.write(|w| {
+            w.res()
+                ._10bit()
+                .inpsel()
+                .analog_input_one_third_prescaling()
+                .refsel()
+                .supply_one_third_prescaling()
+        });
+        adc.enable.write(|w| w.enable().enabled());
+        self.adc = Some(adc);
+        self
+    }
+
+    fn insert(&mut self, pin: Pin, gpio: gpio::Pin<Disconnected>) {
+        self.slots[pin.index()] = Slot::Disconnected(gpio);
+    }
+
+    fn take_slot(&mut self, pin: Pin) -> Result<Slot, Error> {
+        match mem::replace(&mut self.slots[pin.index()], Slot::Missing) {
+            Slot::Missing => Err(Error::Unavailable(pin)),
+            slot => Ok(slot),
+        }
+    }
+
+    /// Select the pull resistor used by [`digital_read`](EdgeConnector::digital_read)
+    pub fn set_pull(&mut self, pin: Pin, pull: Pull) {
+        self.pulls[pin.index()] = pull;
+    }
+
+    /// Configure the pin as input and read its level
+    pub fn digital_read(&mut self, pin: Pin) -> Result<bool, Error> {
+        let slot = self.take_slot(pin)?.into_input(self.pulls[pin.index()]);
+        let high = slot.is_high();
+        self.slots[pin.index()] = slot;
+        Ok(high)
+    }
+
+    /// Configure the pin as push-pull output and set its level
+    pub fn digital_write(&mut self, pin: Pin, high: bool) -> Result<(), Error> {
+        let slot = match self.take_slot(pin)? {
+            Slot::Output(mut p) => {
+                if high {
+                    p.set_high().ok();
+                } else {
+                    p.set_low().ok();
+                }
+                Slot::Output(p)
+            }
+            slot => {
+                let level = if high { Level::High } else { Level::Low };
+                slot.into_disconnected().map_or(Slot::Missing, |p| {
+                    Slot::Output(p.into_push_pull_output(level))
+                })
+            }
+        };
+        self.slots[pin.index()] = slot;
+        Ok(())
+    }
+
+    /// Read the voltage on an analog pin as a 10 bit value
+    pub fn analog_read(&mut self, pin: Pin) -> Result<u16, Error> {
+        let channel = pin.capabilities().analog.ok_or(Error::NotAnalog(pin))?;
+        if self.adc.is_none() {
+            return Err(Error::NoAdc);
+        }
+        // the analog input needs the digital input buffer disconnected
+        let slot = self.take_slot(pin)?.into_disconnected();
+        self.slots[pin.index()] = slot.map_or(Slot::Missing, Slot::Disconnected);
+
+        let adc = self.adc.as_ref().ok_or(Error::NoAdc)?;
+        adc.config
+            .modify(|_, w| unsafe { w.psel().bits(1 << channel) });
+        adc.events_end.write(|w| unsafe { w.bits(0) });
+        adc.tasks_start.write(|w| unsafe { w.bits(1) });
+        while adc.events_end.read().bits() == 0 {}
+        adc.events_end.write(|w| unsafe { w.bits(0) });
+        adc.config.modify(|_, w| w.psel().disabled());
+
+        Ok(adc.result.read().result().bits())
+    }
+
+    /// Check whether a touch pad is touched
+    ///
+    /// The pads are read as floating inputs; a finger connects the pad to GND
+    /// through the body and pulls the level low. The pull selected with
+    /// [`set_pull`](EdgeConnector::set_pull) is kept for the next
+    /// [`digital_read`](EdgeConnector::digital_read).
+    pub fn is_touched(&mut self, pin: Pin) -> Result<bool, Error> {
+        if !pin.is_touch() {
+            return Err(Error::NotTouch(pin));
+        }
+        let pull = mem::replace(&mut self.pulls[pin.index()], Pull::None);
+        let level = self.digital_read(pin);
+        self.pulls[pin.index()] = pull;
+        Ok(!level?)
+    }
+
+    /// Take a pin out of the connector, e.g. to hand it to a driver
+    ///
+    /// The pin is disconnected before it is returned. Use
+    /// [`restore`](EdgeConnector::restore) to put it back.
+    pub fn take(&mut self, pin: Pin) -> Result<gpio::Pin<Disconnected>, Error> {
+        self.take_slot(pin)?
+            .into_disconnected()
+            .ok_or(Error::Unavailable(pin))
+    }
+
+    /// Take a pin out of the connector after checking it can drive PWM
+    pub fn take_pwm(&mut self, pin: Pin) -> Result<gpio::Pin<Disconnected>, Error> {
+        if !pin.is_pwm() {
+            return Err(Error::NotPwm(pin));
+        }
+        self.take(pin)
+    }
+
+    /// Put a pin back that was taken with [`take`](EdgeConnector::take)
+    ///
+    /// Returns the pin again if it is not the GPIO belonging to `pin`.
+    pub fn restore(
+        &mut self,
+        pin: Pin,
+        gpio: gpio::Pin<Disconnected>,
+    ) -> Result<(), gpio::Pin<Disconnected>> {
+        if gpio.pin() != pin.gpio() {
+            return Err(gpio);
+        }
+        self.insert(pin, gpio);
+        Ok(())
+    }
+
+    /// Release all pins, disconnected and indexed by [`Pin`], and the ADC
+    pub fn free(
+        self,
+    ) -> (
+        [Option<gpio::Pin<Disconnected>>; NUM_PINS],
+        Option<pac::ADC>,
+    ) {
+        let Self { slots, adc, .. } = self;
+        (slots.map(Slot::into_disconnected), adc)
+    }
+}
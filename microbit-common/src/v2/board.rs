@@ -85,6 +85,13 @@ pub struct Board {
     /// nRF52 peripheral: GPIOTE
     pub GPIOTE: pac::GPIOTE,
 
+    /// nRF52 peripheral: LPCOMP <br>
+    /// Can be used with [`SoundTrigger::new()`](crate::microphone::SoundTrigger::new())
+    pub LPCOMP: pac::LPCOMP,
+
+    /// nRF52 peripheral: POWER
+    pub POWER: pac::POWER,
+
     /// nRF52 preipheral: PPI
     pub PPI: pac::PPI,
 
@@ -100,6 +107,9 @@ pub struct Board {
     /// nRF52 peripheral: PWM3
     pub PWM3: pac::PWM3,
 
+    /// nRF52 peripheral: QDEC
+    pub QDEC: pac::QDEC,
+
     /// nRF52 peripheral: RADIO
     pub RADIO: pac::RADIO,
 
@@ -115,6 +125,10 @@ pub struct Board {
     /// nRF52 peripheral: RTC2
     pub RTC2: pac::RTC2,
 
+    /// nRF52 peripheral: SPI1 <br>
+    /// Can be used with [`SpiBus::new()`](`crate::spi::SpiBus::new()`)
+    pub SPI1: pac::SPI1,
+
     /// nRF52 peripheral: TEMP <br>
     /// Can be used with [`Temp::new()`](`crate::hal::temp::Temp::new()`)
     pub TEMP: pac::TEMP,
@@ -261,16 +275,20 @@ impl Board {
             CLOCK: p.CLOCK,
             FICR: p.FICR,
             GPIOTE: p.GPIOTE,
+            LPCOMP: p.LPCOMP,
+            POWER: p.POWER,
             PPI: p.PPI,
             PWM0: p.PWM0,
             PWM1: p.PWM1,
             PWM2: p.PWM2,
             PWM3: p.PWM3,
+            QDEC: p.QDEC,
             RADIO: p.RADIO,
             RNG: p.RNG,
             RTC0: p.RTC0,
             RTC1: p.RTC1,
             RTC2: p.RTC2,
+            SPI1: p.SPI1,
             TEMP: p.TEMP,
             TIMER0: p.TIMER0,
             TIMER1: p.TIMER1,